@@ -1,83 +1,609 @@
-use crate::http::{Client, Request};
+use crate::check::{Check, Checked};
+use crate::control::{Adjustable, LoadControl};
+use crate::feeder::Feeder;
+use crate::http::{
+    Client, ConnectTiming, ErrorCategory, Response, error_category, error_phase, version_name,
+};
+use crate::limiter::RateLimiter;
 use crate::otlp::Metrics;
+use crate::profile::{LoadProfile, StageUnit};
+use crate::replay::{Replay, ReplaySpeed};
+use crate::reporter::REPORT_INTERVAL;
+use crate::scenario::{Scenario, Step};
+use crate::template::{RenderContext, Vars};
 use anyhow::{Result, bail};
+use clap::ValueEnum;
+use rand::distr::Distribution;
+use rand::distr::weighted::WeightedIndex;
 use std::future::Future;
 use std::pin::Pin;
 use std::sync::Arc;
-use std::time::{Duration, Instant};
-use tokio::sync::{Barrier, Mutex};
+use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+use std::time::Duration;
+use strum::AsRefStr;
+use tokio::sync::{Barrier, Semaphore};
+use tokio::task::{JoinHandle, JoinSet};
+use tokio::time::Instant;
 use tokio_util::sync::CancellationToken;
 use tracing::*;
 
 type BoxedFuture = Pin<Box<dyn Future<Output = Result<()>> + Send>>;
-type BoxedAsyncClosure = Box<dyn Fn() -> BoxedFuture + Send>;
+// The argument is the intended send time when requests follow a schedule
+type BoxedAsyncClosure = Box<dyn Fn(Option<Instant>) -> BoxedFuture + Send + Sync>;
+
+/// How requests are issued over the course of a run
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, AsRefStr)]
+pub enum Executor {
+    /// Each client waits for its previous response before sending the next request
+    ClosedLoop,
+    /// Requests are sent on a fixed arrival timeline, independent of response times
+    ConstantArrivalRate,
+}
+
+/// Beginning of the run whose requests are sent as normal but left out of the report
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Warmup {
+    Requests(u64),
+    Duration(Duration),
+}
+
+#[derive(Debug)]
+struct WarmupGate {
+    warmup: Warmup,
+    start: Instant,
+    issued: AtomicU64,
+}
+
+impl WarmupGate {
+    fn new(warmup: Warmup) -> Self {
+        Self {
+            warmup,
+            start: Instant::now(),
+            issued: AtomicU64::new(0),
+        }
+    }
+
+    // Called once per iteration, right before its first request is sent, so that the steps of
+    // an iteration are all warm-up or none of them
+    fn is_warmup(&self) -> bool {
+        match self.warmup {
+            Warmup::Requests(count) => self.issued.fetch_add(1, Relaxed) < count,
+            Warmup::Duration(duration) => self.start.elapsed() < duration,
+        }
+    }
+
+    // Whether no iteration after the warm-up has started yet, without counting one
+    fn is_open(&self) -> bool {
+        match self.warmup {
+            Warmup::Requests(count) => self.issued.load(Relaxed) <= count,
+            Warmup::Duration(duration) => self.start.elapsed() < duration,
+        }
+    }
+}
 
 pub async fn bench(
     cxl: CancellationToken,
-    req: Request,
+    scenarios: Vec<Scenario>,
+    opts: BenchOption,
+    meter: Metrics,
+) -> Result<()> {
+    let stage_recorder = opts
+        .profile
+        .clone()
+        .map(|profile| tokio::spawn(record_stages(profile, meter.clone())));
+
+    let result = match (opts.replay.clone(), opts.executor) {
+        (Some(replay), _) => bench_replay(cxl, &replay, opts, meter).await,
+        (None, Executor::ClosedLoop) => bench_closed_loop(cxl, &scenarios, opts, meter).await,
+        (None, Executor::ConstantArrivalRate) => {
+            bench_constant_arrival_rate(cxl, &scenarios, opts, meter).await
+        }
+    };
+
+    if let Some(stage_recorder) = stage_recorder {
+        stage_recorder.abort();
+    }
+
+    result
+}
+
+// Export the current stage and its target while the profile is running
+async fn record_stages(profile: LoadProfile, meter: Metrics) {
+    let start = Instant::now();
+    let mut interval = tokio::time::interval(REPORT_INTERVAL);
+
+    loop {
+        interval.tick().await;
+        match profile.progress(start.elapsed().as_secs_f64()) {
+            Some(progress) => meter.record_stage(&progress),
+            None => break,
+        }
+    }
+}
+
+async fn bench_closed_loop(
+    cxl: CancellationToken,
+    scenarios: &[Scenario],
     opts: BenchOption,
     meter: Metrics,
 ) -> Result<()> {
     let clients = opts.clients;
-    let iter = Arc::new(Mutex::new(opts.iter()));
+    let mut iter = opts.iter();
     let barrier = Arc::new(Barrier::new(clients));
 
-    let mut handles = Vec::new();
-    for _ in 0..clients {
-        let iter = Arc::clone(&iter);
-        let cxl = cxl.clone();
-        let cli = Client::try_new(req.clone())?;
-        let meter = meter.clone();
-        let bucket = opts.token_bucket();
-        let barrier = barrier.clone();
+    let state = Arc::new(RunState::new(&opts));
+    let connections = Connections::try_new(scenarios, &opts, &meter, &state)?;
+    let clis = (0..clients)
+        .map(|index| connections.targets(scenarios, index))
+        .collect::<Result<Vec<_>>>()?;
+    // Created after the clients so that building them does not eat into the schedule
+    let limiter = opts.rate_limiter()?;
+    let retuner = limiter
+        .clone()
+        .map(|limiter| retune_limiter(limiter, &opts.control));
+    let run = ClosedLoop {
+        cxl: cxl.clone(),
+        meter: meter.clone(),
+        weights: scenario_weights(scenarios)?,
+        state,
+        limiter,
+        client_profile: opts.client_profile().cloned(),
+        // Shared by the clients added during the run
+        deadline: opts
+            .run_duration()
+            .map(|duration| Instant::now() + duration),
+        control: opts.control.clone(),
+    };
 
-        let handle = tokio::spawn(async move {
-            let task = create_request_task(cli, meter.clone());
+    let mut handles = JoinSet::new();
+    for (index, cli) in clis.into_iter().enumerate() {
+        let count = iter.next();
+        handles.spawn(run.clone().client(cli, index, count, Some(barrier.clone())));
+    }
 
-            let count = iter.lock().await.next();
-            let duration = opts.duration;
+    // Clients are added when the target grows, and idle while it is below their index
+    let mut spawned = clients;
+    let mut load = opts.control.subscribe();
+    let adjustable = opts.control.adjustable() == Adjustable::Clients;
+    let result = loop {
+        tokio::select! {
+            handle = handles.join_next() => match handle {
+                Some(Ok(Ok(()))) => {}
+                Some(Ok(Err(err))) => break Err(err),
+                Some(Err(err)) => break Err(err.into()),
+                None => break Ok(()),
+            },
+            Ok(()) = load.changed(), if adjustable => {
+                let target = load.borrow_and_update().clients;
+                while spawned < target {
+                    let cli = connections.targets(scenarios, spawned)?;
+                    handles.spawn(run.clone().client(cli, spawned, None, None));
+                    spawned += 1;
+                }
+            }
+        }
+    };
 
+    if let Some(retuner) = retuner {
+        retuner.abort();
+    }
+    result
+}
+
+// What the clients of a closed-loop run share
+#[derive(Clone)]
+struct ClosedLoop {
+    cxl: CancellationToken,
+    meter: Metrics,
+    weights: WeightedIndex<u32>,
+    state: Arc<RunState>,
+    limiter: Option<Arc<RateLimiter>>,
+    client_profile: Option<LoadProfile>,
+    deadline: Option<Instant>,
+    control: LoadControl,
+}
+
+impl ClosedLoop {
+    // Only the clients started with the run wait on `barrier` and record its start time
+    async fn client(
+        self,
+        cli: Vec<Vec<StepTarget>>,
+        index: usize,
+        count: Option<u64>,
+        barrier: Option<Arc<Barrier>>,
+    ) -> Result<()> {
+        // A client stops on its own when its rows of the data file run out
+        let cxl = self.cxl.child_token();
+        let task = create_request_task(cli, self.weights, self.state, index, cxl.clone());
+        let control = &self.control;
+
+        if let Some(barrier) = barrier {
             barrier.wait().await;
+            self.meter.record_start_time();
+        }
+
+        match (count, self.deadline, self.limiter, self.client_profile) {
+            // Count specified
+            (Some(c), None, None, None) => run_until_count(task, &cxl, c, control, index).await,
+
+            // Duration specified
+            (None, Some(d), None, None) => run_until_duration(task, &cxl, d, control, index).await,
+
+            // Count & RPS specified
+            (Some(c), None, Some(l), None) => {
+                run_until_count_with_limiter(task, &cxl, c, &l, control, index).await
+            }
+
+            // Duration & RPS, or stages in rps specified
+            (None, Some(d), Some(l), None) => {
+                run_until_duration_with_limiter(task, &cxl, d, &l, control, index).await
+            }
+
+            // Stages in clients specified
+            (None, Some(_), None, Some(p)) => {
+                run_with_client_profile(task, &cxl, &p, index, control).await
+            }
+
+            // Either num or duration must be specified
+            _ => bail!("Either num or duration must be specified."),
+        }
+    }
+}
+
+// Follow the changes of the target rate until aborted
+fn retune_limiter(limiter: Arc<RateLimiter>, control: &LoadControl) -> JoinHandle<()> {
+    let mut load = control.subscribe();
+    tokio::spawn(async move {
+        while load.changed().await.is_ok() {
+            let rps = load.borrow_and_update().rps;
+            if let Some(rps) = rps
+                && let Err(err) = limiter.set_rps(rps)
+            {
+                warn!("Failed to change the rate: {}", err);
+            }
+        }
+    })
+}
+
+// Wait until the client with `index` may send, false when the run ends meanwhile.
+// The slots of `limiter` missed while waiting are skipped rather than sent in a burst.
+async fn wait_active(
+    control: &LoadControl,
+    cxl: &CancellationToken,
+    index: usize,
+    deadline: Option<Instant>,
+    limiter: Option<&RateLimiter>,
+) -> bool {
+    if control.is_active(index) {
+        return true;
+    }
+
+    let deadline = async move {
+        match deadline {
+            Some(deadline) => tokio::time::sleep_until(deadline).await,
+            None => std::future::pending().await,
+        }
+    };
+    tokio::select! {
+        _ = cxl.cancelled() => return false,
+        _ = deadline => return false,
+        _ = control.active(index) => {}
+    }
+
+    if let Some(limiter) = limiter {
+        limiter.catch_up();
+    }
+    true
+}
+
+async fn bench_constant_arrival_rate(
+    cxl: CancellationToken,
+    scenarios: &[Scenario],
+    opts: BenchOption,
+    meter: Metrics,
+) -> Result<()> {
+    // Requests are spread over the clients in turn, so each one keeps its own connection pool
+    // unless they share a few connections
+    let state = Arc::new(RunState::new(&opts));
+    let weights = scenario_weights(scenarios)?;
+    // The arrivals stop as soon as any client runs out of rows of the data file
+    let cxl = cxl.child_token();
+    let connections = Connections::try_new(scenarios, &opts, &meter, &state)?;
+    let mut tasks = Vec::with_capacity(opts.clients);
+    for index in 0..opts.clients {
+        let targets = connections.targets(scenarios, index)?;
+        tasks.push(create_request_task(
+            targets,
+            weights.clone(),
+            state.clone(),
+            index,
+            cxl.clone(),
+        ));
+    }
 
-            meter.record_start_time();
+    let Some(limiter) = opts.rate_limiter()? else {
+        bail!("RPS must be specified for the constant arrival rate executor");
+    };
+    let retuner = retune_limiter(limiter.clone(), &opts.control);
 
-            match (count, duration, bucket) {
-                // Count specified
-                (Some(c), None, None) => run_until_count(task, &cxl, c).await,
+    meter.record_start_time();
 
-                // Duration specified
-                (None, Some(d), None) => run_until_duration(task, &cxl, d).await,
+    let result = run_at_constant_arrival_rate(&tasks, &cxl, &opts, &limiter, &meter).await;
 
-                // Count & Bucket specified
-                (Some(c), None, Some(Ok(b))) => run_until_count_with_bucket(task, &cxl, c, b).await,
+    retuner.abort();
+    result
+}
 
-                // Duration & Bucket specified
-                (None, Some(d), Some(Ok(b))) => {
-                    run_until_duration_with_bucket(task, &cxl, d, b).await
+// Send the recorded requests in order, at their recorded times or as fast as the clients can.
+// The log is replayed once, unless the count or the duration ends the run earlier.
+async fn bench_replay(
+    cxl: CancellationToken,
+    replay: &Replay,
+    opts: BenchOption,
+    meter: Metrics,
+) -> Result<()> {
+    // All the recorded requests share one connection pool
+    let scenarios = std::slice::from_ref(&replay.scenario);
+    let state = Arc::new(RunState::new(&opts));
+    let targets = Arc::new(
+        Connections::try_new(scenarios, &opts, &meter, &state)?
+            .targets(scenarios, 0)?
+            .pop()
+            .unwrap_or_default(),
+    );
+    let limit = opts
+        .count
+        .map_or(replay.len(), |count| replay.len().min(count as usize));
+    let deadline = opts
+        .run_duration()
+        .map(|duration| Instant::now() + duration);
+
+    meter.record_start_time();
+
+    if replay.speed == ReplaySpeed::Max {
+        let cursor = Arc::new(AtomicU64::new(0));
+        let mut handles = Vec::with_capacity(opts.clients);
+        for client in 0..opts.clients {
+            let targets = Arc::clone(&targets);
+            let state = Arc::clone(&state);
+            let cursor = Arc::clone(&cursor);
+            let cxl = cxl.clone();
+            let control = opts.control.clone();
+
+            handles.push(tokio::spawn(async move {
+                loop {
+                    if !wait_active(&control, &cxl, client, deadline, None).await {
+                        break;
+                    }
+                    let index = cursor.fetch_add(1, Relaxed) as usize;
+                    if index >= limit
+                        || cxl.is_cancelled()
+                        || deadline.is_some_and(|deadline| Instant::now() >= deadline)
+                    {
+                        break;
+                    }
+                    send_recorded(&targets[index], &state, None).await;
                 }
+            }));
+        }
+
+        for handle in handles {
+            handle.await?;
+        }
+        return Ok(());
+    }
+
+    let mut start = Instant::now();
+    let in_flight = Arc::new(Semaphore::new(opts.max_in_flight));
+    let mut running = JoinSet::new();
+    for index in 0..limit {
+        // The recorded timing resumes where it was paused
+        let paused_at = Instant::now();
+        if !wait_active(&opts.control, &cxl, 0, deadline, None).await {
+            break;
+        }
+        start += paused_at.elapsed();
+
+        let Some(offset) = replay.send_at(index) else {
+            break;
+        };
+        let intended = start + offset;
+        if deadline.is_some_and(|deadline| intended >= deadline) {
+            break;
+        }
+
+        tokio::select! {
+            _ = cxl.cancelled() => break,
+            _ = tokio::time::sleep_until(intended) => {}
+        }
 
-                // Either num or duration must be specified
-                _ => bail!("Either num or duration must be specified."),
-            }?;
-            Ok::<_, anyhow::Error>(())
-        });
-        handles.push(handle);
+        match Arc::clone(&in_flight).try_acquire_owned() {
+            Ok(permit) => {
+                let targets = Arc::clone(&targets);
+                let state = Arc::clone(&state);
+                running.spawn(async move {
+                    send_recorded(&targets[index], &state, Some(intended)).await;
+                    drop(permit);
+                });
+            }
+            Err(_) => meter.record_dropped(),
+        }
+
+        while running.try_join_next().is_some() {}
     }
 
-    for handle in handles {
-        handle.await??;
+    while let Some(result) = running.join_next().await {
+        result?;
     }
 
     Ok(())
 }
 
-fn create_request_task(cli: Client, meter: Metrics) -> BoxedAsyncClosure {
-    Box::new(move || {
-        let cli = cli.clone();
-        let meter = meter.clone();
+async fn send_recorded(target: &StepTarget, state: &RunState, intended: Option<Instant>) {
+    let meter = match &state.warmup {
+        Some(gate) if gate.is_warmup() => &target.warmup_meter,
+        _ => &target.meter,
+    };
+
+    if let Err(err) = request(target.client.clone(), meter, intended, &target.step.checks).await {
+        debug!("error: {:?}", err);
+    }
+}
+
+// A scenario step as sent by one client
+struct StepTarget {
+    step: Step,
+    client: Client,
+    meter: Metrics,
+    warmup_meter: Metrics,
+}
+
+// The connections the clients send through: one pool each, unless --connections or
+// --max-streams make the client with `index` share the one at `index % count`
+struct Connections {
+    shared: Vec<Client>,
+    meter: Metrics,
+    // Tells the connections opened during warm-up apart
+    state: Arc<RunState>,
+}
+
+impl Connections {
+    fn try_new(
+        scenarios: &[Scenario],
+        opts: &BenchOption,
+        meter: &Metrics,
+        state: &Arc<RunState>,
+    ) -> Result<Self> {
+        let mut connections = Self {
+            shared: Vec::new(),
+            meter: meter.clone(),
+            state: state.clone(),
+        };
+        for _ in 0..opts.connection_count().unwrap_or(0) {
+            let client = connections.open(scenarios)?;
+            connections.shared.push(match opts.max_streams {
+                Some(max_streams) => client.with_max_streams(max_streams),
+                None => client,
+            });
+        }
+        Ok(connections)
+    }
+
+    // A client with a connection pool of its own, built from the first step
+    fn open(&self, scenarios: &[Scenario]) -> Result<Client> {
+        let Some(first) = scenarios.iter().flat_map(|scenario| &scenario.steps).next() else {
+            bail!("At least one scenario must be specified");
+        };
+        let (meter, warmup_meter) = (self.meter.clone(), self.meter.warmup());
+        let state = self.state.clone();
+        Client::try_new(
+            first.request.clone(),
+            Some(Arc::new(move |timing: &ConnectTiming| {
+                if state.in_warmup() {
+                    warmup_meter.record_connection(timing)
+                } else {
+                    meter.record_connection(timing)
+                }
+            })),
+        )
+    }
+
+    fn targets(&self, scenarios: &[Scenario], index: usize) -> Result<Vec<Vec<StepTarget>>> {
+        let base = match self.shared.len() {
+            0 => self.open(scenarios)?,
+            count => self.shared[index % count].clone(),
+        };
+        create_targets(scenarios, &self.meter, &base)
+    }
+}
+
+// The clients of all steps share the connection pool of `base`
+fn create_targets(
+    scenarios: &[Scenario],
+    meter: &Metrics,
+    base: &Client,
+) -> Result<Vec<Vec<StepTarget>>> {
+    scenarios
+        .iter()
+        .map(|scenario| {
+            scenario
+                .steps
+                .iter()
+                .map(|step| {
+                    let meter = meter.with_step(scenario, step);
+                    Ok(StepTarget {
+                        step: step.clone(),
+                        client: base.try_with_request(step.request.clone())?,
+                        warmup_meter: meter.warmup(),
+                        meter,
+                    })
+                })
+                .collect()
+        })
+        .collect()
+}
+
+fn scenario_weights(scenarios: &[Scenario]) -> Result<WeightedIndex<u32>> {
+    WeightedIndex::new(scenarios.iter().map(|scenario| scenario.weight))
+        .map_err(|e| anyhow::anyhow!("Invalid scenario weights: {}", e))
+}
+
+// State shared by all the clients over the run
+#[derive(Debug)]
+struct RunState {
+    warmup: Option<WarmupGate>,
+    // Numbers the requests of the run
+    seq: AtomicU64,
+    feeder: Option<Arc<Feeder>>,
+}
+
+impl RunState {
+    fn new(opts: &BenchOption) -> Self {
+        Self {
+            warmup: opts.warmup.map(WarmupGate::new),
+            seq: AtomicU64::new(0),
+            feeder: opts.feeder.clone(),
+        }
+    }
+
+    // Whether a connection opened now is one of the warm-up
+    fn in_warmup(&self) -> bool {
+        self.warmup.as_ref().is_some_and(WarmupGate::is_open)
+    }
+}
+
+// `stop` is cancelled when the data file runs out of rows for this client
+fn create_request_task(
+    targets: Vec<Vec<StepTarget>>,
+    weights: WeightedIndex<u32>,
+    state: Arc<RunState>,
+    client_id: usize,
+    stop: CancellationToken,
+) -> BoxedAsyncClosure {
+    let targets = Arc::new(targets);
+
+    Box::new(move |intended| {
+        // Picked for every iteration in proportion to the scenario weights
+        let index = weights.sample(&mut rand::rng());
+        let targets = Arc::clone(&targets);
+        let state = Arc::clone(&state);
+        let stop = stop.clone();
 
         Box::pin(async move {
-            let result = request(cli.clone(), meter.clone()).await;
+            // Each iteration starts with the next row of the data file
+            let vars = match &state.feeder {
+                Some(feeder) => match feeder.next(client_id) {
+                    Some(row) => row.clone(),
+                    None => {
+                        stop.cancel();
+                        return Ok(());
+                    }
+                },
+                None => Vars::new(),
+            };
+
+            let steps = &targets[index];
+            let result = run_steps(steps, vars, intended, &state, client_id).await;
             if let Err(err) = result {
                 debug!("error: {:?}", err);
             }
@@ -86,36 +612,93 @@ fn create_request_task(cli: Client, meter: Metrics) -> BoxedAsyncClosure {
     })
 }
 
-async fn run_until_count<F, Fut, T>(f: F, cxl: &CancellationToken, count: u64) -> Result<()>
+// Run the steps of a scenario in order, a failed step ends the iteration
+async fn run_steps(
+    steps: &[StepTarget],
+    mut vars: Vars,
+    mut intended: Option<Instant>,
+    state: &RunState,
+    client_id: usize,
+) -> Result<()> {
+    let warmup = state.warmup.as_ref().is_some_and(WarmupGate::is_warmup);
+    for target in steps {
+        let meter = if warmup {
+            &target.warmup_meter
+        } else {
+            &target.meter
+        };
+
+        let ctx = RenderContext {
+            vars: &vars,
+            client_id,
+            seq: state.seq.fetch_add(1, Relaxed),
+        };
+        let client = match target.step.render(&ctx)? {
+            Some(request) => target.client.try_with_request(request)?,
+            None => target.client.clone(),
+        };
+
+        // Only the first step follows the schedule, the next ones are sent as soon as possible
+        let resp = request(client, meter, intended.take(), &target.step.checks).await?;
+
+        for extraction in &target.step.extract {
+            match extraction.extract(&resp) {
+                Ok(value) => vars.insert(extraction.var.clone(), value),
+                Err(err) => {
+                    meter.record_error("extract", ErrorCategory::Extract, &err);
+                    return Err(err);
+                }
+            };
+        }
+    }
+
+    Ok(())
+}
+
+async fn run_until_count<F, Fut, T>(
+    f: F,
+    cxl: &CancellationToken,
+    count: u64,
+    control: &LoadControl,
+    index: usize,
+) -> Result<()>
 where
-    F: Fn() -> Fut,
+    F: Fn(Option<Instant>) -> Fut,
     Fut: Future<Output = Result<T>>,
 {
     for _ in 0..count {
-        if cxl.is_cancelled() {
+        if cxl.is_cancelled() || !wait_active(control, cxl, index, None, None).await {
             break;
         }
-        f().await?;
+        f(None).await?;
     }
     Ok(())
 }
 
-async fn run_until_count_with_bucket<F, Fut, T>(
+async fn run_until_count_with_limiter<F, Fut, T>(
     f: F,
     cxl: &CancellationToken,
     count: u64,
-    mut bucket: TokenBucket,
+    limiter: &RateLimiter,
+    control: &LoadControl,
+    index: usize,
 ) -> Result<()>
 where
-    F: Fn() -> Fut,
+    F: Fn(Option<Instant>) -> Fut,
     Fut: Future<Output = Result<T>>,
 {
     for _ in 0..count {
-        if cxl.is_cancelled() {
+        if !wait_active(control, cxl, index, None, Some(limiter)).await {
             break;
         }
-        bucket.try_acquire().await;
-        f().await?;
+        let intended = tokio::select! {
+            _ = cxl.cancelled() => break,
+            intended = limiter.acquire() => intended,
+        };
+        let Some(intended) = intended else {
+            break;
+        };
+        f(Some(intended)).await?;
     }
     Ok(())
 }
@@ -123,119 +706,193 @@ where
 async fn run_until_duration<F, Fut, T>(
     f: F,
     cxl: &CancellationToken,
-    duration: Duration,
+    deadline: Instant,
+    control: &LoadControl,
+    index: usize,
 ) -> Result<()>
 where
-    F: Fn() -> Fut,
+    F: Fn(Option<Instant>) -> Fut,
     Fut: Future<Output = Result<T>>,
 {
-    let start = Instant::now();
-    while start.elapsed() < duration && !cxl.is_cancelled() {
-        f().await?;
+    while Instant::now() < deadline && !cxl.is_cancelled() {
+        if !wait_active(control, cxl, index, Some(deadline), None).await {
+            break;
+        }
+        f(None).await?;
     }
     Ok(())
 }
 
-async fn run_until_duration_with_bucket<F, Fut, T>(
+async fn run_until_duration_with_limiter<F, Fut, T>(
     f: F,
     cxl: &CancellationToken,
-    duration: Duration,
-    mut bucket: TokenBucket,
+    deadline: Instant,
+    limiter: &RateLimiter,
+    control: &LoadControl,
+    index: usize,
 ) -> Result<()>
 where
-    F: Fn() -> Fut,
+    F: Fn(Option<Instant>) -> Fut,
     Fut: Future<Output = Result<T>>,
 {
-    let start = Instant::now();
-    bucket.update();
-    while start.elapsed() < duration && !cxl.is_cancelled() {
-        bucket.try_acquire().await;
-        f().await?;
+    while !cxl.is_cancelled() {
+        if !wait_active(control, cxl, index, Some(deadline), Some(limiter)).await {
+            break;
+        }
+        let Some(intended) = limiter.reserve().filter(|intended| *intended < deadline) else {
+            break;
+        };
+        tokio::select! {
+            _ = cxl.cancelled() => break,
+            _ = tokio::time::sleep_until(intended) => {}
+        }
+        f(Some(intended)).await?;
     }
     Ok(())
 }
 
-async fn request(client: Client, meter: Metrics) -> Result<()> {
+// The client with `index` is active while the profile targets more than `index` clients,
+// so clients join in order during a ramp-up and leave in reverse order during a ramp-down
+async fn run_with_client_profile<F, Fut, T>(
+    f: F,
+    cxl: &CancellationToken,
+    profile: &LoadProfile,
+    index: usize,
+    control: &LoadControl,
+) -> Result<()>
+where
+    F: Fn(Option<Instant>) -> Fut,
+    Fut: Future<Output = Result<T>>,
+{
+    const IDLE_POLL_INTERVAL: Duration = Duration::from_millis(100);
+
     let start = Instant::now();
-    let result = client.request().await;
+    let deadline = start + profile.duration();
+    while start.elapsed() < profile.duration() && !cxl.is_cancelled() {
+        if !wait_active(control, cxl, index, Some(deadline), None).await {
+            break;
+        }
+        if (index as f64) < profile.target(start.elapsed().as_secs_f64()) {
+            f(None).await?;
+        } else {
+            tokio::select! {
+                _ = cxl.cancelled() => break,
+                _ = tokio::time::sleep(IDLE_POLL_INTERVAL) => {}
+            }
+        }
+    }
+    Ok(())
+}
 
-    let (status, size) = match result {
-        Ok((status, size)) => (status, size),
-        Err(err) => {
-            let phase = match err.downcast_ref::<reqwest::Error>() {
-                Some(err) if err.is_timeout() => "timeout",
-                Some(err) if err.is_connect() => "connect",
-                Some(err) if err.is_request() => "request",
-                Some(err) if err.is_redirect() => "redirect",
-                _ => "unknown",
-            };
+// Open model: requests are sent on the limiter's schedule whether or not earlier responses
+// have arrived. In-flight requests are capped by `max_in_flight`, and arrivals that find
+// no free slot are counted as dropped instead of being delayed.
+async fn run_at_constant_arrival_rate(
+    tasks: &[BoxedAsyncClosure],
+    cxl: &CancellationToken,
+    opts: &BenchOption,
+    limiter: &RateLimiter,
+    meter: &Metrics,
+) -> Result<()> {
+    let in_flight = Arc::new(Semaphore::new(opts.max_in_flight));
+    let mut running = JoinSet::new();
+    let deadline = opts
+        .run_duration()
+        .map(|duration| Instant::now() + duration);
+
+    for (n, task) in (0_u64..).zip(tasks.iter().cycle()) {
+        if opts.count.is_some_and(|count| n >= count)
+            || !wait_active(&opts.control, cxl, 0, deadline, Some(limiter)).await
+        {
+            break;
+        }
 
-            meter.record_error(phase);
-            return Err(err);
+        let Some(intended) = limiter
+            .reserve()
+            .filter(|intended| deadline.is_none_or(|deadline| *intended < deadline))
+        else {
+            break;
+        };
+
+        tokio::select! {
+            _ = cxl.cancelled() => break,
+            _ = tokio::time::sleep_until(intended) => {}
         }
-    };
-    let elapsed = start.elapsed().as_secs_f64();
 
-    meter.record_duration(elapsed, status);
-    meter.record_response_size(size);
+        match Arc::clone(&in_flight).try_acquire_owned() {
+            Ok(permit) => {
+                let fut = task(Some(intended));
+                running.spawn(async move {
+                    let result = fut.await;
+                    drop(permit);
+                    result
+                });
+            }
+            Err(_) => meter.record_dropped(),
+        }
+
+        while let Some(result) = running.try_join_next() {
+            result??;
+        }
+    }
+
+    while let Some(result) = running.join_next().await {
+        result??;
+    }
 
     Ok(())
 }
 
-struct TokenBucket {
-    capacity: f64, // Maximum number of tokens in the bucket. Uses same value as fill_rate but can be adjusted to allow bursts
-    tokens: f64,   // Current number of tokens
-    last_refill: Instant, // Time when tokens were last refilled
-    fill_rate: f64, // Rate of token addition per second (same as capacity)
-}
+// `intended` is when the request should have been sent. Measuring from it as well keeps
+// the time spent waiting behind a slow server in the latency (coordinated omission).
+async fn request(
+    client: Client,
+    meter: &Metrics,
+    intended: Option<Instant>,
+    checks: &[Check],
+) -> Result<Response> {
+    let start = Instant::now();
+    let result = client.request().await;
 
-impl TokenBucket {
-    /// rps: Maximum number of requests per second
-    pub fn try_new(rps: u64, clients: usize) -> Result<Self> {
-        if clients == 0 {
-            bail!("Number of clients must be greater than zero");
-        }
-        if rps < clients as u64 {
-            bail!("RPS must be greater than or equal to the number of clients");
-        }
-        if rps == 0 {
-            bail!("RPS must be greater than zero");
+    let resp = match result {
+        Ok(resp) => resp,
+        Err(err) => {
+            meter.record_error(error_phase(&err), error_category(&err), &err);
+            return Err(err);
         }
+    };
+    let elapsed = start.elapsed();
 
-        let capacity = (rps / clients as u64) as f64;
-        let fill_rate = (rps / clients as u64) as f64;
-        Ok(Self {
-            capacity,
-            tokens: 0.0,
-            last_refill: Instant::now(),
-            fill_rate, // Considered the same as RPS
-        })
+    let version = version_name(resp.version);
+    meter.record_duration(elapsed.as_secs_f64(), resp.status, version);
+    if let Some(intended) = intended {
+        let corrected = corrected_duration(intended, start, elapsed);
+        meter.record_corrected_duration(corrected.as_secs_f64(), resp.status, version);
     }
+    meter.record_response_size(resp.size);
+    meter.record_response_timing(resp.timing.ttfb, resp.timing.download);
 
-    pub fn update(&mut self) {
-        self.last_refill = Instant::now();
+    let checked = Checked::new(&resp, elapsed);
+    for check in checks {
+        meter.record_check(&check.name, check.passes(&checked));
     }
 
-    /// Acquire tokens for one request
-    /// If tokens are insufficient, wait until the required amount is accumulated
-    pub async fn try_acquire(&mut self) {
-        let now = Instant::now();
-        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
-
-        // Refill tokens according to elapsed time
-        let new_tokens = elapsed * self.fill_rate;
-        if new_tokens > 0.0 {
-            self.tokens = (self.tokens + new_tokens).min(self.capacity);
-            self.last_refill = now;
-        }
+    Ok(resp)
+}
 
-        if self.tokens > 0.0 {
-            self.tokens -= 1.0;
-        } else {
-            let wait_time = Duration::from_secs_f64(1.0 / self.fill_rate);
-            tokio::time::sleep(wait_time).await;
-            Box::pin(self.try_acquire()).await;
-        }
+// Timers fire on millisecond ticks, so a request on time may start up to a tick after its
+// intended send time
+const TIMER_GRANULARITY: Duration = Duration::from_millis(1);
+
+// Measured from the intended send time when the request started late, e.g. behind a slow
+// response. Lateness within the granularity of the timer is left out rather than added to
+// every request.
+fn corrected_duration(intended: Instant, start: Instant, elapsed: Duration) -> Duration {
+    let late = start.saturating_duration_since(intended);
+    if late > TIMER_GRANULARITY {
+        late + elapsed
+    } else {
+        elapsed
     }
 }
 
@@ -245,6 +902,18 @@ pub struct BenchOption {
     pub duration: Option<Duration>,
     pub clients: usize,
     pub rps: Option<u64>,
+    pub executor: Executor,
+    pub max_in_flight: usize,
+    pub profile: Option<LoadProfile>,
+    pub warmup: Option<Warmup>,
+    pub feeder: Option<Arc<Feeder>>,
+    pub replay: Option<Arc<Replay>>,
+    /// Pauses the run and changes its rate or clients while it is in progress
+    pub control: LoadControl,
+    /// Connections shared by the clients, each client has its own when neither is set
+    pub connections: Option<usize>,
+    /// Requests sent at once over each shared connection
+    pub max_streams: Option<usize>,
 }
 
 impl BenchOption {
@@ -253,35 +922,172 @@ impl BenchOption {
         duration: Option<Duration>,
         clients: usize,
         rps: Option<u64>,
+        executor: Executor,
+        max_in_flight: usize,
+        profile: Option<LoadProfile>,
     ) -> Result<Self> {
         if clients == 0 {
             bail!("Number of clients must be greater than zero");
         }
 
-        if count.is_some() && duration.is_some() {
-            bail!("Either count or duration must be specified");
+        let profile_unit = profile.as_ref().map(LoadProfile::unit);
+
+        if executor == Executor::ConstantArrivalRate
+            && rps.is_none()
+            && profile_unit != Some(StageUnit::Rps)
+        {
+            bail!("RPS must be specified for the constant arrival rate executor");
         }
 
-        if count.is_none() && duration.is_none() {
-            bail!("Either count or duration must be specified");
+        if executor == Executor::ConstantArrivalRate && profile_unit == Some(StageUnit::Clients) {
+            bail!("Stages in clients cannot be used with the constant arrival rate executor");
         }
 
-        if let Some(count) = count {
-            if clients > count as usize {
-                bail!("Number of clients must be greater than or equal to the number of requests");
-            }
+        if rps.is_some() && profile_unit == Some(StageUnit::Rps) {
+            bail!("RPS cannot be specified together with stages in rps");
+        }
+
+        if max_in_flight == 0 {
+            bail!("Maximum number of in-flight requests must be greater than zero");
+        }
+
+        let limits = [count.is_some(), duration.is_some(), profile.is_some()];
+        if limits.into_iter().filter(|limit| *limit).count() != 1 {
+            bail!("Either count, duration or stages must be specified");
+        }
+
+        // Enough clients are started for the peak, the profile decides how many are active
+        let clients = match &profile {
+            Some(profile) if profile.unit() == StageUnit::Clients => profile.peak().ceil() as usize,
+            _ => clients,
+        };
+
+        if let Some(count) = count
+            && clients > count as usize
+        {
+            bail!("Number of clients must be greater than or equal to the number of requests");
         }
 
+        // Clients added during a run by count would have no requests to send
+        let adjustable = match (rps, executor, count, &profile) {
+            (Some(_), _, _, _) => Adjustable::Rps,
+            (None, Executor::ClosedLoop, None, None) => Adjustable::Clients,
+            _ => Adjustable::Nothing,
+        };
+
         Ok(Self {
             count,
             duration,
             clients,
             rps,
+            executor,
+            max_in_flight,
+            profile,
+            warmup: None,
+            feeder: None,
+            replay: None,
+            control: LoadControl::new(rps, clients, adjustable),
+            connections: None,
+            max_streams: None,
+        })
+    }
+
+    pub fn with_warmup(self, warmup: Option<Warmup>) -> Result<Self> {
+        match (warmup, self.count, self.run_duration()) {
+            (Some(Warmup::Requests(warmup)), Some(count), _) if warmup >= count => {
+                bail!("Warm-up requests must be fewer than the number of requests");
+            }
+            (Some(Warmup::Duration(warmup)), _, Some(duration)) if warmup >= duration => {
+                bail!("Warm-up duration must be shorter than the duration of the run");
+            }
+            _ => {}
+        }
+
+        Ok(Self { warmup, ..self })
+    }
+
+    /// Feed the rows of a data file into the requests, split between the clients if partitioned
+    pub fn with_feeder(self, feeder: Option<Feeder>) -> Result<Self> {
+        let feeder = feeder
+            .map(|feeder| feeder.with_clients(self.clients))
+            .transpose()?
+            .map(Arc::new);
+
+        Ok(Self { feeder, ..self })
+    }
+
+    /// Send the requests of an access log or a HAR file instead of the scenarios
+    pub fn with_replay(self, replay: Option<Replay>) -> Result<Self> {
+        if replay.is_some() && self.profile.is_some() {
+            bail!("Stages cannot be used with a replay");
+        }
+
+        // The recorded requests are sent by the clients started with the run
+        let control = match &replay {
+            Some(_) => LoadControl::new(None, self.clients, Adjustable::Nothing),
+            None => self.control,
+        };
+
+        Ok(Self {
+            replay: replay.map(Arc::new),
+            control,
+            ..self
+        })
+    }
+
+    /// Share `connections` between the clients, with at most `max_streams` requests at once
+    /// over each. Only the latter makes as many connections as needed for every client to send.
+    pub fn with_connections(
+        self,
+        connections: Option<usize>,
+        max_streams: Option<usize>,
+    ) -> Result<Self> {
+        if connections == Some(0) {
+            bail!("Number of connections must be greater than zero");
+        }
+        if max_streams == Some(0) {
+            bail!("Maximum number of streams must be greater than zero");
+        }
+
+        Ok(Self {
+            connections,
+            max_streams,
+            ..self
         })
     }
 
-    fn token_bucket(&self) -> Option<Result<TokenBucket>> {
-        self.rps.map(|rps| TokenBucket::try_new(rps, self.clients))
+    fn connection_count(&self) -> Option<usize> {
+        match (self.connections, self.max_streams) {
+            (Some(connections), _) => Some(connections),
+            (None, Some(max_streams)) => Some(self.clients.div_ceil(max_streams)),
+            (None, None) => None,
+        }
+    }
+
+    /// How long the run lasts, either given directly or as the total of the stages
+    pub fn run_duration(&self) -> Option<Duration> {
+        self.duration
+            .or_else(|| self.profile.as_ref().map(LoadProfile::duration))
+    }
+
+    fn client_profile(&self) -> Option<&LoadProfile> {
+        self.profile
+            .as_ref()
+            .filter(|profile| profile.unit() == StageUnit::Clients)
+    }
+
+    fn rate_limiter(&self) -> Result<Option<Arc<RateLimiter>>> {
+        let rate_profile = self
+            .profile
+            .clone()
+            .filter(|profile| profile.unit() == StageUnit::Rps);
+
+        let limiter = match (self.rps, rate_profile) {
+            (Some(rps), _) => Some(RateLimiter::try_new(rps)?),
+            (None, Some(profile)) => Some(RateLimiter::try_from_profile(profile)?),
+            (None, None) => None,
+        };
+        Ok(limiter.map(Arc::new))
     }
 
     pub fn iter(&self) -> BenchOptionIterator {
@@ -329,10 +1135,37 @@ impl Iterator for BenchOptionIterator {
 #[cfg(test)]
 mod tests {
     use super::*;
+    use crate::control::Source;
+    use crate::histogram::{Phase, TimingRecorder};
+    use crate::http::{HttpVersion, Method, Negotiation, Request};
+    use crate::profile::Stage;
+    use futures::FutureExt;
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[test]
+    fn test_corrected_duration() {
+        let intended = Instant::now();
+        let elapsed = Duration::from_millis(20);
+
+        // On time or late by the timer only
+        assert_eq!(corrected_duration(intended, intended, elapsed), elapsed);
+        let start = intended + Duration::from_micros(900);
+        assert_eq!(corrected_duration(intended, start, elapsed), elapsed);
+        // Started ahead of the schedule
+        let start = intended - Duration::from_millis(5);
+        assert_eq!(corrected_duration(intended, start, elapsed), elapsed);
+        // Held back by a slow response
+        let start = intended + Duration::from_millis(30);
+        assert_eq!(
+            corrected_duration(intended, start, elapsed),
+            Duration::from_millis(50)
+        );
+    }
 
     #[test]
     fn test_bench_option_iterator_exact_division() {
-        let bench_option = BenchOption::try_new(Some(9), None, 3, None).unwrap();
+        let bench_option =
+            BenchOption::try_new(Some(9), None, 3, None, Executor::ClosedLoop, 1000, None).unwrap();
 
         let mut iter = bench_option.iter();
 
@@ -345,7 +1178,9 @@ mod tests {
 
     #[test]
     fn test_bench_option_iterator() {
-        let bench_option = BenchOption::try_new(Some(10), None, 3, None).unwrap();
+        let bench_option =
+            BenchOption::try_new(Some(10), None, 3, None, Executor::ClosedLoop, 1000, None)
+                .unwrap();
 
         let mut iter = bench_option.iter();
 
@@ -358,7 +1193,8 @@ mod tests {
 
     #[test]
     fn test_bench_option_iterator_single_client() {
-        let bench_option = BenchOption::try_new(Some(5), None, 1, None).unwrap();
+        let bench_option =
+            BenchOption::try_new(Some(5), None, 1, None, Executor::ClosedLoop, 1000, None).unwrap();
 
         let mut iter = bench_option.iter();
 
@@ -369,74 +1205,337 @@ mod tests {
 
     #[test]
     fn test_bench_option_iterator_no_requests() {
-        let bench_option = BenchOption::try_new(None, None, 3, None);
+        let bench_option =
+            BenchOption::try_new(None, None, 3, None, Executor::ClosedLoop, 1000, None);
         assert!(bench_option.is_err());
     }
 
     #[test]
     fn test_bench_option_iterator_zero_clients() {
-        let bench_option = BenchOption::try_new(Some(5), None, 0, None);
+        let bench_option =
+            BenchOption::try_new(Some(5), None, 0, None, Executor::ClosedLoop, 1000, None);
         assert!(bench_option.is_err());
     }
 
     #[test]
     fn test_bench_option_iterator_more_clients_than_requests() {
-        let bench_option = BenchOption::try_new(Some(2), None, 5, None);
+        let bench_option =
+            BenchOption::try_new(Some(2), None, 5, None, Executor::ClosedLoop, 1000, None);
         assert!(bench_option.is_err());
     }
 
     #[test]
-    fn test_token_bucket_initialization_various_rates() {
-        // Standard case - 100 RPS
-        let bucket = TokenBucket::try_new(100, 1).unwrap();
-        assert_eq!(bucket.capacity, 100.0);
-        assert_eq!(bucket.tokens, 0.0);
+    fn test_bench_option_constant_arrival_rate_requires_rps() {
+        let bench_option = BenchOption::try_new(
+            None,
+            Some(Duration::from_secs(1)),
+            1,
+            None,
+            Executor::ConstantArrivalRate,
+            1000,
+            None,
+        );
+        assert!(bench_option.is_err());
+    }
 
-        // Low RPS case
-        let bucket = TokenBucket::try_new(5, 1).unwrap();
-        assert_eq!(bucket.capacity, 5.0);
-        assert_eq!(bucket.tokens, 0.0);
+    #[test]
+    fn test_bench_option_stages() {
+        let stage = |from, to, unit| Stage {
+            duration: Duration::from_secs(10),
+            from,
+            to,
+            unit,
+        };
+        let rps_profile = LoadProfile::try_new(vec![stage(0.0, 100.0, StageUnit::Rps)]).unwrap();
+        let clients_profile =
+            LoadProfile::try_new(vec![stage(1.0, 20.5, StageUnit::Clients)]).unwrap();
+
+        let opts = BenchOption::try_new(
+            None,
+            None,
+            10,
+            None,
+            Executor::ConstantArrivalRate,
+            1000,
+            Some(rps_profile.clone()),
+        )
+        .unwrap();
+        assert_eq!(opts.run_duration(), Some(Duration::from_secs(10)));
+        assert!(opts.rate_limiter().unwrap().is_some());
+
+        let opts = BenchOption::try_new(
+            None,
+            None,
+            10,
+            None,
+            Executor::ClosedLoop,
+            1000,
+            Some(clients_profile.clone()),
+        )
+        .unwrap();
+        // Clients are sized for the peak of the profile
+        assert_eq!(opts.clients, 21);
+        assert!(opts.rate_limiter().unwrap().is_none());
+
+        // Stages replace count and duration
+        let opts = BenchOption::try_new(
+            Some(10),
+            None,
+            1,
+            None,
+            Executor::ClosedLoop,
+            1000,
+            Some(rps_profile.clone()),
+        );
+        assert!(opts.is_err());
+
+        // RPS is given by the stages
+        let opts = BenchOption::try_new(
+            None,
+            None,
+            1,
+            Some(10),
+            Executor::ClosedLoop,
+            1000,
+            Some(rps_profile),
+        );
+        assert!(opts.is_err());
+
+        let opts = BenchOption::try_new(
+            None,
+            None,
+            1,
+            None,
+            Executor::ConstantArrivalRate,
+            1000,
+            Some(clients_profile),
+        );
+        assert!(opts.is_err());
+    }
 
-        // High RPS case
-        let bucket = TokenBucket::try_new(1000, 1).unwrap();
-        assert_eq!(bucket.capacity, 1000.0);
-        assert_eq!(bucket.tokens, 0.0);
+    #[tokio::test(start_paused = true)]
+    async fn test_client_profile_activates_clients_in_order() {
+        let profile = LoadProfile::try_new(vec![Stage {
+            duration: Duration::from_secs(10),
+            from: 0.0,
+            to: 10.0,
+            unit: StageUnit::Clients,
+        }])
+        .unwrap();
+        let cxl = CancellationToken::new();
+
+        let first_request = |index| {
+            let profile = profile.clone();
+            let cxl = cxl.clone();
+            async move {
+                let start = Instant::now();
+                let first = std::sync::Mutex::new(None);
+                let task = |_| {
+                    first.lock().unwrap().get_or_insert(start.elapsed());
+                    tokio::time::sleep(Duration::from_millis(500)).map(Ok::<_, anyhow::Error>)
+                };
+                let control = LoadControl::new(None, 10, Adjustable::Nothing);
+                run_with_client_profile(task, &cxl, &profile, index, &control)
+                    .await
+                    .unwrap();
+                first.into_inner().unwrap()
+            }
+        };
 
-        // Multiple clients case
-        let bucket = TokenBucket::try_new(100, 4).unwrap();
-        assert_eq!(bucket.capacity, 25.0); // (100 RPS / 4 clients) / (1000ms / 100ms window)
-        assert_eq!(bucket.tokens, 0.0);
+        // The first client starts as soon as the ramp leaves 0, the last one once it reaches 10
+        let first = first_request(0).await.unwrap();
+        assert!(first <= Duration::from_millis(100));
+        let last = first_request(9).await.unwrap();
+        assert!(last >= Duration::from_secs(9) && last < Duration::from_secs(10));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_paused_run_sends_nothing() {
+        let cxl = CancellationToken::new();
+        let control = LoadControl::new(None, 2, Adjustable::Clients);
+        let sent = AtomicU64::new(0);
+        let task = |_| {
+            sent.fetch_add(1, Ordering::Relaxed);
+            tokio::time::sleep(Duration::from_millis(100)).map(Ok::<_, anyhow::Error>)
+        };
+
+        // Paused between two requests after a second, resumed a second later
+        let toggle = {
+            let control = control.clone();
+            tokio::spawn(async move {
+                tokio::time::sleep(Duration::from_millis(1050)).await;
+                control.toggle_pause(Source::Keyboard);
+                tokio::time::sleep(Duration::from_secs(1)).await;
+                control.toggle_pause(Source::Keyboard);
+            })
+        };
+        let deadline = Instant::now() + Duration::from_secs(3);
+        run_until_duration(&task, &cxl, deadline, &control, 0)
+            .await
+            .unwrap();
+        toggle.await.unwrap();
+        // 11 requests from 0s to 1s, then 10 from 2.05s to 2.95s
+        assert_eq!(sent.load(Ordering::Relaxed), 21);
+
+        // Beyond the target number of clients nothing is sent until the run ends
+        sent.store(0, Ordering::Relaxed);
+        let deadline = Instant::now() + Duration::from_secs(1);
+        run_until_duration(&task, &cxl, deadline, &control, 2)
+            .await
+            .unwrap();
+        assert_eq!(sent.load(Ordering::Relaxed), 0);
     }
 
     #[test]
-    fn test_token_bucket_error_cases_extended() {
-        // When number of clients is zero
-        assert!(TokenBucket::try_new(100, 0).is_err());
+    fn test_bench_option_warmup() {
+        let opts = |count, duration| {
+            BenchOption::try_new(count, duration, 1, None, Executor::ClosedLoop, 1000, None)
+                .unwrap()
+        };
+
+        let warmup = Some(Warmup::Requests(10));
+        assert!(opts(Some(100), None).with_warmup(warmup).is_ok());
+        assert!(opts(Some(10), None).with_warmup(warmup).is_err());
+
+        let warmup = Some(Warmup::Duration(Duration::from_secs(10)));
+        assert!(
+            opts(None, Some(Duration::from_secs(30)))
+                .with_warmup(warmup)
+                .is_ok()
+        );
+        assert!(
+            opts(None, Some(Duration::from_secs(10)))
+                .with_warmup(warmup)
+                .is_err()
+        );
+    }
 
-        // When RPS is less than number of clients
-        assert!(TokenBucket::try_new(5, 10).is_err());
-        assert!(TokenBucket::try_new(1, 2).is_err());
-        assert!(TokenBucket::try_new(99, 100).is_err());
+    #[test]
+    fn test_bench_option_connections() {
+        let opts =
+            BenchOption::try_new(Some(100), None, 10, None, Executor::ClosedLoop, 1000, None)
+                .unwrap();
+        assert_eq!(opts.connection_count(), None);
+
+        let shared = |connections, max_streams| {
+            opts.clone()
+                .with_connections(connections, max_streams)
+                .unwrap()
+                .connection_count()
+        };
+        assert_eq!(shared(Some(2), None), Some(2));
+        assert_eq!(shared(Some(2), Some(3)), Some(2));
+        // As many connections as needed for all clients to send at once
+        assert_eq!(shared(None, Some(3)), Some(4));
+        assert_eq!(shared(None, Some(100)), Some(1));
+
+        assert!(opts.clone().with_connections(Some(0), None).is_err());
+        assert!(opts.with_connections(None, Some(0)).is_err());
+    }
 
-        // Edge cases that should work
-        assert!(TokenBucket::try_new(100, 100).is_ok());
-        assert!(TokenBucket::try_new(1, 1).is_ok());
+    #[tokio::test(start_paused = true)]
+    async fn test_warmup_gate() {
+        let gate = WarmupGate::new(Warmup::Requests(2));
+        let phases: Vec<_> = (0..4).map(|_| (gate.is_warmup(), gate.is_open())).collect();
+        // Still open while the last warm-up iteration is going on
+        assert_eq!(
+            phases,
+            [(true, true), (true, true), (false, false), (false, false)]
+        );
+
+        let gate = WarmupGate::new(Warmup::Duration(Duration::from_secs(1)));
+        assert!(gate.is_warmup());
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(!gate.is_warmup());
     }
 
     #[tokio::test]
-    async fn test_token_bucket_refill() {
-        let mut bucket = TokenBucket::try_new(10, 1).unwrap();
-        bucket.update();
+    async fn test_warmup_connections() {
+        let mut server = mockito::Server::new_async().await;
+        server.mock("GET", "/").create_async().await;
+
+        // mockito closes every connection, so each request opens one
+        let connects = |include_warmup| {
+            let url = server.url();
+            async move {
+                let request = Request {
+                    http_version: HttpVersion::Http11,
+                    negotiation: Negotiation::Auto,
+                    url: url::Url::parse(&url).unwrap(),
+                    method: Method::Get,
+                    headers: Default::default(),
+                    timeout: Duration::from_secs(3),
+                    body: Vec::new(),
+                    basic_auth: None,
+                    insecure: false,
+                    disable_keepalive: false,
+                    root_cert: None,
+                    resolve: None,
+                };
+                let scenario = Scenario::try_single(&url, request, &[]).unwrap();
+                let opts =
+                    BenchOption::try_new(Some(3), None, 1, None, Executor::ClosedLoop, 1000, None)
+                        .unwrap()
+                        .with_warmup(Some(Warmup::Requests(1)))
+                        .unwrap();
+                let timing = TimingRecorder::try_new(3, include_warmup).unwrap();
+                let meter = noop_metrics().with_timing_recorder(timing.clone());
+                bench(CancellationToken::new(), vec![scenario], opts, meter)
+                    .await
+                    .unwrap();
+                timing
+                    .phases()
+                    .into_iter()
+                    .find(|(phase, _)| *phase == Phase::Connect)
+                    .map_or(0, |(_, latencies)| latencies.count())
+            }
+        };
+        // The connection of the warm-up request is left out unless --include-warmup
+        assert_eq!(connects(false).await, 2);
+        assert_eq!(connects(true).await, 3);
+    }
 
-        // Wait for 0.1 seconds - should accumulate 1 tokens
-        tokio::time::sleep(Duration::from_millis(100)).await;
-        bucket.try_acquire().await;
-        assert!(bucket.tokens >= 0.0 && bucket.tokens <= 0.1); // Should have ~1 tokens left after consuming one
+    fn noop_metrics() -> Metrics {
+        let meter = opentelemetry::global::meter("test");
+        Metrics::new(&meter)
+    }
 
-        // Consume all remaining tokens
-        for _ in 0..1 {
-            bucket.try_acquire().await;
-        }
-        assert!(bucket.tokens < 0.1); // Should be almost 0
+    #[tokio::test(start_paused = true)]
+    async fn test_constant_arrival_rate_does_not_wait_for_responses() {
+        let started = Arc::new(AtomicU64::new(0));
+        let task: BoxedAsyncClosure = {
+            let started = Arc::clone(&started);
+            Box::new(move |_| {
+                let started = Arc::clone(&started);
+                Box::pin(async move {
+                    started.fetch_add(1, Ordering::Relaxed);
+                    tokio::time::sleep(Duration::from_millis(950)).await;
+                    Ok(())
+                })
+            })
+        };
+
+        let start = Instant::now();
+        let cxl = CancellationToken::new();
+        // 10 arrivals per second, each request takes 950ms and at most 5 may be in flight
+        let limiter = RateLimiter::try_new(10).unwrap();
+        let opts = BenchOption::try_new(
+            Some(20),
+            None,
+            1,
+            Some(10),
+            Executor::ConstantArrivalRate,
+            5,
+            None,
+        )
+        .unwrap();
+        run_at_constant_arrival_rate(&[task], &cxl, &opts, &limiter, &noop_metrics())
+            .await
+            .unwrap();
+
+        // Arrivals at 0.5s..0.9s and 1.5s..1.9s find no free slot and are dropped
+        assert_eq!(started.load(Ordering::Relaxed), 10);
+        // The last arrival is scheduled at 1.9s, not delayed by the slow responses
+        assert!(start.elapsed() < Duration::from_millis(1900 + 950 + 1));
     }
 }
@@ -1,19 +1,31 @@
-use anyhow::{Result, bail};
+use anyhow::{Context, Result, bail};
 use clap::builder::{Styles, styling};
 use clap::error::ErrorKind;
 use clap::{CommandFactory, Parser};
-use hb::bench::BenchOption;
+use hb::bench::{BenchOption, Executor, Warmup};
+use hb::check::Check;
+use hb::control::LoadControl;
+use hb::feeder::{Feeder, FeederEnd, FeederOrder};
+use hb::histogram::{DEFAULT_PRECISION, parse_percentile};
 use hb::http::HttpVersion;
-use hb::http::{Method, Request};
+use hb::http::{Method, Negotiation, Request};
 use hb::otlp::{OtlpOptions, OtlpProtocol};
+use hb::profile::{LoadProfile, Stage, StageUnit};
+use hb::replay::{Replay, ReplaySpeed};
+use hb::reporter::GroupBy;
 use hb::reporter::formatter::OutputFormat;
+use hb::reporter::timeseries::Timeseries;
+use hb::scenario::{Scenario, ScenarioFile};
+use hb::threshold::{AbortCondition, AbortSignal, Threshold, Verdict};
+use hb::time::parse_duration;
 use regex::Regex;
 use std::ffi::OsString;
 use std::fmt::{Debug, Display};
-use std::net::IpAddr;
+use std::net::{IpAddr, SocketAddr};
 use std::path::PathBuf;
 use std::str::FromStr;
 use std::time::Duration;
+use tokio_util::sync::CancellationToken;
 
 #[derive(Parser, Debug)]
 #[command(author, version, about, next_line_help = true, long_about = None, styles(help_styles()))]
@@ -27,6 +39,20 @@ pub struct Args {
     #[arg(short, long, value_parser = parse_duration, group = "load_params")]
     pub duration: Option<Duration>,
 
+    /// Stage of a load profile in the format <duration>:[<from>->]<to><unit>, can be repeated.
+    /// e.g. 30s:0->200rps, 2m:200rps, 1m:10->50clients. Available units: rps, clients
+    #[arg(long = "stage", value_parser = parse_stage, group = "load_params")]
+    pub stages: Vec<Stage>,
+
+    /// Warm-up at the beginning of the run, as a number of requests or a duration (e.g. 100, 10s).
+    /// Warm-up requests are recorded with phase="warmup" and excluded from the report
+    #[arg(long, value_parser = parse_warmup)]
+    pub warmup: Option<Warmup>,
+
+    /// Include warm-up requests in the report
+    #[arg(long, requires = "warmup")]
+    pub include_warmup: bool,
+
     /// Number of clients to simulate
     #[arg(short, long, default_value = "10", value_parser = parse_positive::<usize>)]
     pub clients: usize,
@@ -35,10 +61,27 @@ pub struct Args {
     #[arg(short, long)]
     pub thread: Option<usize>,
 
-    /// If server doesn't address http/2, it will be downgraded to http/1.1
+    /// 2 agrees on HTTP/2 with the server as --h2-negotiation says, by default falling back to
+    /// http/1.1 if an https server doesn't address it. 3 sends the requests over QUIC and needs
+    /// an https URL
     #[arg(long, default_value = "1.1")]
     pub http_version: HttpVersion,
 
+    /// How HTTP/2 is agreed on with the server for --http-version 2. The report shows the
+    /// protocol versions of the responses
+    #[arg(long, default_value = "auto")]
+    pub h2_negotiation: Negotiation,
+
+    /// Number of connections the clients share, like h2load's -c. Needs --http-version 2.
+    /// Without it each client opens connections of its own
+    #[arg(long, value_parser = parse_positive::<usize>, conflicts_with = "replay")]
+    pub connections: Option<usize>,
+
+    /// Maximum number of concurrent streams per shared connection, like h2load's -m.
+    /// Without --connections, as many connections are opened as the clients need
+    #[arg(long, value_parser = parse_positive::<usize>, conflicts_with = "replay")]
+    pub max_streams: Option<usize>,
+
     /// HTTP method to use
     #[arg(short, long, default_value = "get")]
     pub method: Method,
@@ -93,18 +136,95 @@ pub struct Args {
     #[arg(long)]
     pub resolve: Option<IpAddr>,
 
-    /// Requests per second
-    /// Experimental feature: this is not guaranteed to be accurate
-    #[arg(long)]
+    /// Requests per second, shared by all clients
+    #[arg(long, value_parser = parse_positive::<u64>)]
     pub rps: Option<u64>,
 
+    /// How requests are issued.
+    /// constant-arrival-rate sends requests at --rps regardless of response times
+    #[arg(long, default_value = "closed-loop")]
+    pub executor: Executor,
+
+    /// Maximum number of in-flight requests for the constant-arrival-rate executor.
+    /// Requests that would exceed it are counted as dropped
+    #[arg(long, default_value = "1000", value_parser = parse_positive::<usize>)]
+    pub max_in_flight: usize,
+
+    /// Scenario file in YAML or TOML listing weighted requests.
+    /// Their URLs are resolved against <URL> and unset fields fall back to the options above
+    #[arg(long)]
+    pub scenario: Option<PathBuf>,
+
+    /// CSV or JSONL file whose rows fill {{column}} placeholders, one row per iteration
+    #[arg(long)]
+    pub data_file: Option<PathBuf>,
+
+    /// Status codes every response is checked against, e.g. 200,201.
+    /// Responses with any other status count as failed checks
+    #[arg(long, value_delimiter = ',')]
+    pub expect_status: Vec<u16>,
+
+    /// Latency every response is checked against, slower responses count as failed checks
+    #[arg(long, value_parser = parse_duration)]
+    pub max_latency: Option<Duration>,
+
+    /// Condition on the final report, e.g. "p99<250ms", "error_rate<0.5%" or "rps>1000".
+    /// The run exits with code 3 when any of them does not hold
+    #[arg(long = "threshold", value_name = "EXPR")]
+    pub thresholds: Vec<Threshold>,
+
+    /// Condition on the last seconds of the run that stops it early, e.g. "error_rate>20% for 10s"
+    /// or "p95>2s for 30s". The window defaults to 10s
+    #[arg(long = "abort-on", value_name = "EXPR")]
+    pub abort_on: Vec<AbortCondition>,
+
+    /// nginx combined access log, or HAR file with the .har extension, to replay against <URL>.
+    /// The recorded requests are sent once in order
+    #[arg(long, conflicts_with_all = ["scenario", "data_file", "stages", "rps"])]
+    pub replay: Option<PathBuf>,
+
+    /// Speed multiplier of the recorded timing for --replay, or "max" to send as fast as possible
+    #[arg(long, default_value = "1", value_parser = parse_replay_speed, requires = "replay")]
+    pub replay_speed: ReplaySpeed,
+
+    /// Order in which the rows of --data-file are used
+    #[arg(long, default_value = "sequential", requires = "data_file")]
+    pub data_order: FeederOrder,
+
+    /// What happens once every row of --data-file has been used
+    #[arg(long, default_value = "circular", requires = "data_file")]
+    pub data_end: FeederEnd,
+
     /// Output format
     #[arg(short, long = "output", default_value = "text")]
     pub output_format: OutputFormat,
 
-    /// URL to make the request to
-    #[arg(name = "URL")]
-    pub url: url::Url,
+    /// Latency percentiles in the report, e.g. 50,90,99,99.9,99.99
+    #[arg(long, default_value = "50,95,99", value_delimiter = ',', value_parser = parse_percentile)]
+    pub percentiles: Vec<f64>,
+
+    /// Significant digits kept when recording latencies, from 1 to 5.
+    /// Each one more makes percentiles ten times as precise and uses more memory
+    #[arg(long, default_value_t = DEFAULT_PRECISION, value_parser = clap::value_parser!(u8).range(1..=5))]
+    pub latency_precision: u8,
+
+    /// Attributes the per-endpoint breakdown of the report is grouped on
+    #[arg(long, default_value = "method,path", value_delimiter = ',')]
+    pub group_by: Vec<GroupBy>,
+
+    /// Write rps, error rate, p50 and p99 of every second of the run to this CSV file.
+    /// They are in the JSON report as "timeseries" as well
+    #[arg(long, value_name = "PATH")]
+    pub timeseries_csv: Option<PathBuf>,
+
+    /// Listen on this address for changes of the load during the run, e.g. 127.0.0.1:7777.
+    /// GET /load returns the targets and PUT /load changes them, e.g. {"rps": 200}
+    #[arg(long, value_name = "ADDR")]
+    pub control_addr: Option<SocketAddr>,
+
+    /// URL to make the request to, which may contain placeholders such as {{seq}}
+    #[arg(name = "URL", value_parser = parse_url)]
+    pub url: String,
 
     /// No-interactive mode
     #[arg(long)]
@@ -125,7 +245,8 @@ impl Args {
 
         Request {
             http_version: self.http_version.clone(),
-            url: self.url.clone(),
+            negotiation: self.h2_negotiation,
+            url: url::Url::parse(&self.url).expect("URL is validated when parsed"),
             method: self.method.clone(),
             headers: self.headers.iter().cloned().collect(),
             timeout: self.timeout,
@@ -138,21 +259,111 @@ impl Args {
         }
     }
 
-    pub fn otlp_options(&self) -> OtlpOptions {
-        OtlpOptions {
+    pub fn feeder(&self) -> Result<Option<Feeder>> {
+        self.data_file
+            .as_deref()
+            .map(|path| Feeder::from_path(path, self.data_order, self.data_end))
+            .transpose()
+    }
+
+    /// Read the --replay file. Without -n, -d or --stage the whole file is replayed.
+    pub fn load_replay(&mut self) -> Result<Option<Replay>> {
+        let Some(path) = &self.replay else {
+            return Ok(None);
+        };
+        let mut replay = Replay::from_path(path, &self.request(), self.replay_speed)?;
+        replay.scenario = replay.scenario.with_checks(&self.checks()?);
+
+        if self.duration.is_none() {
+            let len = replay.len() as u64;
+            self.requests = Some(self.requests.map_or(len, |requests| requests.min(len)));
+        }
+        // A short log does not need all the clients
+        self.clients = self.clients.min(replay.len());
+
+        Ok(Some(replay))
+    }
+
+    pub fn scenarios(&self, feeder: Option<&Feeder>) -> Result<Vec<Scenario>> {
+        let request = self.request();
+        let inputs = feeder.map(Feeder::columns).unwrap_or_default();
+        let scenarios = match &self.scenario {
+            Some(path) => ScenarioFile::from_path(path)?.scenarios(&request, inputs)?,
+            None => vec![Scenario::try_single(&self.url, request, inputs)?],
+        };
+
+        let checks = self.checks()?;
+        Ok(scenarios
+            .into_iter()
+            .map(|scenario| scenario.with_checks(&checks))
+            .collect())
+    }
+
+    // Checks given on the command line apply to every request
+    fn checks(&self) -> Result<Vec<Check>> {
+        let mut checks = Vec::new();
+        if !self.expect_status.is_empty() {
+            checks.push(Check::status(self.expect_status.clone())?);
+        }
+        if let Some(latency) = self.max_latency {
+            checks.push(Check::max_latency(latency)?);
+        }
+        Ok(checks)
+    }
+
+    /// `token` is cancelled when an abort condition holds,
+    /// and `control` is shown on the interactive screen
+    pub fn otlp_options(
+        &self,
+        token: CancellationToken,
+        control: LoadControl,
+    ) -> Result<OtlpOptions> {
+        Ok(OtlpOptions {
             endpoint: self.otlp_endpoint.clone(),
             protocol: self.otlp_protocol.clone(),
             interval: self.otlp_interval,
             requests: self.requests,
             duration: self.duration,
+            profile: self.load_profile()?,
+            include_warmup: self.include_warmup,
             output_format: self.output_format.clone(),
             no_interactive: self.no_interactive,
             no_clear_console: self.no_clear_console,
-        }
+            thresholds: self.thresholds.clone(),
+            verdict: Verdict::new(&self.thresholds),
+            abort_on: self.abort_on.clone(),
+            abort: AbortSignal::new(token),
+            percentiles: self.percentiles.clone(),
+            latency_precision: self.latency_precision,
+            group_by: self.group_by.clone(),
+            timeseries: Timeseries::default(),
+            control,
+        })
     }
 
     pub fn bench_options(&self) -> Result<BenchOption> {
-        BenchOption::try_new(self.requests, self.duration, self.clients, self.rps)
+        BenchOption::try_new(
+            self.requests,
+            self.duration,
+            self.clients,
+            self.rps,
+            self.executor,
+            self.max_in_flight,
+            self.load_profile()?,
+        )?
+        .with_warmup(self.warmup)?
+        .with_connections(self.connections, self.max_streams)
+    }
+
+    fn load_profile(&self) -> Result<Option<LoadProfile>> {
+        if self.stages.is_empty() {
+            return Ok(None);
+        }
+        LoadProfile::try_new(self.stages.clone()).map(Some)
+    }
+
+    fn stage_unit(&self) -> Option<StageUnit> {
+        self.stages.first().map(|stage| stage.unit)
     }
 
     pub fn parse_wrapper() -> Result<Args> {
@@ -181,30 +392,59 @@ impl Args {
 
     // This function is a wrapper around Args::parse() that performs additional validation and configuration
     fn validation(&self) -> Result<()> {
-        if let Some(rps) = self.rps {
-            if self.clients as u64 > rps {
-                let mut cmd = Args::command();
-                let err = cmd
-                    .error(
-                        ErrorKind::ArgumentConflict,
-                        "RPS must be greater than or equal to the number of clients",
-                    )
-                    .into();
-                return Err(err);
-            }
+        if let Some(num) = self.requests
+            && self.clients as u64 > num
+        {
+            let mut cmd = Args::command();
+            let err = cmd
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "Number of clients must be greater than or equal to the number of requests",
+                )
+                .into();
+            return Err(err);
+        }
+
+        if self.executor == Executor::ConstantArrivalRate
+            && self.rps.is_none()
+            && self.stage_unit() != Some(StageUnit::Rps)
+        {
+            let mut cmd = Args::command();
+            let err = cmd
+                .error(
+                    ErrorKind::MissingRequiredArgument,
+                    "--rps or stages in rps are required for the constant-arrival-rate executor",
+                )
+                .into();
+            return Err(err);
+        }
+
+        if self.rps.is_some() && self.stage_unit() == Some(StageUnit::Rps) {
+            let mut cmd = Args::command();
+            let err = cmd
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--rps cannot be used with stages in rps",
+                )
+                .into();
+            return Err(err);
         }
 
-        if let Some(num) = self.requests {
-            if self.clients as u64 > num {
-                let mut cmd = Args::command();
-                let err = cmd
-                    .error(
-                        ErrorKind::ArgumentConflict,
-                        "Number of clients must be greater than or equal to the number of requests",
-                    )
-                    .into();
-                return Err(err);
-            }
+        if let Err(e) = self.load_profile() {
+            let mut cmd = Args::command();
+            let err = cmd.error(ErrorKind::ValueValidation, e).into();
+            return Err(err);
+        }
+
+        if self.scenario.is_some() && self.url.contains("{{") {
+            let mut cmd = Args::command();
+            let err = cmd
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "URL placeholders cannot be used with --scenario, use them in the scenario file",
+                )
+                .into();
+            return Err(err);
         }
 
         if self.disable_keepalive && self.http_version == HttpVersion::Http2 {
@@ -218,6 +458,30 @@ impl Args {
             return Err(err);
         }
 
+        if self.h2_negotiation != Negotiation::Auto && self.http_version != HttpVersion::Http2 {
+            let mut cmd = Args::command();
+            let err = cmd
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--h2-negotiation needs --http-version 2",
+                )
+                .into();
+            return Err(err);
+        }
+
+        if (self.connections.is_some() || self.max_streams.is_some())
+            && self.http_version != HttpVersion::Http2
+        {
+            let mut cmd = Args::command();
+            let err = cmd
+                .error(
+                    ErrorKind::ArgumentConflict,
+                    "--connections and --max-streams need --http-version 2",
+                )
+                .into();
+            return Err(err);
+        }
+
         Ok(())
     }
 }
@@ -238,40 +502,67 @@ fn parse_basic_auth(input: &str) -> Result<(String, String)> {
     Ok((parts[0].to_string(), parts[1].to_string()))
 }
 
-fn parse_duration(input: &str) -> Result<Duration> {
-    let re_validate = Regex::new(r"^(\d+(?:\.\d+)?(?:ms|s|m|h|d))+$")?;
-    if !re_validate.is_match(input) {
-        bail!("Invalid format");
-    }
-
-    let re = Regex::new(r"(?P<value>\d+)(?P<unit>(ms|s|m|h|d))")?;
-    let mut total_duration = Duration::new(0, 0);
+fn parse_stage(input: &str) -> Result<Stage> {
+    let re = Regex::new(
+        r"^(?P<duration>[^:]+):(?:(?P<from>\d+(?:\.\d+)?)(?P<from_unit>rps|clients)?->)?(?P<to>\d+(?:\.\d+)?)(?P<unit>rps|clients)$",
+    )?;
+    let Some(caps) = re.captures(input) else {
+        bail!(
+            "Stage must be in the format <duration>:[<from>->]<to><unit>. Available units are rps/clients."
+        );
+    };
 
-    if re.captures_iter(input).count() == 0 {
-        bail!("Duration must be in the format <value><unit>. Available units are ms/s/m/h/d.");
+    let unit = match &caps["unit"] {
+        "rps" => StageUnit::Rps,
+        "clients" => StageUnit::Clients,
+        _ => bail!("Available units are rps/clients"),
+    };
+    if caps
+        .name("from_unit")
+        .is_some_and(|from_unit| from_unit.as_str() != unit.as_ref())
+    {
+        bail!("Stage must use the same unit for both ends");
     }
 
-    for caps in re.captures_iter(input) {
-        let value: u64 = caps["value"].parse()?;
-        let unit = &caps["unit"];
+    let to: f64 = caps["to"].parse()?;
+    let from = match caps.name("from") {
+        Some(from) => from.as_str().parse()?,
+        None => to,
+    };
 
-        let duration = match unit {
-            "ms" => Duration::from_millis(value),
-            "s" => Duration::from_secs(value),
-            "m" => Duration::from_secs(value * 60),
-            "h" => Duration::from_secs(value * 60 * 60),
-            "d" => Duration::from_secs(value * 60 * 60 * 24),
-            _ => bail!("Available units are ms/s/m/h/d"),
-        };
+    Ok(Stage {
+        duration: parse_duration(&caps["duration"])?,
+        from,
+        to,
+        unit,
+    })
+}
 
-        total_duration += duration;
+fn parse_warmup(input: &str) -> Result<Warmup> {
+    if input.chars().all(|c| c.is_ascii_digit()) {
+        parse_positive(input).map(Warmup::Requests)
+    } else {
+        parse_duration(input).map(Warmup::Duration)
     }
+}
+
+// Placeholders are kept as written, only the URL itself is checked
+fn parse_url(input: &str) -> Result<String> {
+    url::Url::parse(input).with_context(|| format!("Invalid URL '{}'", input))?;
+    Ok(input.to_string())
+}
 
-    if total_duration.as_millis() == 0 {
-        bail!("Duration must be greater than 0");
+fn parse_replay_speed(input: &str) -> Result<ReplaySpeed> {
+    if input == "max" {
+        return Ok(ReplaySpeed::Max);
     }
 
-    Ok(total_duration)
+    match input.parse::<f64>() {
+        Ok(multiplier) if multiplier > 0.0 && multiplier.is_finite() => {
+            Ok(ReplaySpeed::Timed(multiplier))
+        }
+        _ => bail!("Replay speed must be a number greater than 0 or max"),
+    }
 }
 
 fn parse_key_value(s: &str) -> Result<(String, String)> {
@@ -301,6 +592,7 @@ mod tests {
     use std::str::FromStr;
 
     use super::*;
+    use hb::control::Adjustable;
 
     #[test]
     fn test_parse_basic_auth() {
@@ -317,31 +609,296 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_duration() {
+    fn test_parse_stage() {
+        let stage = |secs, from, to, unit| Stage {
+            duration: Duration::from_secs(secs),
+            from,
+            to,
+            unit,
+        };
         let inputs = [
-            ("500ms", Duration::from_millis(500)),
-            ("30s", Duration::from_secs(30)),
-            ("45m", Duration::from_secs(2700)),
-            ("1h", Duration::from_secs(3600)),
-            ("2d", Duration::from_secs(172800)),
-            ("1h30m15s", Duration::from_secs(5415)),
+            ("30s:0->200rps", stage(30, 0.0, 200.0, StageUnit::Rps)),
+            ("2m:200rps", stage(120, 200.0, 200.0, StageUnit::Rps)),
+            (
+                "10s:200rps->1000rps",
+                stage(10, 200.0, 1000.0, StageUnit::Rps),
+            ),
+            (
+                "1m30s:50->0clients",
+                stage(90, 50.0, 0.0, StageUnit::Clients),
+            ),
+            ("5s:2.5rps", stage(5, 2.5, 2.5, StageUnit::Rps)),
         ];
 
         inputs.iter().for_each(|(input, expected)| {
-            let result = parse_duration(input).unwrap();
+            let result = parse_stage(input).unwrap();
             assert_eq!(result, *expected);
         });
     }
 
     #[test]
-    fn test_parse_duration_invalid() {
-        let inputs = ["1x", "-1s", "0s"];
+    fn test_parse_stage_invalid() {
+        let inputs = [
+            "30s",
+            "30s:200",
+            "0s:200rps",
+            "30s:0rps->200clients",
+            "x:200rps",
+        ];
         inputs.iter().for_each(|input| {
-            let result = parse_duration(input);
-            assert!(result.is_err());
+            let result = parse_stage(input);
+            assert!(result.is_err(), "{}", input);
         });
     }
 
+    #[test]
+    fn test_parse_wrapper_stages() {
+        let args = vec![
+            "test",
+            "--stage",
+            "30s:0->200rps",
+            "--stage",
+            "2m:200rps",
+            "--executor",
+            "constant-arrival-rate",
+            "http://example.com",
+        ];
+        let args = Args::parse_wrapper_from(args).unwrap();
+        let profile = args.load_profile().unwrap().unwrap();
+        assert_eq!(profile.duration(), Duration::from_secs(150));
+        assert!(args.bench_options().is_ok());
+    }
+
+    #[test]
+    fn test_parse_wrapper_stages_conflict_with_rps() {
+        let args = vec![
+            "test",
+            "--stage",
+            "30s:0->200rps",
+            "--rps",
+            "100",
+            "http://example.com",
+        ];
+        let err = Args::parse_wrapper_from(args);
+        assert!(err.is_err());
+        assert!(
+            err.unwrap_err()
+                .to_string()
+                .contains("--rps cannot be used with stages in rps")
+        );
+    }
+
+    #[test]
+    fn test_parse_wrapper_mixed_stage_units() {
+        let args = vec![
+            "test",
+            "--stage",
+            "30s:0->200rps",
+            "--stage",
+            "30s:10clients",
+            "http://example.com",
+        ];
+        let err = Args::parse_wrapper_from(args);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_parse_warmup() {
+        assert_eq!(parse_warmup("100").unwrap(), Warmup::Requests(100));
+        assert_eq!(
+            parse_warmup("10s").unwrap(),
+            Warmup::Duration(Duration::from_secs(10))
+        );
+        assert!(parse_warmup("0").is_err());
+        assert!(parse_warmup("10x").is_err());
+    }
+
+    #[test]
+    fn test_parse_url() {
+        let url = "http://example.com/items/{{random_int 1 10}}?id={{uuid}}";
+        assert_eq!(parse_url(url).unwrap(), url);
+        assert!(parse_url("/items").is_err());
+    }
+
+    #[test]
+    fn test_parse_data_file_options() {
+        let args = Args::parse_from(["test", "http://example.com/{{user_id}}"]);
+        assert_eq!(args.data_file, None);
+        assert_eq!(args.data_order, FeederOrder::Sequential);
+        assert_eq!(args.data_end, FeederEnd::Circular);
+
+        let args = Args::parse_from([
+            "test",
+            "--data-file",
+            "users.csv",
+            "--data-order",
+            "partitioned",
+            "--data-end",
+            "stop",
+            "http://example.com/{{user_id}}",
+        ]);
+        assert_eq!(args.data_file, Some(PathBuf::from("users.csv")));
+        assert_eq!(args.data_order, FeederOrder::Partitioned);
+        assert_eq!(args.data_end, FeederEnd::Stop);
+
+        let result = Args::try_parse_from(["test", "--data-order", "random", "http://example.com"]);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_parse_wrapper_url_placeholders_conflict_with_scenario() {
+        let args = vec![
+            "test",
+            "--scenario",
+            "scenario.yaml",
+            "http://example.com/{{seq}}",
+        ];
+        let err = Args::parse_wrapper_from(args);
+        assert!(err.is_err());
+        assert!(
+            err.unwrap_err()
+                .to_string()
+                .contains("URL placeholders cannot be used with --scenario")
+        );
+    }
+
+    #[test]
+    fn test_checks() {
+        let args = Args::parse_from(["test", "http://example.com"]);
+        assert!(args.checks().unwrap().is_empty());
+
+        let args = Args::parse_from([
+            "test",
+            "--expect-status",
+            "200,201",
+            "--max-latency",
+            "500ms",
+            "http://example.com",
+        ]);
+        assert_eq!(args.expect_status, vec![200, 201]);
+        assert_eq!(args.max_latency, Some(Duration::from_millis(500)));
+
+        let checks = args.checks().unwrap();
+        let names: Vec<_> = checks.iter().map(|check| check.name.as_str()).collect();
+        assert_eq!(names, ["status", "max_latency"]);
+
+        let scenarios = args.scenarios(None).unwrap();
+        assert_eq!(scenarios[0].steps[0].checks.len(), 2);
+    }
+
+    fn control() -> LoadControl {
+        LoadControl::new(None, 1, Adjustable::Nothing)
+    }
+
+    #[test]
+    fn test_thresholds() {
+        let args = Args::parse_from([
+            "test",
+            "--threshold",
+            "p99<250ms",
+            "--threshold",
+            "error_rate < 0.5%",
+            "http://example.com",
+        ]);
+        let thresholds: Vec<_> = args.thresholds.iter().map(ToString::to_string).collect();
+        assert_eq!(thresholds, ["p99<250ms", "error_rate<0.5%"]);
+        let opts = args
+            .otlp_options(CancellationToken::new(), control())
+            .unwrap();
+        assert!(!opts.verdict.passed());
+
+        let args = Args::parse_from(["test", "http://example.com"]);
+        let opts = args
+            .otlp_options(CancellationToken::new(), control())
+            .unwrap();
+        assert!(opts.verdict.passed());
+
+        let err = Args::try_parse_from(["test", "--threshold", "p99<fast", "http://example.com"]);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_abort_on() {
+        let args = Args::parse_from([
+            "test",
+            "--abort-on",
+            "error_rate>20% for 10s",
+            "--abort-on",
+            "p95>2s",
+            "http://example.com",
+        ]);
+        let conditions: Vec<_> = args.abort_on.iter().map(ToString::to_string).collect();
+        assert_eq!(conditions, ["error_rate>20% for 10s", "p95>2s"]);
+
+        let err = Args::try_parse_from(["test", "--abort-on", "max>1s", "http://example.com"]);
+        assert!(err.is_err());
+    }
+
+    #[test]
+    fn test_percentiles() {
+        let args = Args::parse_from(["test", "http://example.com"]);
+        assert_eq!(args.percentiles, [50.0, 95.0, 99.0]);
+        assert_eq!(args.latency_precision, DEFAULT_PRECISION);
+        assert_eq!(args.group_by, [GroupBy::Method, GroupBy::Path]);
+        assert!(args.timeseries_csv.is_none());
+        assert!(args.control_addr.is_none());
+
+        let args = Args::parse_from([
+            "test",
+            "--percentiles",
+            "90,99.9,99.99",
+            "--latency-precision",
+            "4",
+            "http://example.com",
+        ]);
+        assert_eq!(args.percentiles, [90.0, 99.9, 99.99]);
+        assert_eq!(args.latency_precision, 4);
+
+        let args = Args::parse_from([
+            "test",
+            "--group-by",
+            "scenario,status",
+            "http://example.com",
+        ]);
+        assert_eq!(args.group_by, [GroupBy::Scenario, GroupBy::Status]);
+        assert!(
+            Args::try_parse_from(["test", "--group-by", "host", "http://example.com"]).is_err()
+        );
+
+        for (flag, value) in [("--percentiles", "50,101"), ("--latency-precision", "6")] {
+            assert!(Args::try_parse_from(["test", flag, value, "http://example.com"]).is_err());
+        }
+    }
+
+    #[test]
+    fn test_parse_replay_speed() {
+        assert_eq!(parse_replay_speed("max").unwrap(), ReplaySpeed::Max);
+        assert_eq!(parse_replay_speed("2").unwrap(), ReplaySpeed::Timed(2.0));
+        assert_eq!(parse_replay_speed("0.5").unwrap(), ReplaySpeed::Timed(0.5));
+        assert!(parse_replay_speed("0").is_err());
+        assert!(parse_replay_speed("fast").is_err());
+    }
+
+    #[test]
+    fn test_parse_wrapper_replay_conflicts() {
+        let conflicts = [
+            ["--scenario", "scenario.yaml"],
+            ["--data-file", "users.csv"],
+            ["--stage", "10s:10rps"],
+            ["--rps", "10"],
+        ];
+        for conflict in conflicts {
+            let args = ["test", "--replay", "access.log"]
+                .into_iter()
+                .chain(conflict)
+                .chain(["http://example.com"]);
+            assert!(Args::try_parse_from(args).is_err(), "{:?}", conflict);
+        }
+
+        let result = Args::try_parse_from(["test", "--replay-speed", "2", "http://example.com"]);
+        assert!(result.is_err());
+    }
+
     #[test]
     fn test_parse_key_value() {
         let input = "key:value";
@@ -412,7 +969,7 @@ mod tests {
         assert_eq!(args.resolve, Some(IpAddr::from_str("127.0.0.1").unwrap()));
         assert_eq!(args.rps, Some(50));
         assert_eq!(args.output_format, OutputFormat::Json);
-        assert_eq!(args.url.to_string(), "http://example.com/");
+        assert_eq!(args.url, "http://example.com");
     }
 
     #[test]
@@ -431,22 +988,19 @@ mod tests {
     }
 
     #[test]
-    fn test_parse_wrapper_invalid_rps() {
+    fn test_parse_wrapper_rps_less_than_clients() {
         let args = vec![
             "test",
             "--rps",
             "5",
             "--clients",
-            "10", // clients > rps should fail
+            "10", // the rate is shared by all clients, so this is fine
+            "--duration",
+            "10s",
             "http://example.com",
         ];
-        let err = Args::parse_wrapper_from(args);
-        assert!(err.is_err());
-        assert!(
-            err.unwrap_err()
-                .to_string()
-                .contains("RPS must be greater than or equal to the number of clients")
-        );
+        let args = Args::parse_wrapper_from(args).unwrap();
+        assert_eq!(args.rps, Some(5));
     }
 
     #[test]
@@ -486,6 +1040,91 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_parse_wrapper_h2_negotiation() {
+        let args = Args::parse_wrapper_from(["test", "http://example.com"]).unwrap();
+        assert_eq!(args.h2_negotiation, Negotiation::Auto);
+
+        for (mode, negotiation) in [
+            ("h2-alpn", Negotiation::H2Alpn),
+            ("h2c-prior-knowledge", Negotiation::H2cPriorKnowledge),
+            ("h2c-upgrade", Negotiation::H2cUpgrade),
+        ] {
+            let args = ["test", "--http-version", "2", "--h2-negotiation", mode];
+            let args = Args::parse_wrapper_from(args.into_iter().chain(["http://example.com"]));
+            assert_eq!(args.unwrap().request().negotiation, negotiation);
+        }
+
+        let err = Args::parse_wrapper_from([
+            "test",
+            "--h2-negotiation",
+            "h2c-upgrade",
+            "http://example.com",
+        ]);
+        assert!(
+            err.unwrap_err()
+                .to_string()
+                .contains("--h2-negotiation needs --http-version 2")
+        );
+    }
+
+    #[test]
+    fn test_parse_wrapper_connections() {
+        let args = Args::parse_wrapper_from([
+            "test",
+            "--http-version",
+            "2",
+            "--connections",
+            "2",
+            "--max-streams",
+            "50",
+            "--duration",
+            "10s",
+            "http://example.com",
+        ])
+        .unwrap();
+        assert_eq!(args.connections, Some(2));
+        assert_eq!(args.max_streams, Some(50));
+        let opts = args.bench_options().unwrap();
+        assert_eq!(opts.connections, Some(2));
+        assert_eq!(opts.max_streams, Some(50));
+
+        let err = Args::parse_wrapper_from(["test", "--connections", "2", "http://example.com"]);
+        assert!(
+            err.unwrap_err()
+                .to_string()
+                .contains("--connections and --max-streams need --http-version 2")
+        );
+        let args = ["test", "--max-streams", "0", "http://example.com"];
+        assert!(Args::try_parse_from(args).is_err());
+        let args = [
+            "test",
+            "--connections",
+            "2",
+            "--replay",
+            "a.log",
+            "http://example.com",
+        ];
+        assert!(Args::try_parse_from(args).is_err());
+    }
+
+    #[test]
+    fn test_parse_wrapper_constant_arrival_rate_requires_rps() {
+        let args = vec![
+            "test",
+            "--duration",
+            "10s",
+            "--executor",
+            "constant-arrival-rate",
+            "http://example.com",
+        ];
+        let err = Args::parse_wrapper_from(args);
+        assert!(err.is_err());
+        assert!(err.unwrap_err().to_string().contains(
+            "--rps or stages in rps are required for the constant-arrival-rate executor"
+        ));
+    }
+
     #[test]
     fn test_parse_wrapper_json_output_disables_interactive() {
         let args = vec![
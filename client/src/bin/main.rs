@@ -1,9 +1,14 @@
 mod args;
 
-use anyhow::Result;
+use anyhow::{Context, Result};
 use args::Args;
 use hb::bench;
+use hb::control::server::ControlServer;
 use hb::otlp::setup_metrics;
+use hb::reporter::formatter::OutputFormat;
+use hb::reporter::tui::Keyboard;
+use std::io::{IsTerminal, stdin};
+use std::process::ExitCode;
 use tokio::{
     runtime::Builder,
     signal::unix::{SignalKind, signal},
@@ -11,14 +16,22 @@ use tokio::{
 use tokio_util::sync::CancellationToken;
 use tracing::*;
 
-fn main() -> Result<()> {
+// Distinct from errors (1) and usage errors (2), so that CI can tell a slow run from a broken one
+const THRESHOLD_FAILURE_EXIT_CODE: u8 = 3;
+
+fn main() -> Result<ExitCode> {
     tracing_subscriber::fmt()
         .with_env_filter(tracing_subscriber::EnvFilter::from_default_env())
         .init();
 
-    let args = Args::parse_wrapper()?;
-    let req = args.request();
-    let opts = args.bench_options()?;
+    let mut args = Args::parse_wrapper()?;
+    let replay = args.load_replay()?;
+    let feeder = args.feeder()?;
+    let scenarios = args.scenarios(feeder.as_ref())?;
+    let opts = args
+        .bench_options()?
+        .with_feeder(feeder)?
+        .with_replay(replay)?;
 
     let mut builder = Builder::new_multi_thread();
     if let Some(threads) = args.thread {
@@ -26,17 +39,39 @@ fn main() -> Result<()> {
     }
     let runtime = builder.enable_all().build()?;
 
-    runtime.block_on(async move {
-        let otlp_opts = args.otlp_options();
-        let (provider, metrics) = setup_metrics(otlp_opts, &req).await?;
-
+    let control = opts.control.clone();
+    let passed = runtime.block_on(async move {
         let cxl = CancellationToken::new();
+        let otlp_opts = args.otlp_options(cxl.clone(), control.clone())?;
+        let verdict = otlp_opts.verdict.clone();
+        let abort = otlp_opts.abort.clone();
+        let timeseries = otlp_opts.timeseries.clone();
+        let timeseries_csv = args.timeseries_csv.clone();
+        let (provider, metrics) = setup_metrics(otlp_opts).await?;
+
         let main_cxl: CancellationToken = cxl.child_token();
 
+        // Bound before the run so that a taken port fails it right away
+        if let Some(addr) = args.control_addr {
+            let server = ControlServer::bind(addr, control.clone()).await?;
+            info!("Listening for load changes on {}", server.local_addr()?);
+            tokio::spawn(server.serve(main_cxl.clone()));
+        }
+
+        // Keys are read from a terminal only, the screen is drawn either way
+        let keyboard = match args.output_format {
+            OutputFormat::Text
+                if !args.no_interactive && !args.no_clear_console && stdin().is_terminal() =>
+            {
+                Some(Keyboard::start(control, cxl.clone())?)
+            }
+            _ => None,
+        };
+
         let main_task = tokio::spawn(async move {
             let bench_cxl = main_cxl.clone();
 
-            bench::bench(bench_cxl, req, opts, metrics).await?;
+            bench::bench(bench_cxl, scenarios, opts, metrics).await?;
             main_cxl.cancel();
             Ok::<_, anyhow::Error>(())
         });
@@ -58,12 +93,26 @@ fn main() -> Result<()> {
             Ok::<_, anyhow::Error>(())
         });
 
-        main_task.await??;
+        let result = main_task.await;
+        // Back to a normal terminal for the final report
+        drop(keyboard);
+        result??;
 
         provider.shutdown()?;
 
-        Ok::<_, anyhow::Error>(())
+        if let Some(path) = timeseries_csv {
+            timeseries
+                .write_csv(&path)
+                .context("Failed to write the timeseries")?;
+        }
+
+        // An aborted run fails like a threshold that does not hold
+        Ok::<_, anyhow::Error>(verdict.passed() && abort.reason().is_none())
     })?;
 
-    Ok(())
+    if passed {
+        Ok(ExitCode::SUCCESS)
+    } else {
+        Ok(ExitCode::from(THRESHOLD_FAILURE_EXIT_CODE))
+    }
 }
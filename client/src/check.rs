@@ -0,0 +1,327 @@
+use crate::http::Response;
+use crate::time::deserialize_duration;
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use serde_json_path::JsonPath;
+use std::cell::OnceCell;
+use std::time::Duration;
+
+/// A check on every response, exactly one kind of assertion is set
+///
+/// ```yaml
+/// checks:
+///   - status: [200, 201]
+///   - name: no-error
+///     json: $.error
+///     exists: false
+///   - max_latency: 500ms
+/// ```
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct CheckSpec {
+    /// Name of the check in the report, derived from the assertion when not set
+    pub name: Option<String>,
+    /// Expected status codes
+    pub status: Option<Vec<u16>>,
+    /// Text the body must contain
+    pub body_contains: Option<String>,
+    /// Regex the body must match
+    pub body_regex: Option<String>,
+    /// JSONPath into the body, compared with `equals` or tested with `exists`
+    pub json: Option<String>,
+    #[serde(default, deserialize_with = "deserialize_some")]
+    pub equals: Option<serde_json::Value>,
+    pub exists: Option<bool>,
+    /// Name of a header the response must have
+    pub header: Option<String>,
+    /// Maximum size of the body in bytes
+    pub max_body_size: Option<u64>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    pub max_latency: Option<Duration>,
+}
+
+// Keeps an explicit `null` apart from a missing field
+fn deserialize_some<'de, D>(deserializer: D) -> Result<Option<serde_json::Value>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    serde_json::Value::deserialize(deserializer).map(Some)
+}
+
+// The kind of assertion set in a spec, before it is compiled
+enum Kind {
+    Status(Vec<u16>),
+    BodyContains(String),
+    BodyRegex(String),
+    Json(String),
+    Header(String),
+    MaxBodySize(u64),
+    MaxLatency(Duration),
+}
+
+#[derive(Debug, Clone)]
+enum Assertion {
+    Status(Vec<u16>),
+    BodyContains(String),
+    BodyRegex(Regex),
+    JsonEquals(JsonPath, serde_json::Value),
+    JsonExists(JsonPath, bool),
+    Header(String),
+    MaxBodySize(u64),
+    MaxLatency(Duration),
+}
+
+/// A named assertion on a response, a failure is counted but does not stop the run
+#[derive(Debug, Clone)]
+pub struct Check {
+    pub name: String,
+    assertion: Assertion,
+}
+
+impl Check {
+    pub fn try_new(spec: CheckSpec) -> Result<Self> {
+        let CheckSpec {
+            name,
+            status,
+            body_contains,
+            body_regex,
+            json,
+            equals,
+            exists,
+            header,
+            max_body_size,
+            max_latency,
+        } = spec;
+
+        let mut kinds: Vec<Kind> = [
+            status.map(Kind::Status),
+            body_contains.map(Kind::BodyContains),
+            body_regex.map(Kind::BodyRegex),
+            json.map(Kind::Json),
+            header.map(Kind::Header),
+            max_body_size.map(Kind::MaxBodySize),
+            max_latency.map(Kind::MaxLatency),
+        ]
+        .into_iter()
+        .flatten()
+        .collect();
+        let (Some(kind), true) = (kinds.pop(), kinds.is_empty()) else {
+            bail!(
+                "A check must have exactly one of status, body_contains, body_regex, json, header, max_body_size or max_latency"
+            );
+        };
+        if !matches!(kind, Kind::Json(_)) && (equals.is_some() || exists.is_some()) {
+            bail!("equals and exists can only be used with json");
+        }
+
+        let (default_name, assertion) = match kind {
+            Kind::Status(codes) => {
+                if codes.is_empty() {
+                    bail!("At least one status code must be expected");
+                }
+                ("status".to_string(), Assertion::Status(codes))
+            }
+            Kind::BodyContains(text) => {
+                ("body_contains".to_string(), Assertion::BodyContains(text))
+            }
+            Kind::BodyRegex(re) => {
+                let re = Regex::new(&re).with_context(|| format!("Invalid regex '{}'", re))?;
+                ("body_regex".to_string(), Assertion::BodyRegex(re))
+            }
+            Kind::Json(path) => {
+                let parsed = JsonPath::parse(&path)
+                    .with_context(|| format!("Invalid JSONPath '{}'", path))?;
+                let assertion = match (equals, exists) {
+                    (Some(value), None) => Assertion::JsonEquals(parsed, value),
+                    (None, Some(exists)) => Assertion::JsonExists(parsed, exists),
+                    _ => bail!("json must be used with either equals or exists"),
+                };
+                (format!("json {}", path), assertion)
+            }
+            Kind::Header(name) => (format!("header {}", name), Assertion::Header(name)),
+            Kind::MaxBodySize(size) => ("max_body_size".to_string(), Assertion::MaxBodySize(size)),
+            Kind::MaxLatency(latency) => {
+                ("max_latency".to_string(), Assertion::MaxLatency(latency))
+            }
+        };
+
+        Ok(Self {
+            name: name.unwrap_or(default_name),
+            assertion,
+        })
+    }
+
+    /// Expect the status to be one of `codes`
+    pub fn status(codes: Vec<u16>) -> Result<Self> {
+        Self::try_new(CheckSpec {
+            status: Some(codes),
+            ..Default::default()
+        })
+    }
+
+    /// Expect the response within `latency`
+    pub fn max_latency(latency: Duration) -> Result<Self> {
+        Self::try_new(CheckSpec {
+            max_latency: Some(latency),
+            ..Default::default()
+        })
+    }
+
+    pub(crate) fn passes(&self, checked: &Checked) -> bool {
+        let resp = checked.resp;
+        match &self.assertion {
+            Assertion::Status(codes) => codes.contains(&resp.status),
+            Assertion::BodyContains(text) => String::from_utf8_lossy(&resp.body).contains(text),
+            Assertion::BodyRegex(re) => re.is_match(&String::from_utf8_lossy(&resp.body)),
+            Assertion::JsonEquals(path, expected) => checked
+                .json()
+                .is_some_and(|body| path.query(body).first() == Some(expected)),
+            Assertion::JsonExists(path, exists) => checked
+                .json()
+                .is_some_and(|body| path.query(body).first().is_some() == *exists),
+            Assertion::Header(name) => resp.headers.contains_key(name),
+            Assertion::MaxBodySize(size) => resp.body.len() as u64 <= *size,
+            Assertion::MaxLatency(latency) => checked.elapsed <= *latency,
+        }
+    }
+}
+
+/// A response as the checks see it, its body parsed as JSON at most once for all of them
+pub(crate) struct Checked<'a> {
+    resp: &'a Response,
+    elapsed: Duration,
+    json: OnceCell<Option<serde_json::Value>>,
+}
+
+impl<'a> Checked<'a> {
+    /// `elapsed` is the latency of the response as recorded
+    pub(crate) fn new(resp: &'a Response, elapsed: Duration) -> Self {
+        Self {
+            resp,
+            elapsed,
+            json: OnceCell::new(),
+        }
+    }
+
+    // A body that is not JSON fails the JSON checks
+    fn json(&self) -> Option<&serde_json::Value> {
+        self.json
+            .get_or_init(|| serde_json::from_slice(&self.resp.body).ok())
+            .as_ref()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    fn response(status: u16, body: &str) -> Response {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-request-id", HeaderValue::from_static("r-1"));
+        Response {
+            status,
+            size: 0,
+            version: reqwest::Version::HTTP_11,
+            timing: Default::default(),
+            headers,
+            body: body.to_string().into(),
+        }
+    }
+
+    fn check(yaml: &str) -> Check {
+        Check::try_new(serde_norway::from_str(yaml).unwrap()).unwrap()
+    }
+
+    fn passes(check: &Check, resp: &Response) -> bool {
+        check.passes(&Checked::new(resp, Duration::from_millis(100)))
+    }
+
+    #[test]
+    fn test_check_status() {
+        let check = check("status: [200, 201]");
+        assert_eq!(check.name, "status");
+        assert!(passes(&check, &response(201, "")));
+        assert!(!passes(&check, &response(500, "")));
+    }
+
+    #[test]
+    fn test_check_body() {
+        let contains = check("{name: has-ok, body_contains: ok}");
+        assert_eq!(contains.name, "has-ok");
+        assert!(passes(&contains, &response(200, "it is ok")));
+        assert!(!passes(&contains, &response(200, "failed")));
+
+        let regex = check(r#"body_regex: 'id=\d+'"#);
+        assert!(passes(&regex, &response(200, "id=42")));
+        assert!(!passes(&regex, &response(200, "id=")));
+
+        let size = check("max_body_size: 4");
+        assert!(passes(&size, &response(200, "1234")));
+        assert!(!passes(&size, &response(200, "12345")));
+    }
+
+    #[test]
+    fn test_check_json() {
+        let no_error = check("{json: $.error, exists: false}");
+        assert_eq!(no_error.name, "json $.error");
+        assert!(passes(&no_error, &response(200, r#"{"data": 1}"#)));
+        assert!(!passes(&no_error, &response(200, r#"{"error": "boom"}"#)));
+        assert!(!passes(&no_error, &response(200, "not json")));
+
+        let equals = check("{json: $.status, equals: active}");
+        assert!(passes(&equals, &response(200, r#"{"status": "active"}"#)));
+        assert!(!passes(&equals, &response(200, r#"{"status": "closed"}"#)));
+
+        let null = check("{json: $.error, equals: null}");
+        assert!(passes(&null, &response(200, r#"{"error": null}"#)));
+        assert!(!passes(&null, &response(200, r#"{}"#)));
+
+        // The body is parsed once and shared by the checks of the response
+        let resp = response(200, r#"{"status": "active"}"#);
+        let checked = Checked::new(&resp, Duration::ZERO);
+        assert!(checked.json.get().is_none());
+        assert!(equals.passes(&checked));
+        assert!(no_error.passes(&checked));
+        assert_eq!(
+            checked.json.get(),
+            Some(&Some(serde_json::json!({"status": "active"})))
+        );
+    }
+
+    #[test]
+    fn test_check_header_and_latency() {
+        let header = check("header: x-request-id");
+        assert_eq!(header.name, "header x-request-id");
+        assert!(passes(&header, &response(200, "")));
+        assert!(!passes(&check("header: x-missing"), &response(200, "")));
+
+        let latency = Check::max_latency(Duration::from_millis(50)).unwrap();
+        let resp = response(200, "");
+        assert!(latency.passes(&Checked::new(&resp, Duration::from_millis(50))));
+        assert!(!latency.passes(&Checked::new(&resp, Duration::from_millis(51))));
+    }
+
+    #[test]
+    fn test_check_invalid() {
+        let specs = [
+            "{}",
+            "{status: [200], body_contains: ok}",
+            "status: []",
+            "body_regex: '('",
+            "json: $.error",
+            "{json: $.error, equals: 1, exists: true}",
+            "{json: error, exists: true}",
+            "{header: x-id, exists: true}",
+            "max_latency: fast",
+        ];
+
+        for spec in specs {
+            let result = serde_norway::from_str::<CheckSpec>(spec)
+                .map_err(anyhow::Error::from)
+                .and_then(Check::try_new);
+            assert!(result.is_err(), "{}", spec);
+        }
+    }
+}
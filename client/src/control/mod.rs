@@ -0,0 +1,320 @@
+pub mod server;
+
+use crate::time::now_ts;
+use anyhow::{Result, bail};
+use serde::Serialize;
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use tokio::sync::watch;
+
+/// What the load of a run can be changed by while it is in progress
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Adjustable {
+    /// The constant rate of --rps
+    Rps,
+    /// The number of clients of a closed-loop run by duration
+    Clients,
+    /// Neither, e.g. the rate follows stages or the clients split a number of requests
+    Nothing,
+}
+
+/// Load targets of the run at one point in time
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+pub struct LoadState {
+    pub paused: bool,
+    pub rps: Option<u64>,
+    pub clients: usize,
+}
+
+/// What a change of the load came from
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Source {
+    Keyboard,
+    /// The HTTP control endpoint of --control-addr
+    Endpoint,
+}
+
+impl fmt::Display for Source {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Source::Keyboard => write!(f, "keyboard"),
+            Source::Endpoint => write!(f, "endpoint"),
+        }
+    }
+}
+
+/// One change of the load
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(tag = "event", rename_all = "lowercase")]
+pub enum LoadChange {
+    Pause,
+    Resume,
+    Rps { from: Option<u64>, to: u64 },
+    Clients { from: usize, to: usize },
+}
+
+impl fmt::Display for LoadChange {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            LoadChange::Pause => write!(f, "paused"),
+            LoadChange::Resume => write!(f, "resumed"),
+            LoadChange::Rps {
+                from: Some(from),
+                to,
+            } => write!(f, "rps {} -> {}", from, to),
+            LoadChange::Rps { from: None, to } => write!(f, "rps -> {}", to),
+            LoadChange::Clients { from, to } => write!(f, "clients {} -> {}", from, to),
+        }
+    }
+}
+
+/// A change of the load at the time it was made
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoadEvent {
+    pub ts: f64,
+    pub change: LoadChange,
+    pub source: Source,
+}
+
+/// Pauses the run and changes its load while it is in progress, shared by the clients
+/// and whatever drives the changes, e.g. the keyboard of the interactive screen
+#[derive(Debug, Clone)]
+pub struct LoadControl {
+    state: watch::Sender<LoadState>,
+    adjustable: Adjustable,
+    events: Arc<Mutex<Vec<LoadEvent>>>,
+}
+
+impl LoadControl {
+    // Share of the current target added or removed by a step, at least one
+    const STEP_RATIO: f64 = 0.1;
+
+    pub fn new(rps: Option<u64>, clients: usize, adjustable: Adjustable) -> Self {
+        let (state, _) = watch::channel(LoadState {
+            paused: false,
+            rps,
+            clients,
+        });
+        Self {
+            state,
+            adjustable,
+            events: Arc::default(),
+        }
+    }
+
+    pub fn state(&self) -> LoadState {
+        *self.state.borrow()
+    }
+
+    pub fn adjustable(&self) -> Adjustable {
+        self.adjustable
+    }
+
+    /// Every change so far, the oldest first
+    pub fn events(&self) -> Vec<LoadEvent> {
+        self.events.lock().unwrap().clone()
+    }
+
+    fn record(&self, change: LoadChange, source: Source) {
+        self.events.lock().unwrap().push(LoadEvent {
+            ts: now_ts(),
+            change,
+            source,
+        });
+    }
+
+    /// Pause or resume the run, returns whether it is paused now
+    pub fn toggle_pause(&self, source: Source) -> bool {
+        let paused = !self.state().paused;
+        self.set_paused(paused, source);
+        paused
+    }
+
+    pub fn set_paused(&self, paused: bool, source: Source) {
+        let changed = self.state.send_if_modified(|state| {
+            let changed = state.paused != paused;
+            state.paused = paused;
+            changed
+        });
+        if changed {
+            let change = if paused {
+                LoadChange::Pause
+            } else {
+                LoadChange::Resume
+            };
+            self.record(change, source);
+        }
+    }
+
+    /// Change the rate of the run, only when it has a constant one
+    pub fn set_rps(&self, rps: u64, source: Source) -> Result<()> {
+        self.check_rps(rps)?;
+        let from = self.state.borrow().rps;
+        if from != Some(rps) {
+            self.state.send_modify(|state| state.rps = Some(rps));
+            self.record(LoadChange::Rps { from, to: rps }, source);
+        }
+        Ok(())
+    }
+
+    /// Change the number of clients, only for closed-loop runs by duration
+    pub fn set_clients(&self, clients: usize, source: Source) -> Result<()> {
+        self.check_clients(clients)?;
+        let from = self.state.borrow().clients;
+        if from != clients {
+            self.state.send_modify(|state| state.clients = clients);
+            self.record(LoadChange::Clients { from, to: clients }, source);
+        }
+        Ok(())
+    }
+
+    /// Whether `set_rps` would accept `rps`
+    pub(crate) fn check_rps(&self, rps: u64) -> Result<()> {
+        if self.adjustable != Adjustable::Rps {
+            bail!("The rate of this run cannot be changed");
+        }
+        if rps == 0 {
+            bail!("RPS must be greater than zero");
+        }
+        Ok(())
+    }
+
+    /// Whether `set_clients` would accept `clients`
+    pub(crate) fn check_clients(&self, clients: usize) -> Result<()> {
+        if self.adjustable != Adjustable::Clients {
+            bail!("The number of clients of this run cannot be changed");
+        }
+        if clients == 0 {
+            bail!("Number of clients must be greater than zero");
+        }
+        Ok(())
+    }
+
+    /// Raise whatever the run can adjust by a step
+    pub fn increase(&self, source: Source) -> Result<()> {
+        self.step(source, |target, step| target.saturating_add(step))
+    }
+
+    /// Lower whatever the run can adjust by a step, never to zero
+    pub fn decrease(&self, source: Source) -> Result<()> {
+        self.step(source, |target, step| target.saturating_sub(step).max(1))
+    }
+
+    fn step(&self, source: Source, apply: impl Fn(u64, u64) -> u64) -> Result<()> {
+        let step = |target: u64| ((target as f64 * Self::STEP_RATIO).round() as u64).max(1);
+        let state = self.state();
+        match self.adjustable {
+            Adjustable::Rps => {
+                let rps = state.rps.unwrap_or(1);
+                self.set_rps(apply(rps, step(rps)), source)
+            }
+            Adjustable::Clients => {
+                let clients = state.clients as u64;
+                self.set_clients(apply(clients, step(clients)) as usize, source)
+            }
+            Adjustable::Nothing => bail!("The load of this run cannot be changed"),
+        }
+    }
+
+    /// Whether the client with `index` may send now
+    pub fn is_active(&self, index: usize) -> bool {
+        let state = self.state.borrow();
+        !state.paused && index < state.clients
+    }
+
+    /// Wait until the client with `index` may send
+    pub async fn active(&self, index: usize) {
+        let mut receiver = self.state.subscribe();
+        // The sender lives as long as `self`, so the wait does not fail
+        let _ = receiver
+            .wait_for(|state| !state.paused && index < state.clients)
+            .await;
+    }
+
+    /// Receives every change of the state
+    pub fn subscribe(&self) -> watch::Receiver<LoadState> {
+        self.state.subscribe()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[test]
+    fn test_load_control_steps() {
+        let control = LoadControl::new(Some(100), 10, Adjustable::Rps);
+        control.increase(Source::Keyboard).unwrap();
+        assert_eq!(control.state().rps, Some(110));
+        control.decrease(Source::Keyboard).unwrap();
+        assert_eq!(control.state().rps, Some(99));
+        assert!(control.set_clients(20, Source::Endpoint).is_err());
+        assert!(control.set_rps(0, Source::Endpoint).is_err());
+
+        let control = LoadControl::new(None, 2, Adjustable::Clients);
+        control.increase(Source::Keyboard).unwrap();
+        assert_eq!(control.state().clients, 3);
+        for _ in 0..5 {
+            control.decrease(Source::Keyboard).unwrap();
+        }
+        assert_eq!(control.state().clients, 1);
+        assert!(control.set_rps(100, Source::Endpoint).is_err());
+
+        let control = LoadControl::new(None, 2, Adjustable::Nothing);
+        assert!(control.increase(Source::Keyboard).is_err());
+        assert_eq!(control.state().clients, 2);
+    }
+
+    #[test]
+    fn test_load_control_events() {
+        let control = LoadControl::new(Some(100), 10, Adjustable::Rps);
+        control.set_rps(200, Source::Endpoint).unwrap();
+        // Unchanged targets are no events
+        control.set_rps(200, Source::Endpoint).unwrap();
+        control.set_paused(false, Source::Endpoint);
+        control.toggle_pause(Source::Keyboard);
+        assert!(control.set_rps(0, Source::Endpoint).is_err());
+
+        let events = control.events();
+        assert_eq!(events.len(), 2);
+        assert_eq!(
+            events[0].change,
+            LoadChange::Rps {
+                from: Some(100),
+                to: 200
+            }
+        );
+        assert_eq!(events[0].source, Source::Endpoint);
+        assert_eq!(events[0].change.to_string(), "rps 100 -> 200");
+        assert_eq!(events[1].change, LoadChange::Pause);
+        assert_eq!(events[1].source, Source::Keyboard);
+        assert_eq!(
+            serde_json::to_value(events[0].change).unwrap(),
+            serde_json::json!({"event": "rps", "from": 100, "to": 200})
+        );
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_load_control_pause() {
+        let control = LoadControl::new(None, 2, Adjustable::Clients);
+        assert!(control.is_active(1));
+        assert!(!control.is_active(2));
+
+        assert!(control.toggle_pause(Source::Keyboard));
+        assert!(!control.is_active(0));
+
+        let waiter = {
+            let control = control.clone();
+            tokio::spawn(async move { control.active(0).await })
+        };
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        assert!(!waiter.is_finished());
+
+        assert!(!control.toggle_pause(Source::Keyboard));
+        waiter.await.unwrap();
+        assert!(control.is_active(0));
+    }
+}
@@ -0,0 +1,217 @@
+use crate::control::{Adjustable, LoadControl, LoadState, Source};
+use anyhow::{Context, Result};
+use http_body_util::{BodyExt, Full};
+use hyper::body::{Bytes, Incoming};
+use hyper::server::conn::http1;
+use hyper::service::service_fn;
+use hyper::{Method, Request, Response, StatusCode, header};
+use hyper_util::rt::TokioIo;
+use serde::{Deserialize, Serialize};
+use std::convert::Infallible;
+use std::net::SocketAddr;
+use tokio::net::TcpListener;
+use tokio_util::sync::CancellationToken;
+use tracing::*;
+
+/// Load of the run as served by the endpoint
+#[derive(Debug, Serialize)]
+struct LoadResponse {
+    #[serde(flatten)]
+    state: LoadState,
+    adjustable: Adjustable,
+}
+
+/// Targets to change, the missing ones are left as they are
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct LoadRequest {
+    paused: Option<bool>,
+    rps: Option<u64>,
+    clients: Option<usize>,
+}
+
+/// A local HTTP endpoint changing the load of the run while it is in progress.
+/// `GET /load` returns the current targets and `PUT /load` changes them, e.g. `{"rps": 200}`.
+#[derive(Debug)]
+pub struct ControlServer {
+    listener: TcpListener,
+    control: LoadControl,
+}
+
+impl ControlServer {
+    pub async fn bind(addr: SocketAddr, control: LoadControl) -> Result<Self> {
+        let listener = TcpListener::bind(addr)
+            .await
+            .with_context(|| format!("Failed to listen for load changes on {}", addr))?;
+        Ok(Self { listener, control })
+    }
+
+    pub fn local_addr(&self) -> Result<SocketAddr> {
+        Ok(self.listener.local_addr()?)
+    }
+
+    /// Accept connections until `cxl` is cancelled
+    pub async fn serve(self, cxl: CancellationToken) {
+        loop {
+            let stream = tokio::select! {
+                _ = cxl.cancelled() => break,
+                accepted = self.listener.accept() => match accepted {
+                    Ok((stream, _)) => stream,
+                    Err(err) => {
+                        debug!("Failed to accept a control connection: {}", err);
+                        continue;
+                    }
+                },
+            };
+
+            let control = self.control.clone();
+            tokio::spawn(async move {
+                let service = service_fn(move |req| {
+                    let control = control.clone();
+                    async move { Ok::<_, Infallible>(handle_request(req, &control).await) }
+                });
+                if let Err(err) = http1::Builder::new()
+                    .serve_connection(TokioIo::new(stream), service)
+                    .await
+                {
+                    debug!("Error serving a control connection: {}", err);
+                }
+            });
+        }
+    }
+}
+
+async fn handle_request(req: Request<Incoming>, control: &LoadControl) -> Response<Full<Bytes>> {
+    let result = match (req.method(), req.uri().path()) {
+        (&Method::GET, "/load") => Ok(load_response(control)),
+        (&Method::PUT, "/load") => change_load(req, control).await,
+        (_, "/load") => {
+            return response(
+                StatusCode::METHOD_NOT_ALLOWED,
+                error_body("Use GET or PUT".to_string()),
+            );
+        }
+        _ => return response(StatusCode::NOT_FOUND, error_body("Not found".to_string())),
+    };
+    match result {
+        Ok(body) => response(StatusCode::OK, body),
+        Err(err) => response(StatusCode::BAD_REQUEST, error_body(format!("{:#}", err))),
+    }
+}
+
+async fn change_load(req: Request<Incoming>, control: &LoadControl) -> Result<String> {
+    let body = req.into_body().collect().await?.to_bytes();
+    let request: LoadRequest =
+        serde_json::from_slice(&body).context("Invalid body, e.g. {\"rps\": 200}")?;
+
+    // Checked up front so that a request changes either everything or nothing
+    if let Some(rps) = request.rps {
+        control.check_rps(rps)?;
+    }
+    if let Some(clients) = request.clients {
+        control.check_clients(clients)?;
+    }
+    if let Some(rps) = request.rps {
+        control.set_rps(rps, Source::Endpoint)?;
+    }
+    if let Some(clients) = request.clients {
+        control.set_clients(clients, Source::Endpoint)?;
+    }
+    if let Some(paused) = request.paused {
+        control.set_paused(paused, Source::Endpoint);
+    }
+    Ok(load_response(control))
+}
+
+fn load_response(control: &LoadControl) -> String {
+    let load = LoadResponse {
+        state: control.state(),
+        adjustable: control.adjustable(),
+    };
+    serde_json::to_string(&load).unwrap_or_default()
+}
+
+fn error_body(message: String) -> String {
+    serde_json::json!({ "error": message }).to_string()
+}
+
+fn response(status: StatusCode, body: String) -> Response<Full<Bytes>> {
+    let mut response = Response::new(Full::new(Bytes::from(body)));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        header::HeaderValue::from_static("application/json"),
+    );
+    response
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::control::LoadChange;
+    use serde_json::{Value, json};
+
+    #[tokio::test]
+    async fn test_control_server() {
+        let control = LoadControl::new(Some(100), 10, Adjustable::Rps);
+        let server = ControlServer::bind("127.0.0.1:0".parse().unwrap(), control.clone())
+            .await
+            .unwrap();
+        let url = format!("http://{}/load", server.local_addr().unwrap());
+        let cxl = CancellationToken::new();
+        let handle = tokio::spawn(server.serve(cxl.clone()));
+
+        let client = reqwest::Client::new();
+        let load: Value = client.get(&url).send().await.unwrap().json().await.unwrap();
+        assert_eq!(
+            load,
+            json!({"paused": false, "rps": 100, "clients": 10, "adjustable": "rps"})
+        );
+
+        let res = client
+            .put(&url)
+            .json(&json!({"rps": 250, "paused": true}))
+            .send()
+            .await
+            .unwrap();
+        assert_eq!(res.status(), StatusCode::OK);
+        let load: Value = res.json().await.unwrap();
+        assert_eq!(load["rps"], 250);
+        assert_eq!(load["paused"], true);
+
+        // Nothing changes when any of the targets cannot
+        for body in [
+            json!({"rps": 300, "clients": 20}),
+            json!({"rps": 0}),
+            json!({"qps": 1}),
+        ] {
+            let res = client.put(&url).json(&body).send().await.unwrap();
+            assert_eq!(res.status(), StatusCode::BAD_REQUEST);
+        }
+        assert_eq!(control.state().rps, Some(250));
+
+        let res = client.delete(&url).send().await.unwrap();
+        assert_eq!(res.status(), StatusCode::METHOD_NOT_ALLOWED);
+
+        let events: Vec<_> = control.events().iter().map(|e| e.change).collect();
+        assert_eq!(
+            events,
+            [
+                LoadChange::Rps {
+                    from: Some(100),
+                    to: 250
+                },
+                LoadChange::Pause
+            ]
+        );
+        assert!(
+            control
+                .events()
+                .iter()
+                .all(|e| e.source == Source::Endpoint)
+        );
+
+        cxl.cancel();
+        handle.await.unwrap();
+    }
+}
@@ -0,0 +1,93 @@
+use crate::time::now_ts;
+use std::collections::{BTreeMap, VecDeque};
+use std::sync::{Arc, Mutex};
+
+/// One failed request of the log
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoggedError {
+    pub ts: f64,
+    /// Method and path of the request
+    pub endpoint: String,
+    /// What went wrong, e.g. connection_refused
+    pub category: String,
+    pub message: String,
+}
+
+/// The latest errors of the run, the older ones are dropped once it is full. The first message
+/// of every category is kept for the whole run.
+#[derive(Debug, Clone)]
+pub struct ErrorLog {
+    errors: Arc<Mutex<VecDeque<LoggedError>>>,
+    samples: Arc<Mutex<BTreeMap<String, String>>>,
+    capacity: usize,
+}
+
+impl ErrorLog {
+    pub const DEFAULT_CAPACITY: usize = 100;
+
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            errors: Arc::new(Mutex::new(VecDeque::with_capacity(capacity))),
+            samples: Arc::default(),
+            capacity,
+        }
+    }
+
+    pub fn push(&self, endpoint: String, category: &str, message: String) {
+        self.samples
+            .lock()
+            .unwrap()
+            .entry(category.to_string())
+            .or_insert_with(|| message.clone());
+
+        let mut errors = self.errors.lock().unwrap();
+        if errors.len() == self.capacity {
+            errors.pop_front();
+        }
+        errors.push_back(LoggedError {
+            ts: now_ts(),
+            endpoint,
+            category: category.to_string(),
+            message,
+        });
+    }
+
+    /// Up to `count` of the latest errors, the latest first
+    pub fn latest(&self, count: usize) -> Vec<LoggedError> {
+        let errors = self.errors.lock().unwrap();
+        errors.iter().rev().take(count).cloned().collect()
+    }
+
+    /// Message of the first error of `category`
+    pub fn sample(&self, category: &str) -> Option<String> {
+        self.samples.lock().unwrap().get(category).cloned()
+    }
+}
+
+impl Default for ErrorLog {
+    fn default() -> Self {
+        Self::new(Self::DEFAULT_CAPACITY)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_error_log() {
+        let log = ErrorLog::new(2);
+        for n in 0..3 {
+            log.push("GET /".to_string(), "connect", format!("error {}", n));
+        }
+
+        let latest = log.latest(5);
+        assert_eq!(latest.len(), 2);
+        assert_eq!(latest[0].message, "error 2");
+        assert_eq!(latest[1].message, "error 1");
+        assert_eq!(log.latest(1).len(), 1);
+        // Kept after the error itself was dropped
+        assert_eq!(log.sample("connect").as_deref(), Some("error 0"));
+        assert_eq!(log.sample("timeout"), None);
+    }
+}
@@ -0,0 +1,161 @@
+use crate::http::Response;
+use anyhow::{Context, Result, bail};
+use regex::Regex;
+use serde::Deserialize;
+use serde_json_path::JsonPath;
+
+/// Where a value is captured from in a response, exactly one of the fields is set
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ExtractorSpec {
+    /// JSONPath into the response body, e.g. `$.data.token`
+    pub json: Option<String>,
+    /// Name of a response header
+    pub header: Option<String>,
+    /// Regex over the response body, the first capture group is used when present
+    pub regex: Option<String>,
+}
+
+#[derive(Debug, Clone)]
+enum Extractor {
+    Json(JsonPath),
+    Header(String),
+    Regex(Regex),
+}
+
+/// Captures a value from a response into a journey variable
+#[derive(Debug, Clone)]
+pub struct Extraction {
+    pub var: String,
+    extractor: Extractor,
+}
+
+impl Extraction {
+    pub fn try_new(var: String, spec: ExtractorSpec) -> Result<Self> {
+        let extractor = match (spec.json, spec.header, spec.regex) {
+            (Some(path), None, None) => Extractor::Json(
+                JsonPath::parse(&path).with_context(|| format!("Invalid JSONPath '{}'", path))?,
+            ),
+            (None, Some(name), None) => Extractor::Header(name),
+            (None, None, Some(re)) => Extractor::Regex(
+                Regex::new(&re).with_context(|| format!("Invalid regex '{}'", re))?,
+            ),
+            _ => bail!(
+                "'{}' must be extracted by one of json, header or regex",
+                var
+            ),
+        };
+
+        Ok(Self { var, extractor })
+    }
+
+    pub(crate) fn extract(&self, resp: &Response) -> Result<String> {
+        let value = match &self.extractor {
+            Extractor::Json(path) => {
+                let body: serde_json::Value =
+                    serde_json::from_slice(&resp.body).context("Response body is not JSON")?;
+                path.query(&body).first().map(|value| match value {
+                    serde_json::Value::String(s) => s.clone(),
+                    value => value.to_string(),
+                })
+            }
+            Extractor::Header(name) => resp
+                .headers
+                .get(name)
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string),
+            Extractor::Regex(re) => {
+                let body = String::from_utf8_lossy(&resp.body);
+                re.captures(&body).and_then(|caps| {
+                    caps.get(1)
+                        .or_else(|| caps.get(0))
+                        .map(|m| m.as_str().to_string())
+                })
+            }
+        };
+
+        match value {
+            Some(value) => Ok(value),
+            None => bail!("No value found for '{}'", self.var),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::header::{HeaderMap, HeaderValue};
+
+    fn response(body: &str) -> Response {
+        let mut headers = HeaderMap::new();
+        headers.insert("x-session", HeaderValue::from_static("s-1"));
+        Response {
+            status: 200,
+            size: 0,
+            version: reqwest::Version::HTTP_11,
+            timing: Default::default(),
+            headers,
+            body: body.to_string().into(),
+        }
+    }
+
+    fn json(path: &str) -> ExtractorSpec {
+        ExtractorSpec {
+            json: Some(path.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn header(name: &str) -> ExtractorSpec {
+        ExtractorSpec {
+            header: Some(name.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn regex(re: &str) -> ExtractorSpec {
+        ExtractorSpec {
+            regex: Some(re.to_string()),
+            ..Default::default()
+        }
+    }
+
+    fn extract(spec: ExtractorSpec, body: &str) -> Result<String> {
+        Extraction::try_new("var".to_string(), spec)?.extract(&response(body))
+    }
+
+    #[test]
+    fn test_extract_json() {
+        let body = r#"{"token": "abc", "user": {"id": 42}}"#;
+        assert_eq!(extract(json("$.token"), body).unwrap(), "abc");
+        assert_eq!(extract(json("$.user.id"), body).unwrap(), "42");
+        assert!(extract(json("$.missing"), body).is_err());
+        assert!(extract(json("$.token"), "not json").is_err());
+    }
+
+    #[test]
+    fn test_extract_header() {
+        assert_eq!(extract(header("x-session"), "").unwrap(), "s-1");
+        assert!(extract(header("x-missing"), "").is_err());
+    }
+
+    #[test]
+    fn test_extract_regex() {
+        let body = "order id=123 created";
+        assert_eq!(extract(regex(r"id=(\d+)"), body).unwrap(), "123");
+        assert_eq!(extract(regex(r"\d+"), body).unwrap(), "123");
+        assert!(extract(regex(r"sku=(\w+)"), body).is_err());
+    }
+
+    #[test]
+    fn test_extraction_invalid() {
+        let both = ExtractorSpec {
+            header: Some("x-session".into()),
+            ..json("$.token")
+        };
+        assert!(Extraction::try_new("var".into(), both).is_err());
+        assert!(Extraction::try_new("var".into(), ExtractorSpec::default()).is_err());
+        assert!(Extraction::try_new("var".into(), json("token")).is_err());
+        assert!(Extraction::try_new("var".into(), regex("(")).is_err());
+    }
+}
@@ -0,0 +1,286 @@
+use crate::template::Vars;
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use rand::Rng;
+use rand::seq::SliceRandom;
+use std::path::Path;
+use std::sync::atomic::{AtomicUsize, Ordering::Relaxed};
+use strum::AsRefStr;
+
+/// Order in which the rows of a feeder are handed out
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, AsRefStr)]
+pub enum FeederOrder {
+    /// Rows are shared by all the clients in file order
+    Sequential,
+    /// Rows are picked at random
+    Random,
+    /// Each client reads its own slice of the rows in file order
+    Partitioned,
+}
+
+/// What happens once every row has been used
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, AsRefStr)]
+pub enum FeederEnd {
+    /// Start over from the first row
+    Circular,
+    /// Stop sending requests
+    Stop,
+}
+
+/// Rows of a CSV or JSONL file, each one fills the variables of an iteration
+///
+/// The columns of a CSV file are named by its header. Each line of a JSONL file is an object,
+/// whose string values are used as is and the other values as JSON text.
+#[derive(Debug)]
+pub struct Feeder {
+    columns: Vec<String>,
+    rows: Vec<Vars>,
+    order: FeederOrder,
+    end: FeederEnd,
+    // One cursor for all the clients, or one per client when partitioned
+    cursors: Vec<AtomicUsize>,
+}
+
+impl Feeder {
+    pub fn try_new(
+        columns: Vec<String>,
+        mut rows: Vec<Vars>,
+        order: FeederOrder,
+        end: FeederEnd,
+    ) -> Result<Self> {
+        if rows.is_empty() {
+            bail!("Data file must have at least one row");
+        }
+
+        // Random rows are drawn without replacement when each row is used once
+        if order == FeederOrder::Random && end == FeederEnd::Stop {
+            rows.shuffle(&mut rand::rng());
+        }
+
+        Ok(Self {
+            columns,
+            rows,
+            order,
+            end,
+            cursors: vec![AtomicUsize::new(0)],
+        })
+    }
+
+    pub fn from_path(path: &Path, order: FeederOrder, end: FeederEnd) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read data file {}", path.display()))?;
+
+        let (columns, rows) = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("csv") => parse_csv(&content)?,
+            Some("jsonl" | "ndjson") => parse_jsonl(&content)?,
+            _ => bail!("Data file must have a .csv, .jsonl or .ndjson extension"),
+        };
+
+        Self::try_new(columns, rows, order, end)
+    }
+
+    /// Split the rows between `clients` when partitioned
+    pub fn with_clients(self, clients: usize) -> Result<Self> {
+        if self.order != FeederOrder::Partitioned {
+            return Ok(self);
+        }
+
+        if self.rows.len() < clients {
+            bail!(
+                "Data file has {} rows, partitioning needs at least one row per client ({})",
+                self.rows.len(),
+                clients
+            );
+        }
+
+        Ok(Self {
+            cursors: (0..clients).map(|_| AtomicUsize::new(0)).collect(),
+            ..self
+        })
+    }
+
+    /// Names of the variables each row provides
+    pub fn columns(&self) -> &[String] {
+        &self.columns
+    }
+
+    /// The row for the next iteration of `client_id`, None once the rows run out
+    pub fn next(&self, client_id: usize) -> Option<&Vars> {
+        let len = self.rows.len();
+
+        match (self.order, self.end) {
+            (FeederOrder::Random, FeederEnd::Circular) => {
+                self.rows.get(rand::rng().random_range(0..len))
+            }
+            (FeederOrder::Sequential | FeederOrder::Random, _) => {
+                let n = self.cursors[0].fetch_add(1, Relaxed);
+                self.row(n, len)
+            }
+            (FeederOrder::Partitioned, _) => {
+                // Client k reads the rows k, k + clients, k + 2 * clients, ...
+                let clients = self.cursors.len();
+                let client_id = client_id % clients;
+                let partition_len = (len - client_id).div_ceil(clients);
+                let n = self.cursors[client_id].fetch_add(1, Relaxed);
+                let n = self.row_index(n, partition_len)?;
+                self.rows.get(client_id + n * clients)
+            }
+        }
+    }
+
+    fn row(&self, n: usize, len: usize) -> Option<&Vars> {
+        self.row_index(n, len).and_then(|n| self.rows.get(n))
+    }
+
+    fn row_index(&self, n: usize, len: usize) -> Option<usize> {
+        match self.end {
+            FeederEnd::Circular => Some(n % len),
+            FeederEnd::Stop => (n < len).then_some(n),
+        }
+    }
+}
+
+fn parse_csv(content: &str) -> Result<(Vec<String>, Vec<Vars>)> {
+    let mut reader = csv::Reader::from_reader(content.as_bytes());
+    let columns: Vec<String> = reader
+        .headers()
+        .context("Failed to read the CSV header")?
+        .iter()
+        .map(str::to_string)
+        .collect();
+
+    let rows = reader
+        .records()
+        .enumerate()
+        .map(|(n, record)| {
+            let record = record.with_context(|| format!("Invalid CSV row {}", n + 1))?;
+            Ok(columns
+                .iter()
+                .cloned()
+                .zip(record.iter().map(str::to_string))
+                .collect())
+        })
+        .collect::<Result<Vec<Vars>>>()?;
+
+    Ok((columns, rows))
+}
+
+fn parse_jsonl(content: &str) -> Result<(Vec<String>, Vec<Vars>)> {
+    let rows = content
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .map(|(n, line)| {
+            let object: serde_json::Map<String, serde_json::Value> = serde_json::from_str(line)
+                .with_context(|| format!("Line {} is not a JSON object", n + 1))?;
+            Ok(object
+                .into_iter()
+                .map(|(key, value)| match value {
+                    serde_json::Value::String(s) => (key, s),
+                    value => (key, value.to_string()),
+                })
+                .collect())
+        })
+        .collect::<Result<Vec<Vars>>>()?;
+
+    // Every row must provide the fields of the first one
+    let mut columns: Vec<String> = rows
+        .first()
+        .map(|row| row.keys().cloned().collect())
+        .unwrap_or_default();
+    columns.sort();
+
+    for (n, row) in rows.iter().enumerate() {
+        if let Some(column) = columns.iter().find(|column| !row.contains_key(*column)) {
+            bail!("Row {} has no field '{}'", n + 1, column);
+        }
+    }
+
+    Ok((columns, rows))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn feeder(order: FeederOrder, end: FeederEnd) -> Feeder {
+        let rows = (0..5)
+            .map(|n| Vars::from([("id".to_string(), n.to_string())]))
+            .collect();
+        Feeder::try_new(vec!["id".to_string()], rows, order, end).unwrap()
+    }
+
+    fn ids(feeder: &Feeder, client_id: usize, count: usize) -> Vec<String> {
+        (0..count)
+            .map_while(|_| feeder.next(client_id))
+            .map(|row| row["id"].clone())
+            .collect()
+    }
+
+    #[test]
+    fn test_feeder_sequential() {
+        let circular = feeder(FeederOrder::Sequential, FeederEnd::Circular);
+        assert_eq!(ids(&circular, 0, 3), ["0", "1", "2"]);
+        assert_eq!(ids(&circular, 1, 4), ["3", "4", "0", "1"]);
+
+        let stop = feeder(FeederOrder::Sequential, FeederEnd::Stop);
+        assert_eq!(ids(&stop, 0, 10), ["0", "1", "2", "3", "4"]);
+        assert!(stop.next(1).is_none());
+    }
+
+    #[test]
+    fn test_feeder_random() {
+        let circular = feeder(FeederOrder::Random, FeederEnd::Circular);
+        assert_eq!(ids(&circular, 0, 100).len(), 100);
+
+        let stop = feeder(FeederOrder::Random, FeederEnd::Stop);
+        let mut ids = ids(&stop, 0, 10);
+        ids.sort();
+        assert_eq!(ids, ["0", "1", "2", "3", "4"]);
+    }
+
+    #[test]
+    fn test_feeder_partitioned() {
+        let circular = feeder(FeederOrder::Partitioned, FeederEnd::Circular)
+            .with_clients(2)
+            .unwrap();
+        assert_eq!(ids(&circular, 0, 4), ["0", "2", "4", "0"]);
+        assert_eq!(ids(&circular, 1, 3), ["1", "3", "1"]);
+
+        let stop = feeder(FeederOrder::Partitioned, FeederEnd::Stop)
+            .with_clients(2)
+            .unwrap();
+        assert_eq!(ids(&stop, 0, 10), ["0", "2", "4"]);
+        assert_eq!(ids(&stop, 1, 10), ["1", "3"]);
+
+        assert!(
+            feeder(FeederOrder::Partitioned, FeederEnd::Stop)
+                .with_clients(6)
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_parse_csv() {
+        let (columns, rows) = parse_csv("user_id,sku\n1,A-1\n2,\"B,2\"\n").unwrap();
+        assert_eq!(columns, ["user_id", "sku"]);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[1]["user_id"], "2");
+        assert_eq!(rows[1]["sku"], "B,2");
+
+        assert!(parse_csv("user_id,sku\n1\n").is_err());
+    }
+
+    #[test]
+    fn test_parse_jsonl() {
+        let content = "{\"user_id\": 1, \"sku\": \"A-1\"}\n\n{\"user_id\": 2, \"sku\": \"B-2\", \"extra\": true}\n";
+        let (columns, rows) = parse_jsonl(content).unwrap();
+        assert_eq!(columns, ["sku", "user_id"]);
+        assert_eq!(rows.len(), 2);
+        assert_eq!(rows[0]["user_id"], "1");
+        assert_eq!(rows[0]["sku"], "A-1");
+
+        assert!(parse_jsonl("{\"user_id\": 1}\n{\"sku\": \"A-1\"}\n").is_err());
+        assert!(parse_jsonl("[1, 2]\n").is_err());
+    }
+}
@@ -0,0 +1,528 @@
+use crate::reporter::GroupBy;
+use anyhow::{Result, bail};
+use hdrhistogram::Histogram;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use strum::AsRefStr;
+
+/// Significant digits of the recorded latencies unless set otherwise
+pub const DEFAULT_PRECISION: u8 = 3;
+
+// Slower responses are recorded as an hour
+const MAX_LATENCY_MICROS: u64 = 60 * 60 * 1_000_000;
+
+/// Groups of `--group-by` kept apart, the requests of any other are recorded together
+pub const MAX_GROUPS: usize = 100;
+
+/// Value of every grouped attribute for the requests beyond `MAX_GROUPS`
+pub const OTHER_GROUP: &str = "other";
+
+/// Attributes of a request its latency is recorded with, the ones of the OTel metrics
+#[derive(Debug, Clone, Default, PartialEq, Eq, PartialOrd, Ord)]
+pub struct RequestKey {
+    pub scenario: String,
+    pub step: String,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    /// Protocol version of the response, e.g. "HTTP/2"
+    pub version: &'static str,
+}
+
+impl RequestKey {
+    // The value of `key` as in the OTel attributes, "-" for empty ones like in the report
+    fn value(&self, key: GroupBy) -> String {
+        let value = match key {
+            GroupBy::Scenario => self.scenario.clone(),
+            GroupBy::Step => self.step.clone(),
+            GroupBy::Method => self.method.clone(),
+            GroupBy::Path => self.path.clone(),
+            GroupBy::Status => self.status.to_string(),
+            GroupBy::Version => self.version.to_string(),
+        };
+        if value.is_empty() {
+            "-".to_string()
+        } else {
+            value
+        }
+    }
+}
+
+/// Latencies recorded in-process into HDR histograms by status class and by request attributes,
+/// in microseconds. Unlike the OTel buckets, a percentile is off by at most the precision.
+#[derive(Debug, Clone)]
+pub struct LatencyRecorder {
+    histograms: Arc<Mutex<BTreeMap<&'static str, Histogram<u64>>>>,
+    // The attributes of `--group-by`, no groups are recorded without them
+    group_by: Vec<GroupBy>,
+    // One histogram for every group of the report, by the values of the grouped attributes
+    groups: Arc<Mutex<BTreeMap<Vec<String>, Histogram<u64>>>>,
+    // Latencies since the last call to `take_interval` of every handle, regardless of the status
+    intervals: Arc<Mutex<Vec<Option<Histogram<u64>>>>>,
+    // The one of `intervals` taken by this handle
+    interval: usize,
+    precision: u8,
+    include_warmup: bool,
+}
+
+impl LatencyRecorder {
+    /// `precision` is the number of significant digits kept, from 1 to 5
+    pub fn try_new(precision: u8, include_warmup: bool) -> Result<Self> {
+        if !(1..=5).contains(&precision) {
+            bail!("Latency precision must be between 1 and 5 significant digits");
+        }
+
+        Ok(Self {
+            histograms: Arc::new(Mutex::new(BTreeMap::new())),
+            group_by: Vec::new(),
+            groups: Arc::new(Mutex::new(BTreeMap::new())),
+            intervals: Arc::new(Mutex::new(vec![None])),
+            interval: 0,
+            precision,
+            include_warmup,
+        })
+    }
+
+    /// Also record the latencies by the values of the `group_by` attributes
+    pub fn with_group_by(self, group_by: Vec<GroupBy>) -> Self {
+        Self { group_by, ..self }
+    }
+
+    pub fn record(&self, seconds: f64, key: &RequestKey, warmup: bool) {
+        if warmup && !self.include_warmup {
+            return;
+        }
+
+        let micros = (seconds * 1_000_000.0).round().max(1.0) as u64;
+        let mut histograms = self.histograms.lock().unwrap();
+        histograms
+            .entry(status_class(key.status))
+            .or_insert_with(|| self.histogram())
+            .saturating_record(micros);
+        drop(histograms);
+
+        if !self.group_by.is_empty() {
+            let values = self
+                .group_by
+                .iter()
+                .map(|group| key.value(*group))
+                .collect();
+            let mut groups = self.groups.lock().unwrap();
+            let group = fold(&groups, values);
+            groups
+                .entry(group)
+                .or_insert_with(|| self.histogram())
+                .saturating_record(micros);
+        }
+
+        let mut intervals = self.intervals.lock().unwrap();
+        for interval in intervals.iter_mut() {
+            interval
+                .get_or_insert_with(|| self.histogram())
+                .saturating_record(micros);
+        }
+    }
+
+    /// A handle to the same latencies with an interval of its own, taken apart from the others
+    pub(crate) fn with_interval(&self) -> Self {
+        let mut intervals = self.intervals.lock().unwrap();
+        intervals.push(None);
+        Self {
+            interval: intervals.len() - 1,
+            ..self.clone()
+        }
+    }
+
+    fn histogram(&self) -> Histogram<u64> {
+        Histogram::new_with_bounds(1, MAX_LATENCY_MICROS, self.precision)
+            .expect("precision is validated on creation")
+    }
+
+    /// Latencies recorded since the previous call, None when there were none
+    pub(crate) fn take_interval(&self) -> Option<Latencies> {
+        self.intervals.lock().unwrap()[self.interval]
+            .take()
+            .map(Latencies)
+    }
+
+    /// Latencies of the responses in `class`, e.g. "2xx"
+    pub(crate) fn latencies(&self, class: &str) -> Option<Latencies> {
+        let histograms = self.histograms.lock().unwrap();
+        histograms.get(class).cloned().map(Latencies)
+    }
+
+    /// Latencies by the values of the `--group-by` attributes
+    pub(crate) fn groups(&self) -> BTreeMap<Vec<String>, Latencies> {
+        let groups = self.groups.lock().unwrap();
+        groups
+            .iter()
+            .map(|(group, histogram)| (group.clone(), Latencies(histogram.clone())))
+            .collect()
+    }
+
+    /// The group the requests with the `values` of the grouped attributes are recorded in,
+    /// the "other" one once there are too many groups
+    pub(crate) fn group_of(&self, values: Vec<String>) -> Vec<String> {
+        fold(&self.groups.lock().unwrap(), values)
+    }
+
+    /// Latencies of every response regardless of the status
+    pub(crate) fn all(&self) -> Option<Latencies> {
+        let histograms = self.histograms.lock().unwrap();
+        let mut values = histograms.values();
+        let mut all = values.next()?.clone();
+        for histogram in values {
+            // All histograms share the bounds, so nothing is out of range
+            all.add(histogram).expect("histograms share the bounds");
+        }
+        Some(Latencies(all))
+    }
+}
+
+/// Phase of a request, in the order a request goes through them
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, AsRefStr)]
+pub enum Phase {
+    #[strum(serialize = "DNS")]
+    Dns,
+    Connect,
+    #[strum(serialize = "TLS")]
+    Tls,
+    #[strum(serialize = "TTFB")]
+    Ttfb,
+    Download,
+}
+
+/// Time spent in each phase of the requests, recorded in-process into HDR histograms like the
+/// latencies. The connection phases are shared by requests and are warm-up while the warm-up
+/// of the run is still going on.
+#[derive(Debug, Clone)]
+pub struct TimingRecorder {
+    phases: Arc<Mutex<BTreeMap<Phase, Histogram<u64>>>>,
+    precision: u8,
+    include_warmup: bool,
+}
+
+impl TimingRecorder {
+    /// `precision` is the number of significant digits kept, from 1 to 5
+    pub fn try_new(precision: u8, include_warmup: bool) -> Result<Self> {
+        if !(1..=5).contains(&precision) {
+            bail!("Latency precision must be between 1 and 5 significant digits");
+        }
+
+        Ok(Self {
+            phases: Arc::new(Mutex::new(BTreeMap::new())),
+            precision,
+            include_warmup,
+        })
+    }
+
+    pub fn record(&self, phase: Phase, duration: Duration, warmup: bool) {
+        if warmup && !self.include_warmup {
+            return;
+        }
+
+        let micros = (duration.as_micros() as u64).max(1);
+        let mut phases = self.phases.lock().unwrap();
+        phases
+            .entry(phase)
+            .or_insert_with(|| {
+                Histogram::new_with_bounds(1, MAX_LATENCY_MICROS, self.precision)
+                    .expect("precision is validated on creation")
+            })
+            .saturating_record(micros);
+    }
+
+    /// Times of the phases that were gone through, in the order of a request
+    pub(crate) fn phases(&self) -> Vec<(Phase, Latencies)> {
+        let phases = self.phases.lock().unwrap();
+        phases
+            .iter()
+            .map(|(phase, histogram)| (*phase, Latencies(histogram.clone())))
+            .collect()
+    }
+}
+
+/// A percentile from 0 to 100 such as 99.9
+pub fn parse_percentile(input: &str) -> Result<f64> {
+    match input.parse::<f64>() {
+        Ok(percent) if percent > 0.0 && percent <= 100.0 => Ok(percent),
+        _ => bail!("Percentile must be a number greater than 0 and at most 100"),
+    }
+}
+
+// Beyond `MAX_GROUPS`, requests of a new group are recorded into the "other" one instead
+fn fold(groups: &BTreeMap<Vec<String>, Histogram<u64>>, values: Vec<String>) -> Vec<String> {
+    if groups.len() < MAX_GROUPS || groups.contains_key(&values) {
+        values
+    } else {
+        vec![OTHER_GROUP.to_string(); values.len()]
+    }
+}
+
+fn status_class(status: u16) -> &'static str {
+    match status {
+        200..=299 => "2xx",
+        300..=399 => "3xx",
+        400..=499 => "4xx",
+        500..=599 => "5xx",
+        _ => "999",
+    }
+}
+
+/// A copy of one histogram of the recorder
+#[derive(Debug, Clone)]
+pub(crate) struct Latencies(Histogram<u64>);
+
+impl Latencies {
+    /// Also the latencies of `other`
+    pub(crate) fn add(&mut self, other: &Latencies) {
+        self.0.add(&other.0).expect("histograms share the bounds");
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.0.len()
+    }
+
+    /// Mean in seconds, rounded to the microseconds the latencies are recorded in
+    pub(crate) fn mean(&self) -> f64 {
+        self.0.mean().round() / 1_000_000.0
+    }
+
+    /// Latency in seconds at `percent` from 0 to 100
+    pub(crate) fn percentile(&self, percent: f64) -> f64 {
+        self.0.value_at_percentile(percent) as f64 / 1_000_000.0
+    }
+
+    /// Counts in `bins` ranges of equal width from the fastest to the slowest latency,
+    /// each with the upper bound of the range in seconds
+    pub(crate) fn distribution(&self, bins: usize) -> Vec<(f64, u64)> {
+        if bins == 0 || self.0.is_empty() {
+            return Vec::new();
+        }
+
+        let (min, max) = (self.0.min(), self.0.max());
+        // A single range when every latency is the same
+        let bins = if self.0.equivalent(min, max) { 1 } else { bins };
+        let width = (max - min) as f64 / bins as f64;
+
+        let mut counts = vec![0; bins];
+        for value in self.0.iter_recorded() {
+            let offset = value.value_iterated_to().saturating_sub(min) as f64;
+            let bin = if width > 0.0 {
+                ((offset / width) as usize).min(bins - 1)
+            } else {
+                0
+            };
+            counts[bin] += value.count_at_value();
+        }
+
+        counts
+            .into_iter()
+            .enumerate()
+            .map(|(bin, count)| {
+                let upper = min as f64 + width * (bin + 1) as f64;
+                (upper / 1_000_000.0, count)
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn key(status: u16) -> RequestKey {
+        RequestKey {
+            status,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_latency_recorder() {
+        let recorder = LatencyRecorder::try_new(3, false).unwrap();
+        for n in 1..=1000 {
+            recorder.record(n as f64 / 1000.0, &key(200), false);
+        }
+        recorder.record(10.0, &key(200), true);
+        recorder.record(0.5, &key(503), false);
+
+        let latencies = recorder.latencies("2xx").unwrap();
+        assert!((latencies.percentile(50.0) - 0.5).abs() < 0.001);
+        assert!((latencies.percentile(99.9) - 0.999).abs() < 0.002);
+        assert!((latencies.percentile(100.0) - 1.0).abs() < 0.001);
+
+        let p50 = recorder.latencies("5xx").unwrap().percentile(50.0);
+        assert!((p50 - 0.5).abs() < 0.001);
+        assert!(recorder.latencies("4xx").is_none());
+
+        let other = recorder.with_interval();
+        let interval = recorder.take_interval().unwrap();
+        assert!((interval.percentile(100.0) - 1.0).abs() < 0.001);
+        assert!(recorder.take_interval().is_none());
+
+        // 1000 responses of the 2xx class and the 5xx one at 0.5s
+        let p50 = recorder.all().unwrap().percentile(50.0);
+        assert!((p50 - 0.5).abs() < 0.001);
+        let p100 = recorder.all().unwrap().percentile(100.0);
+        assert!((p100 - 1.0).abs() < 0.001);
+
+        // Taken apart, from when the handle was made
+        recorder.record(2.0, &key(200), false);
+        let mut interval = other.take_interval().unwrap();
+        assert_eq!(interval.count(), 1);
+        assert!(other.take_interval().is_none());
+        interval.add(&recorder.take_interval().unwrap());
+        assert_eq!(interval.count(), 2);
+    }
+
+    #[test]
+    fn test_latency_groups() {
+        let recorder = LatencyRecorder::try_new(3, false)
+            .unwrap()
+            .with_group_by(vec![GroupBy::Method, GroupBy::Path]);
+        let request = |path: &str, status: u16| RequestKey {
+            scenario: "default".to_string(),
+            method: "GET".to_string(),
+            path: path.to_string(),
+            status,
+            version: "HTTP/1.1",
+            ..Default::default()
+        };
+        for n in 1..=100 {
+            recorder.record(n as f64 / 1000.0, &request("/a", 200), false);
+            recorder.record(n as f64 / 100.0, &request("/b", 200), false);
+        }
+        recorder.record(5.0, &request("/b", 500), false);
+        recorder.record(10.0, &request("/a", 200), true);
+
+        let groups = recorder.groups();
+        assert_eq!(groups.len(), 2);
+        let a = &groups[&vec!["GET".to_string(), "/a".to_string()]];
+        assert!((a.percentile(50.0) - 0.050).abs() < 0.0001);
+        assert!((a.percentile(100.0) - 0.100).abs() < 0.0001);
+        let b = &groups[&vec!["GET".to_string(), "/b".to_string()]];
+        assert!((b.percentile(100.0) - 5.0).abs() < 0.01);
+
+        let recorder = LatencyRecorder::try_new(3, false)
+            .unwrap()
+            .with_group_by(vec![GroupBy::Status, GroupBy::Step]);
+        recorder.record(0.1, &request("/a", 200), false);
+        recorder.record(0.1, &request("/b", 500), false);
+        assert_eq!(
+            recorder.groups().keys().collect::<Vec<_>>(),
+            [
+                &vec!["200".to_string(), "-".to_string()],
+                &vec!["500".to_string(), "-".to_string()]
+            ]
+        );
+
+        // Nothing is kept by group without --group-by
+        let recorder = LatencyRecorder::try_new(3, false).unwrap();
+        recorder.record(0.1, &request("/a", 200), false);
+        assert!(recorder.groups().is_empty());
+    }
+
+    #[test]
+    fn test_latency_groups_limit() {
+        let recorder = LatencyRecorder::try_new(1, false)
+            .unwrap()
+            .with_group_by(vec![GroupBy::Path]);
+        let request = |path: String| RequestKey {
+            path,
+            ..Default::default()
+        };
+        for n in 0..MAX_GROUPS + 10 {
+            recorder.record(0.1, &request(format!("/items/{}", n)), false);
+        }
+        // Groups seen before the limit are still kept apart
+        recorder.record(0.2, &request("/items/0".to_string()), false);
+
+        let groups = recorder.groups();
+        assert_eq!(groups.len(), MAX_GROUPS + 1);
+        assert_eq!(groups[&vec!["/items/0".to_string()]].count(), 2);
+        assert_eq!(groups[&vec![OTHER_GROUP.to_string()]].count(), 10);
+        assert_eq!(
+            recorder.group_of(vec!["/items/1".to_string()]),
+            ["/items/1"]
+        );
+        assert_eq!(recorder.group_of(vec!["/new".to_string()]), [OTHER_GROUP]);
+    }
+
+    #[test]
+    fn test_timing_recorder() {
+        let recorder = TimingRecorder::try_new(3, false).unwrap();
+        for n in 1..=100 {
+            recorder.record(Phase::Ttfb, Duration::from_micros(n * 10), false);
+        }
+        recorder.record(Phase::Ttfb, Duration::from_secs(1), true);
+        recorder.record(Phase::Dns, Duration::from_micros(300), false);
+
+        let phases = recorder.phases();
+        assert_eq!(
+            phases
+                .iter()
+                .map(|(phase, _)| phase.as_ref())
+                .collect::<Vec<_>>(),
+            ["DNS", "TTFB"]
+        );
+        let (_, dns) = &phases[0];
+        assert_eq!(dns.count(), 1);
+        assert_eq!(dns.percentile(50.0), 0.0003);
+        let (_, ttfb) = &phases[1];
+        assert_eq!(ttfb.count(), 100);
+        assert_eq!(ttfb.percentile(50.0), 0.0005);
+        assert_eq!(ttfb.percentile(99.0), 0.00099);
+        assert_eq!(ttfb.mean(), 0.000505);
+    }
+
+    #[test]
+    fn test_distribution() {
+        let recorder = LatencyRecorder::try_new(3, false).unwrap();
+        // Bimodal, e.g. cache hits and misses
+        for _ in 0..90 {
+            recorder.record(0.010, &key(200), false);
+        }
+        for _ in 0..10 {
+            recorder.record(0.100, &key(200), false);
+        }
+
+        let distribution = recorder.all().unwrap().distribution(10);
+        assert_eq!(distribution.len(), 10);
+        assert_eq!(distribution[0].1, 90);
+        assert_eq!(distribution[9].1, 10);
+        assert_eq!(
+            distribution.iter().map(|(_, count)| count).sum::<u64>(),
+            100
+        );
+        assert!((distribution[9].0 - 0.100).abs() < 0.001);
+
+        let recorder = LatencyRecorder::try_new(3, false).unwrap();
+        recorder.record(0.5, &key(200), false);
+        let distribution = recorder.all().unwrap().distribution(10);
+        assert_eq!(distribution.len(), 1);
+        assert_eq!(distribution[0].1, 1);
+    }
+
+    #[test]
+    fn test_parse_percentile() {
+        assert_eq!(parse_percentile("99.99").unwrap(), 99.99);
+        assert_eq!(parse_percentile("100").unwrap(), 100.0);
+        for input in ["0", "100.1", "-1", "p99", "NaN"] {
+            assert!(parse_percentile(input).is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_latency_recorder_precision() {
+        assert!(LatencyRecorder::try_new(0, false).is_err());
+        assert!(LatencyRecorder::try_new(6, false).is_err());
+
+        let recorder = LatencyRecorder::try_new(1, true).unwrap();
+        recorder.record(0.123456, &key(200), true);
+        let p50 = recorder.latencies("2xx").unwrap().percentile(50.0);
+        assert!((p50 - 0.123456).abs() > 0.0001);
+        assert!((p50 - 0.123456).abs() / 0.123456 < 0.1);
+    }
+}
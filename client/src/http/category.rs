@@ -0,0 +1,248 @@
+use crate::http::{Http3Error, UpgradeError, connect::DnsError};
+use std::error::Error;
+use std::io;
+use strum::AsRefStr;
+
+/// What went wrong with a failed request, finer than the phase it failed in
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum ErrorCategory {
+    /// The host name could not be resolved
+    Dns,
+    ConnectionRefused,
+    /// The server reset or aborted the connection
+    ConnectionReset,
+    /// The server closed the connection before the response was complete
+    ConnectionClosed,
+    /// The certificate of the server was rejected, e.g. expired or for another name
+    TlsCertificate,
+    /// The TLS handshake failed for any other reason, e.g. no common protocol version
+    TlsHandshake,
+    QuicHandshake,
+    H2cUpgrade,
+    /// The server closed the HTTP/2 connection with GOAWAY
+    #[strum(serialize = "http2_goaway")]
+    Http2GoAway,
+    /// The server reset the HTTP/2 stream with RST_STREAM
+    #[strum(serialize = "http2_rst_stream")]
+    Http2RstStream,
+    /// The response could not be parsed
+    InvalidResponse,
+    /// The body of the response could not be read
+    BodyRead,
+    TooManyRedirects,
+    Timeout,
+    /// Any other failure to open a connection
+    Connect,
+    /// Any other failure to send the request or receive its response
+    Request,
+    /// A value could not be extracted from the response for later steps
+    Extract,
+    Unknown,
+}
+
+/// Category of a failed request, as recorded in the metrics along with its phase
+pub(crate) fn error_category(err: &anyhow::Error) -> ErrorCategory {
+    if let Some(err) = err.downcast_ref::<Http3Error>() {
+        return err.category();
+    }
+    if let Some(err) = err.downcast_ref::<UpgradeError>() {
+        return err.category();
+    }
+    let Some(err) = err.downcast_ref::<reqwest::Error>() else {
+        return ErrorCategory::Unknown;
+    };
+    if err.is_timeout() {
+        return ErrorCategory::Timeout;
+    }
+    if err.is_redirect() {
+        return ErrorCategory::TooManyRedirects;
+    }
+    if let Some(category) = cause_category(err) {
+        return category;
+    }
+    if err.is_body() || err.is_decode() {
+        ErrorCategory::BodyRead
+    } else if err.is_connect() {
+        ErrorCategory::Connect
+    } else if err.is_request() {
+        ErrorCategory::Request
+    } else {
+        ErrorCategory::Unknown
+    }
+}
+
+// The first of the causes of `err` that tells what went wrong
+fn cause_category(err: &(dyn Error + 'static)) -> Option<ErrorCategory> {
+    if let Some(category) = known_category(err) {
+        return Some(category);
+    }
+    // io::Error skips the error it wraps in its source, which is where rustls and reqwest put theirs
+    let inner = err
+        .downcast_ref::<io::Error>()
+        .and_then(io::Error::get_ref)
+        .map(|inner| inner as &(dyn Error + 'static));
+    inner.or_else(|| err.source()).and_then(cause_category)
+}
+
+fn known_category(err: &(dyn Error + 'static)) -> Option<ErrorCategory> {
+    if err.is::<DnsError>() {
+        return Some(ErrorCategory::Dns);
+    }
+    if let Some(err) = err.downcast_ref::<rustls::Error>() {
+        return Some(match err {
+            rustls::Error::InvalidCertificate(_) => ErrorCategory::TlsCertificate,
+            _ => ErrorCategory::TlsHandshake,
+        });
+    }
+    if let Some(err) = err.downcast_ref::<h2::Error>() {
+        return h2_category(err);
+    }
+    if let Some(err) = err.downcast_ref::<hyper::Error>() {
+        if err.is_incomplete_message() {
+            return Some(ErrorCategory::ConnectionClosed);
+        }
+        if err.is_parse() {
+            return Some(ErrorCategory::InvalidResponse);
+        }
+    }
+    err.downcast_ref::<io::Error>().and_then(io_category)
+}
+
+pub(crate) fn h2_category(err: &h2::Error) -> Option<ErrorCategory> {
+    if err.is_go_away() {
+        Some(ErrorCategory::Http2GoAway)
+    } else if err.is_reset() {
+        Some(ErrorCategory::Http2RstStream)
+    } else {
+        err.get_io().and_then(io_category)
+    }
+}
+
+pub(crate) fn io_category(err: &io::Error) -> Option<ErrorCategory> {
+    match err.kind() {
+        io::ErrorKind::ConnectionRefused => Some(ErrorCategory::ConnectionRefused),
+        io::ErrorKind::ConnectionReset
+        | io::ErrorKind::ConnectionAborted
+        | io::ErrorKind::BrokenPipe => Some(ErrorCategory::ConnectionReset),
+        io::ErrorKind::UnexpectedEof => Some(ErrorCategory::ConnectionClosed),
+        io::ErrorKind::TimedOut => Some(ErrorCategory::Timeout),
+        _ => None,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{Client, HttpVersion, Method, Negotiation, Request};
+    use std::collections::HashMap;
+    use std::time::Duration;
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    use tokio::net::TcpListener;
+
+    fn request(url: &str) -> Request {
+        Request {
+            http_version: HttpVersion::Http11,
+            negotiation: Negotiation::Auto,
+            url: url::Url::parse(url).unwrap(),
+            method: Method::Get,
+            headers: HashMap::new(),
+            timeout: Duration::from_secs(5),
+            body: Vec::new(),
+            basic_auth: None,
+            insecure: false,
+            disable_keepalive: true,
+            root_cert: None,
+            resolve: None,
+        }
+    }
+
+    async fn category(req: Request) -> ErrorCategory {
+        let client = Client::try_new(req, None).unwrap();
+        let err = client.request().await.unwrap_err();
+        error_category(&err)
+    }
+
+    #[test]
+    fn test_category_names() {
+        assert_eq!(
+            ErrorCategory::ConnectionRefused.as_ref(),
+            "connection_refused"
+        );
+        assert_eq!(ErrorCategory::Http2GoAway.as_ref(), "http2_goaway");
+        assert_eq!(ErrorCategory::Http2RstStream.as_ref(), "http2_rst_stream");
+        assert_eq!(ErrorCategory::TlsCertificate.as_ref(), "tls_certificate");
+    }
+
+    #[tokio::test]
+    async fn test_connection_refused() {
+        // Nothing listens on the port of a dropped listener
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        drop(listener);
+
+        let req = request(&format!("http://{}/", addr));
+        assert_eq!(category(req).await, ErrorCategory::ConnectionRefused);
+    }
+
+    #[tokio::test]
+    async fn test_dns() {
+        let req = request("http://hb.invalid/");
+        assert_eq!(category(req).await, ErrorCategory::Dns);
+    }
+
+    #[tokio::test]
+    async fn test_connection_closed() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut tcp, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = tcp.read(&mut buf).await;
+            // Promises more of the body than is sent
+            let _ = tcp
+                .write_all(b"HTTP/1.1 200 OK\r\ncontent-length: 10\r\n\r\nhello")
+                .await;
+        });
+
+        let req = request(&format!("http://{}/", addr));
+        assert_eq!(category(req).await, ErrorCategory::ConnectionClosed);
+    }
+
+    #[tokio::test]
+    async fn test_invalid_response() {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut tcp, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = tcp.read(&mut buf).await;
+            let _ = tcp.write_all(b"not http\r\n\r\n").await;
+        });
+
+        let req = request(&format!("http://{}/", addr));
+        assert_eq!(category(req).await, ErrorCategory::InvalidResponse);
+    }
+
+    #[tokio::test]
+    async fn test_tls_handshake() {
+        // Answers the ClientHello with plain HTTP
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut tcp, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = tcp.read(&mut buf).await;
+            let _ = tcp.write_all(b"HTTP/1.1 400 Bad Request\r\n\r\n").await;
+        });
+
+        let req = request(&format!("https://{}/", addr));
+        assert_eq!(category(req).await, ErrorCategory::TlsHandshake);
+    }
+
+    #[test]
+    fn test_unknown() {
+        let err = anyhow::anyhow!("something else");
+        assert_eq!(error_category(&err), ErrorCategory::Unknown);
+    }
+}
@@ -0,0 +1,246 @@
+use futures::future::BoxFuture;
+use reqwest::dns::{Addrs, Name, Resolve, Resolving};
+use rustls::NamedGroup;
+use rustls::client::{
+    ClientSessionMemoryCache, ClientSessionStore, Tls12ClientSessionValue, Tls13ClientSessionValue,
+};
+use rustls::pki_types::ServerName;
+use std::cell::{Cell, RefCell};
+use std::fmt;
+use std::future::Future;
+use std::io;
+use std::sync::Arc;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tower_layer::Layer;
+use tower_service::Service;
+
+/// Called for every connection a client opens, with the time spent opening it
+pub(crate) type OnConnect = Arc<dyn Fn(&ConnectTiming) + Send + Sync>;
+
+/// Time spent in each phase of opening a connection, None for the phases it did not go through,
+/// e.g. DNS for an IP address or TLS for http
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub struct ConnectTiming {
+    pub dns: Option<Duration>,
+    pub connect: Option<Duration>,
+    pub tls: Option<Duration>,
+}
+
+tokio::task_local! {
+    // Set while reqwest opens a connection, for the resolver and TLS to mark their phases in
+    static CONNECTING: RefCell<Marks>;
+    // Set while a request waits for its response, summing up the connections it waited for
+    static WAITING: Cell<Duration>;
+}
+
+#[derive(Debug, Clone, Copy, Default)]
+struct Marks {
+    dns: Option<Duration>,
+    tls_start: Option<Instant>,
+}
+
+/// Output of `fut`, with the time it spent waiting for reqwest to open connections
+pub(crate) async fn waiting_for_connections<F: Future>(fut: F) -> (F::Output, Duration) {
+    WAITING
+        .scope(Cell::default(), async {
+            let output = fut.await;
+            (output, WAITING.with(Cell::get))
+        })
+        .await
+}
+
+/// Calls `OnConnect` once the connector of reqwest has opened a connection, TLS included
+#[derive(Clone)]
+pub(crate) struct TimeConnections(pub OnConnect);
+
+impl<S> Layer<S> for TimeConnections {
+    type Service = Timed<S>;
+
+    fn layer(&self, inner: S) -> Self::Service {
+        Timed {
+            inner,
+            on_connect: Arc::clone(&self.0),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub(crate) struct Timed<S> {
+    inner: S,
+    on_connect: OnConnect,
+}
+
+impl<S, R> Service<R> for Timed<S>
+where
+    S: Service<R>,
+    S::Future: Send + 'static,
+{
+    type Response = S::Response;
+    type Error = S::Error;
+    type Future = BoxFuture<'static, Result<S::Response, S::Error>>;
+
+    fn poll_ready(&mut self, cx: &mut Context<'_>) -> Poll<Result<(), Self::Error>> {
+        self.inner.poll_ready(cx)
+    }
+
+    fn call(&mut self, req: R) -> Self::Future {
+        let connecting = self.inner.call(req);
+        let on_connect = Arc::clone(&self.on_connect);
+        Box::pin(async move {
+            let start = Instant::now();
+            let (conn, marks) = CONNECTING
+                .scope(RefCell::default(), async {
+                    let conn = connecting.await;
+                    (conn, CONNECTING.with(|marks| *marks.borrow()))
+                })
+                .await;
+            let conn = conn?;
+
+            let end = Instant::now();
+            let _ = WAITING.try_with(|waiting| waiting.set(waiting.get() + (end - start)));
+            // TCP connect starts once the name is resolved and ends as the TLS handshake starts
+            let connected = marks.tls_start.unwrap_or(end);
+            on_connect(&ConnectTiming {
+                dns: marks.dns,
+                connect: Some((connected - start).saturating_sub(marks.dns.unwrap_or_default())),
+                tls: marks.tls_start.map(|tls_start| end - tls_start),
+            });
+            Ok(conn)
+        })
+    }
+}
+
+/// Resolves names like reqwest does by default, timing the lookups of the connections it opens
+#[derive(Debug, Default)]
+pub(crate) struct TimedResolver;
+
+impl Resolve for TimedResolver {
+    fn resolve(&self, name: Name) -> Resolving {
+        Box::pin(async move {
+            let start = Instant::now();
+            let addrs: Vec<_> = tokio::net::lookup_host((name.as_str(), 0))
+                .await
+                .map_err(DnsError)?
+                .collect();
+            let _ = CONNECTING.try_with(|marks| marks.borrow_mut().dns = Some(start.elapsed()));
+            Ok(Box::new(addrs.into_iter()) as Addrs)
+        })
+    }
+}
+
+/// Failure of the resolver, kept in the causes of reqwest errors to tell DNS failures apart
+#[derive(Debug)]
+pub(crate) struct DnsError(pub io::Error);
+
+impl fmt::Display for DnsError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        self.0.fmt(f)
+    }
+}
+
+impl std::error::Error for DnsError {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        self.0.source()
+    }
+}
+
+/// Session store that marks the start of TLS handshakes.
+///
+/// reqwest opens the TCP connection and runs the handshake within one call of its connector,
+/// so `TimeConnections` cannot tell them apart and the session store is the only part of the
+/// handshake a client can plug into. It relies on rustls looking up a TLS 1.3 ticket first
+/// thing in `ClientConnection::new`, as it builds the ClientHello, whichever versions are
+/// enabled. This is not documented by rustls, so `test_marks_handshake_start` pins it.
+#[derive(Debug)]
+pub(crate) struct MarkHandshakes(ClientSessionMemoryCache);
+
+impl Default for MarkHandshakes {
+    fn default() -> Self {
+        // The size of the default store of rustls
+        Self(ClientSessionMemoryCache::new(256))
+    }
+}
+
+impl ClientSessionStore for MarkHandshakes {
+    fn set_kx_hint(&self, server_name: ServerName<'static>, group: NamedGroup) {
+        self.0.set_kx_hint(server_name, group);
+    }
+
+    fn kx_hint(&self, server_name: &ServerName<'_>) -> Option<NamedGroup> {
+        self.0.kx_hint(server_name)
+    }
+
+    fn set_tls12_session(&self, server_name: ServerName<'static>, value: Tls12ClientSessionValue) {
+        self.0.set_tls12_session(server_name, value);
+    }
+
+    fn tls12_session(&self, server_name: &ServerName<'_>) -> Option<Tls12ClientSessionValue> {
+        self.0.tls12_session(server_name)
+    }
+
+    fn remove_tls12_session(&self, server_name: &ServerName<'static>) {
+        self.0.remove_tls12_session(server_name);
+    }
+
+    fn insert_tls13_ticket(
+        &self,
+        server_name: ServerName<'static>,
+        value: Tls13ClientSessionValue,
+    ) {
+        self.0.insert_tls13_ticket(server_name, value);
+    }
+
+    fn take_tls13_ticket(
+        &self,
+        server_name: &ServerName<'static>,
+    ) -> Option<Tls13ClientSessionValue> {
+        let _ = CONNECTING.try_with(|marks| marks.borrow_mut().tls_start = Some(Instant::now()));
+        self.0.take_tls13_ticket(server_name)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::tls::client_config;
+    use crate::http::{HttpVersion, Method, Negotiation, Request};
+    use rustls::ClientConnection;
+    use std::collections::HashMap;
+
+    #[tokio::test]
+    async fn test_marks_handshake_start() {
+        let req = Request {
+            http_version: HttpVersion::Http11,
+            negotiation: Negotiation::Auto,
+            url: url::Url::parse("https://localhost/").unwrap(),
+            method: Method::Get,
+            headers: HashMap::new(),
+            timeout: Duration::from_secs(5),
+            body: Vec::new(),
+            basic_auth: None,
+            insecure: false,
+            disable_keepalive: false,
+            root_cert: None,
+            resolve: None,
+        };
+
+        for versions in [rustls::DEFAULT_VERSIONS, &[&rustls::version::TLS12]] {
+            let mut config = client_config(&req, versions).unwrap();
+            config.resumption =
+                rustls::client::Resumption::store(Arc::new(MarkHandshakes::default()));
+            let name = ServerName::try_from("localhost").unwrap();
+
+            let start = Instant::now();
+            let (conn, marks) = CONNECTING
+                .scope(RefCell::default(), async {
+                    let conn = ClientConnection::new(Arc::new(config), name).unwrap();
+                    (conn, CONNECTING.with(|marks| *marks.borrow()))
+                })
+                .await;
+            // Marked as the ClientHello is queued, before anything is sent
+            assert!(marks.tls_start.is_some_and(|tls_start| tls_start >= start));
+            assert!(conn.wants_write());
+        }
+    }
+}
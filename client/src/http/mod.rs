@@ -0,0 +1,702 @@
+mod category;
+mod connect;
+mod quic;
+mod tls;
+mod upgrade;
+
+pub use category::ErrorCategory;
+pub use connect::ConnectTiming;
+pub use quic::Http3Error;
+pub use upgrade::UpgradeError;
+
+use anyhow::{Result, bail};
+pub(crate) use category::error_category;
+use clap::ValueEnum;
+pub(crate) use connect::OnConnect;
+use connect::{MarkHandshakes, TimeConnections, TimedResolver};
+use prometheus_client::encoding::EncodeLabelValue;
+use quic::Http3Client;
+use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+use std::collections::HashMap;
+use std::net::{IpAddr, SocketAddr};
+use std::path::PathBuf;
+use std::str::FromStr;
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use strum::AsRefStr;
+use tokio::sync::Semaphore;
+use upgrade::H2cUpgradeClient;
+
+#[derive(Debug)]
+pub(crate) struct Client {
+    underlying: reqwest::Client,
+    raw_request: reqwest::Request,
+    // Sends the request over QUIC instead, reqwest only builds it then
+    http3: Option<Http3Client>,
+    // Sends the request over an upgraded h2c connection instead
+    h2c_upgrade: Option<H2cUpgradeClient>,
+    // Concurrent requests over the connection, shared by every clone
+    streams: Option<Arc<Semaphore>>,
+}
+
+type Status = u16;
+type ResponseSize = u64;
+
+/// Response kept for inspection, e.g. to capture values for later requests
+#[derive(Debug)]
+pub(crate) struct Response {
+    pub status: Status,
+    pub size: ResponseSize,
+    /// Negotiated with the server, e.g. HTTP/1.1 after a declined upgrade
+    pub version: reqwest::Version,
+    pub timing: ResponseTiming,
+    pub headers: HeaderMap,
+    pub body: bytes::Bytes,
+}
+
+/// Time to the first byte of the response, leaving out the connection setup it waited for,
+/// and to read the rest of it
+#[derive(Debug, Clone, Copy, Default)]
+pub(crate) struct ResponseTiming {
+    pub ttfb: Duration,
+    pub download: Duration,
+}
+
+impl Clone for Client {
+    fn clone(&self) -> Self {
+        let underlying = self.underlying.clone();
+        // Since streams are not used, it will never be None
+        let raw_request = self.raw_request.try_clone().unwrap();
+        Self {
+            underlying,
+            raw_request,
+            http3: self.http3.clone(),
+            h2c_upgrade: self.h2c_upgrade.clone(),
+            streams: self.streams.clone(),
+        }
+    }
+}
+
+fn user_agent() -> String {
+    format!("hb-client/{}", env!("CARGO_PKG_VERSION"))
+}
+
+/// Phase of the request an error happened in, as recorded in the metrics
+pub(crate) fn error_phase(err: &anyhow::Error) -> &'static str {
+    if let Some(err) = err.downcast_ref::<Http3Error>() {
+        return err.phase();
+    }
+    if let Some(err) = err.downcast_ref::<UpgradeError>() {
+        return err.phase();
+    }
+    match err.downcast_ref::<reqwest::Error>() {
+        Some(err) if err.is_timeout() => "timeout",
+        Some(err) if err.is_connect() => "connect",
+        Some(err) if err.is_request() => "request",
+        Some(err) if err.is_redirect() => "redirect",
+        _ => "unknown",
+    }
+}
+
+impl Client {
+    /// `on_connect` is called for every connection the client and its clones open
+    pub(crate) fn try_new(req: Request, on_connect: Option<OnConnect>) -> Result<Self> {
+        let mut builder = reqwest::Client::builder()
+            .timeout(req.timeout)
+            .user_agent(user_agent())
+            .dns_resolver(Arc::new(TimedResolver));
+
+        builder = builder.default_headers(Self::default_headers());
+
+        if let (Some(ip), Some(domain)) = (req.resolve, req.url.domain()) {
+            let socket = match req.url.port() {
+                Some(port) => SocketAddr::new(ip, port),
+                None => {
+                    let port = if req.url.scheme() == "https" { 443 } else { 80 };
+                    SocketAddr::new(ip, port)
+                }
+            };
+
+            builder = builder.resolve(domain, socket);
+        }
+
+        let https = req.url.scheme() == "https";
+        let mut h2c_upgrade = false;
+        let mut alpn = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        if req.http_version == HttpVersion::Http2 {
+            match (req.negotiation, https) {
+                // ALPN offers both h2 and http/1.1
+                (Negotiation::Auto, true) => {}
+                (Negotiation::H2cUpgrade, false) => h2c_upgrade = true,
+                // ALPN offers h2 only
+                (Negotiation::H2Alpn, true)
+                | (Negotiation::Auto | Negotiation::H2cPriorKnowledge, false) => {
+                    builder = builder.http2_prior_knowledge();
+                    alpn = vec![b"h2".to_vec()];
+                }
+                (Negotiation::H2Alpn, false) => {
+                    bail!(
+                        "h2-alpn negotiates HTTP/2 over TLS and needs an https URL: {}",
+                        req.url
+                    );
+                }
+                (Negotiation::H2cPriorKnowledge | Negotiation::H2cUpgrade, true) => {
+                    bail!(
+                        "{} is for HTTP/2 over cleartext and needs an http URL: {}",
+                        req.negotiation.as_ref(),
+                        req.url
+                    );
+                }
+            }
+        }
+
+        if req.disable_keepalive {
+            builder = builder.pool_max_idle_per_host(0);
+        }
+
+        // Built here rather than by reqwest for its session store to mark the start of the TLS
+        // handshakes, which the connector layer cannot see
+        let mut tls = tls::client_config(&req, rustls::DEFAULT_VERSIONS)?;
+        tls.alpn_protocols = alpn;
+        tls.resumption = rustls::client::Resumption::store(Arc::new(MarkHandshakes::default()));
+        builder = builder.use_preconfigured_tls(tls);
+
+        if let Some(on_connect) = &on_connect {
+            builder = builder.connector_layer(TimeConnections(Arc::clone(on_connect)));
+        }
+
+        let client = builder.build()?;
+
+        let http3 = match req.http_version {
+            HttpVersion::Http3 => Some(Http3Client::try_new(&req, on_connect.clone())?),
+            _ => None,
+        };
+        let h2c_upgrade = h2c_upgrade.then(|| {
+            H2cUpgradeClient::new(
+                client.clone(),
+                req.url.domain().map(str::to_string).zip(req.resolve),
+                !req.disable_keepalive,
+                on_connect,
+            )
+        });
+        let underlying = Self::builder(client, req)?;
+        let (underlying, raw_request) = underlying.build_split();
+        let raw_request = raw_request?;
+
+        Ok(Self {
+            underlying,
+            raw_request,
+            http3,
+            h2c_upgrade,
+            streams: None,
+        })
+    }
+
+    /// Send at most `max` requests at once over the connection of this client and its clones,
+    /// the others wait for one of them to complete
+    pub(crate) fn with_max_streams(self, max: usize) -> Self {
+        Self {
+            streams: Some(Arc::new(Semaphore::new(max))),
+            ..self
+        }
+    }
+
+    fn default_headers() -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-tool-name"),
+            HeaderValue::from_static("hb-client"),
+        );
+        headers.insert(
+            HeaderName::from_static("x-tool-version"),
+            HeaderValue::from_static(env!("CARGO_PKG_VERSION")),
+        );
+        headers
+    }
+
+    /// Client for another request sharing the connection pool of this one
+    pub(crate) fn try_with_request(&self, req: Request) -> Result<Self> {
+        let underlying = Self::builder(self.underlying.clone(), req)?;
+        let (underlying, raw_request) = underlying.build_split();
+
+        Ok(Self {
+            underlying,
+            raw_request: raw_request?,
+            http3: self.http3.clone(),
+            h2c_upgrade: self.h2c_upgrade.clone(),
+            streams: self.streams.clone(),
+        })
+    }
+
+    fn builder(underlying: reqwest::Client, req: Request) -> Result<reqwest::RequestBuilder> {
+        let mut builder = underlying
+            .request(req.method.into(), req.url)
+            .timeout(req.timeout);
+
+        if !req.body.is_empty() {
+            builder = builder.body(req.body);
+        }
+
+        for (key, value) in req.headers.iter() {
+            builder = builder.header(key, value);
+        }
+
+        if req.disable_keepalive && req.http_version == HttpVersion::Http11 {
+            builder = builder.header("Connection", "close");
+        }
+
+        if let Some((username, password)) = &req.basic_auth {
+            builder = builder.basic_auth(username, Some(password));
+        }
+
+        Ok(builder)
+    }
+
+    // Emulate the size of the header
+    // Notes: This is not the exact size, but an approximation. ex) HTTP/1.1 200 OK\r\n is not included.
+    async fn calculate_header_size(headers: &reqwest::header::HeaderMap) -> u64 {
+        let mut size = headers.iter().fold(0, |mut acc, (name, value)| {
+            acc += name.as_str().len() + 2; // Header name + ": "
+            if let Ok(v) = value.to_str() {
+                acc += v.len();
+            }
+            acc += 2; // \r\n
+            acc
+        });
+
+        // Add final \r\n that marks end of headers
+        size += 2;
+
+        size as u64
+    }
+
+    pub(crate) async fn request(self) -> Result<Response> {
+        let _stream = match &self.streams {
+            Some(streams) => Some(Arc::clone(streams).acquire_owned().await?),
+            None => None,
+        };
+
+        if let Some(http3) = &self.http3 {
+            return http3.request(self.raw_request).await;
+        }
+        if let Some(h2c_upgrade) = &self.h2c_upgrade {
+            return h2c_upgrade.request(self.raw_request).await;
+        }
+
+        Self::execute(&self.underlying, self.raw_request).await
+    }
+
+    async fn execute(client: &reqwest::Client, req: reqwest::Request) -> Result<Response> {
+        let start = Instant::now();
+        let (resp, connecting) = connect::waiting_for_connections(client.execute(req)).await;
+        let ttfb = start.elapsed().saturating_sub(connecting);
+        let resp = resp?;
+
+        let start = Instant::now();
+        let status = resp.status().as_u16();
+        let version = resp.version();
+        let headers = resp.headers().clone();
+        let header_size = Self::calculate_header_size(&headers).await;
+        let body = resp.bytes().await?;
+        let size = header_size + body.len() as u64;
+        let timing = ResponseTiming {
+            ttfb,
+            download: start.elapsed(),
+        };
+
+        Ok(Response {
+            status,
+            size,
+            version,
+            timing,
+            headers,
+            body,
+        })
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum, AsRefStr, Hash, EncodeLabelValue)]
+#[strum(serialize_all = "UPPERCASE")]
+pub enum Method {
+    Get,
+    Head,
+    Post,
+    Put,
+    Delete,
+    Options,
+    Trace,
+    Patch,
+}
+
+impl From<Method> for reqwest::Method {
+    fn from(item: Method) -> Self {
+        reqwest::Method::from_str(item.as_ref()).unwrap()
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, ValueEnum, AsRefStr)]
+pub enum HttpVersion {
+    #[clap(name = "1.1")]
+    Http11,
+    #[clap(name = "2")]
+    Http2,
+    /// Over QUIC, for https URLs only
+    #[clap(name = "3")]
+    Http3,
+}
+
+impl From<HttpVersion> for reqwest::Version {
+    fn from(item: HttpVersion) -> Self {
+        match item {
+            HttpVersion::Http11 => reqwest::Version::HTTP_11,
+            HttpVersion::Http2 => reqwest::Version::HTTP_2,
+            HttpVersion::Http3 => reqwest::Version::HTTP_3,
+        }
+    }
+}
+
+/// How HTTP/2 is agreed on with the server for `--http-version 2`
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, ValueEnum, AsRefStr)]
+#[strum(serialize_all = "kebab-case")]
+pub enum Negotiation {
+    /// ALPN for https, falling back to HTTP/1.1, and HTTP/2 right away for http
+    #[default]
+    Auto,
+    /// ALPN offering h2 only, for https URLs
+    #[clap(name = "h2-alpn")]
+    #[strum(serialize = "h2-alpn")]
+    H2Alpn,
+    /// HTTP/2 right away without asking, for http URLs
+    #[clap(name = "h2c-prior-knowledge")]
+    #[strum(serialize = "h2c-prior-knowledge")]
+    H2cPriorKnowledge,
+    /// An HTTP/1.1 request with `Upgrade: h2c` on every connection, for http URLs
+    #[clap(name = "h2c-upgrade")]
+    #[strum(serialize = "h2c-upgrade")]
+    H2cUpgrade,
+}
+
+/// Protocol version of a response as recorded in the metrics, e.g. "HTTP/2"
+pub(crate) fn version_name(version: reqwest::Version) -> &'static str {
+    match version {
+        reqwest::Version::HTTP_09 => "HTTP/0.9",
+        reqwest::Version::HTTP_10 => "HTTP/1.0",
+        reqwest::Version::HTTP_2 => "HTTP/2",
+        reqwest::Version::HTTP_3 => "HTTP/3",
+        _ => "HTTP/1.1",
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub http_version: HttpVersion,
+    pub negotiation: Negotiation,
+    pub url: url::Url,
+    pub method: Method,
+    pub headers: HashMap<String, String>,
+    pub timeout: Duration,
+    pub body: Vec<u8>,
+    pub basic_auth: Option<(String, String)>,
+    pub insecure: bool,
+    pub disable_keepalive: bool,
+    pub root_cert: Option<PathBuf>,
+    pub resolve: Option<IpAddr>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use mockito::Server;
+    use reqwest::header::{HeaderMap, HeaderName, HeaderValue};
+    use std::sync::atomic::{AtomicU64, Ordering};
+
+    #[tokio::test]
+    async fn test_calculate_header_size() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("content-type"),
+            HeaderValue::from_static("application/json"),
+        );
+        headers.insert(
+            HeaderName::from_static("content-length"),
+            HeaderValue::from_static("42"),
+        );
+        headers.insert(
+            HeaderName::from_static("x-custom-header"),
+            HeaderValue::from_static("test-value"),
+        );
+
+        let size = Client::calculate_header_size(&headers).await;
+
+        // Size breakdown:
+        // "content-type: application/json\r\n" (32)
+        // "content-length: 42\r\n" (20)
+        // "x-custom-header: test-value\r\n" (29)
+        // Final "\r\n" (2)
+        assert_eq!(size, 83);
+    }
+
+    #[tokio::test]
+    async fn test_calculate_header_size_with_empty_headers() {
+        let headers = HeaderMap::new();
+        let size = Client::calculate_header_size(&headers).await;
+
+        // Only final "\r\n"
+        assert_eq!(size, 2);
+    }
+
+    #[tokio::test]
+    async fn test_calculate_header_size_with_invalid_value() {
+        let mut headers = HeaderMap::new();
+        headers.insert(
+            HeaderName::from_static("x-binary"),
+            HeaderValue::from_bytes(&[0xFF, 0xFE, 0xFD]).unwrap(),
+        );
+
+        let size = Client::calculate_header_size(&headers).await;
+
+        // Invalid header values are ignored, only name and delimiters are counted
+        // "x-binary: \r\n" (12)
+        // Final "\r\n" (2)
+        assert_eq!(size, 14);
+    }
+
+    async fn setup_test_client(server: &Server, path: &str, method: Method) -> Result<Client> {
+        let url = server.url() + path;
+        let request = Request {
+            http_version: HttpVersion::Http11,
+            negotiation: Negotiation::Auto,
+            url: url::Url::parse(&url)?,
+            method,
+            headers: HashMap::new(),
+            timeout: Duration::from_secs(30),
+            body: Vec::new(),
+            basic_auth: None,
+            insecure: false,
+            disable_keepalive: false,
+            root_cert: None,
+            resolve: None,
+        };
+        Client::try_new(request, None)
+    }
+
+    #[tokio::test]
+    async fn test_successful_get_request() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/test")
+            .with_status(200)
+            .with_header("content-type", "text/plain")
+            .with_body("Hello, World!")
+            .create_async()
+            .await;
+
+        let client = setup_test_client(&server, "/test", Method::Get).await?;
+        let Response { status, size, .. } = client.request().await?;
+
+        assert_eq!(status, 200);
+        // header size ( "connection": "close", "content-type": "text/plain", "content-length": "13", "date": "Sun, 16 Feb 2025 04:33:05 GMT") + body size ("Hello, World!")
+        assert_eq!(size, 117);
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_not_found_request() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/notfound")
+            .with_status(404)
+            .create_async()
+            .await;
+
+        let client = setup_test_client(&server, "/notfound", Method::Get).await?;
+        let Response { status, size, .. } = client.request().await?;
+
+        assert_eq!(status, 404);
+        // header size ( "connection": "close", "content-type": "text/plain", "content-length": "0", "date": "Sun, 16 Feb 2025 04:33:05 GMT") + body size ("")
+        assert_eq!(size, 77);
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_post_request_with_body() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("POST", "/submit")
+            .match_body("test data")
+            .with_body("test data")
+            .with_status(201)
+            .create_async()
+            .await;
+
+        let request = Request {
+            http_version: HttpVersion::Http11,
+            negotiation: Negotiation::Auto,
+            url: url::Url::parse(&(server.url() + "/submit"))?,
+            method: Method::Post,
+            headers: HashMap::new(),
+            timeout: Duration::from_secs(30),
+            body: "test data".as_bytes().to_vec(),
+            basic_auth: None,
+            insecure: false,
+            disable_keepalive: false,
+            root_cert: None,
+            resolve: None,
+        };
+
+        let client = Client::try_new(request, None)?;
+        let Response { status, size, .. } = client.request().await?;
+
+        assert_eq!(status, 201);
+        // header size ( "connection": "close", "content-type": "text/plain", "content-length": "9", "date": "Sun, 16 Feb 2025 04:33:05 GMT") + body size ("test data")
+        assert_eq!(size, 86);
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_client_counts_connections() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/test")
+            .with_status(200)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let opened = Arc::new(AtomicU64::new(0));
+        let on_connect: OnConnect = {
+            let opened = Arc::clone(&opened);
+            Arc::new(move |_: &ConnectTiming| {
+                opened.fetch_add(1, Ordering::Relaxed);
+            })
+        };
+        let request = Request {
+            url: url::Url::parse(&(server.url() + "/test"))?,
+            ..client_request(&server)?
+        };
+        let client = Client::try_new(request, Some(on_connect))?.with_max_streams(1);
+        let cloned = client.clone();
+        let (first, second) = tokio::join!(client.request(), cloned.request());
+        assert_eq!(first?.status, 200);
+        assert_eq!(second?.status, 200);
+
+        // mockito closes every connection after its response
+        assert_eq!(opened.load(Ordering::Relaxed), 2);
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_client_times_connections() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/test")
+            .with_status(200)
+            .with_body("hello")
+            .create_async()
+            .await;
+
+        let timings = Arc::new(std::sync::Mutex::new(Vec::new()));
+        let on_connect: OnConnect = {
+            let timings = Arc::clone(&timings);
+            Arc::new(move |timing: &ConnectTiming| timings.lock().unwrap().push(*timing))
+        };
+        let mut url = url::Url::parse(&(server.url() + "/test"))?;
+        url.set_host(Some("localhost"))?;
+        let request = Request {
+            url,
+            ..client_request(&server)?
+        };
+        let resp = Client::try_new(request, Some(on_connect))?
+            .request()
+            .await?;
+        assert_eq!(resp.status, 200);
+        assert!(resp.timing.ttfb > Duration::ZERO);
+
+        let timings = timings.lock().unwrap().clone();
+        assert_eq!(timings.len(), 1);
+        assert!(timings[0].dns.is_some());
+        assert!(timings[0].connect.is_some());
+        assert_eq!(timings[0].tls, None);
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_client_with_request() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("DELETE", "/other")
+            .with_status(204)
+            .create_async()
+            .await;
+
+        let client = setup_test_client(&server, "/test", Method::Get).await?;
+        let request = Request {
+            url: url::Url::parse(&(server.url() + "/other"))?,
+            method: Method::Delete,
+            ..client_request(&server)?
+        };
+        let Response { status, .. } = client.try_with_request(request)?.request().await?;
+
+        assert_eq!(status, 204);
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    fn client_request(server: &Server) -> Result<Request> {
+        Ok(Request {
+            http_version: HttpVersion::Http11,
+            negotiation: Negotiation::Auto,
+            url: url::Url::parse(&server.url())?,
+            method: Method::Get,
+            headers: HashMap::new(),
+            timeout: Duration::from_secs(30),
+            body: Vec::new(),
+            basic_auth: None,
+            insecure: false,
+            disable_keepalive: false,
+            root_cert: None,
+            resolve: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn test_client_builder_headers() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/")
+            .match_header("x-tool-name", "hb-client")
+            .match_header("x-tool-version", env!("CARGO_PKG_VERSION"))
+            .with_status(200)
+            .create_async()
+            .await;
+
+        let request = Request {
+            http_version: HttpVersion::Http11,
+            negotiation: Negotiation::Auto,
+            url: url::Url::parse(&server.url())?,
+            method: Method::Get,
+            headers: HashMap::new(),
+            timeout: Duration::from_secs(30),
+            body: Vec::new(),
+            basic_auth: None,
+            insecure: false,
+            disable_keepalive: false,
+            root_cert: None,
+            resolve: None,
+        };
+
+        let client = Client::try_new(request, None)?;
+        let Response { status, .. } = client.request().await?;
+
+        assert_eq!(status, 200);
+
+        mock.assert_async().await;
+
+        Ok(())
+    }
+}
@@ -0,0 +1,422 @@
+use crate::http::{
+    Client, ConnectTiming, ErrorCategory, OnConnect, Request, Response, ResponseTiming, tls,
+    user_agent,
+};
+use anyhow::{Result, bail};
+use bytes::{BufMut, Bytes, BytesMut};
+use h3::client::SendRequest;
+use quinn::crypto::rustls::QuicClientConfig;
+use reqwest::header::{HeaderValue, USER_AGENT};
+use std::collections::HashMap;
+use std::fmt;
+use std::net::{IpAddr, Ipv4Addr, Ipv6Addr, SocketAddr};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::sync::Mutex;
+use tracing::*;
+
+/// Failures of HTTP/3 requests, told apart for the error phase of the report
+#[derive(Debug)]
+pub enum Http3Error {
+    /// The host could not be resolved
+    Dns(String),
+    /// No UDP socket could be opened
+    Connect(String),
+    /// The QUIC handshake did not complete, e.g. the certificate was rejected or no server answered
+    Handshake(String),
+    /// The request or its response failed on an established connection
+    Request(String),
+    Timeout,
+}
+
+impl Http3Error {
+    pub(crate) fn phase(&self) -> &'static str {
+        match self {
+            Http3Error::Dns(_) | Http3Error::Connect(_) => "connect",
+            Http3Error::Handshake(_) => "quic_handshake",
+            Http3Error::Request(_) => "request",
+            Http3Error::Timeout => "timeout",
+        }
+    }
+
+    pub(crate) fn category(&self) -> ErrorCategory {
+        match self {
+            Http3Error::Dns(_) => ErrorCategory::Dns,
+            Http3Error::Connect(_) => ErrorCategory::Connect,
+            Http3Error::Handshake(_) => ErrorCategory::QuicHandshake,
+            Http3Error::Request(_) => ErrorCategory::Request,
+            Http3Error::Timeout => ErrorCategory::Timeout,
+        }
+    }
+}
+
+impl fmt::Display for Http3Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Http3Error::Dns(message) => write!(f, "DNS lookup failed: {}", message),
+            Http3Error::Connect(message) => write!(f, "Failed to connect: {}", message),
+            Http3Error::Handshake(message) => write!(f, "QUIC handshake failed: {}", message),
+            Http3Error::Request(message) => write!(f, "HTTP/3 request failed: {}", message),
+            Http3Error::Timeout => write!(f, "HTTP/3 request timed out"),
+        }
+    }
+}
+
+impl std::error::Error for Http3Error {}
+
+/// Sends the requests of every clone over a single QUIC connection per host and port, opened on
+/// the first request to it
+#[derive(Clone)]
+pub(crate) struct Http3Client {
+    config: quinn::ClientConfig,
+    // --resolve applies to the domain of the URL of the run only, like with reqwest
+    resolve: Option<(String, IpAddr)>,
+    keepalive: bool,
+    on_connect: Option<OnConnect>,
+    connections: Arc<Mutex<HashMap<(String, u16), Connection>>>,
+}
+
+#[derive(Clone)]
+struct Connection {
+    // Owns the UDP socket of the connection
+    _endpoint: quinn::Endpoint,
+    quic: quinn::Connection,
+    send_request: SendRequest<h3_quinn::OpenStreams, Bytes>,
+}
+
+impl fmt::Debug for Http3Client {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("Http3Client")
+            .field("resolve", &self.resolve)
+            .field("keepalive", &self.keepalive)
+            .finish_non_exhaustive()
+    }
+}
+
+impl Http3Client {
+    pub(crate) fn try_new(req: &Request, on_connect: Option<OnConnect>) -> Result<Self> {
+        if req.url.scheme() != "https" {
+            bail!("HTTP/3 requires an https URL: {}", req.url);
+        }
+
+        let mut tls = tls::client_config(req, &[&rustls::version::TLS13])?;
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+
+        let config = quinn::ClientConfig::new(Arc::new(QuicClientConfig::try_from(tls)?));
+        Ok(Self {
+            config,
+            resolve: req.url.domain().map(str::to_string).zip(req.resolve),
+            keepalive: !req.disable_keepalive,
+            on_connect,
+            connections: Arc::default(),
+        })
+    }
+
+    pub(crate) async fn request(&self, req: reqwest::Request) -> Result<Response> {
+        match req.timeout().copied() {
+            Some(timeout) => tokio::time::timeout(timeout, self.send(req))
+                .await
+                .map_err(|_| Http3Error::Timeout)?,
+            None => self.send(req).await,
+        }
+    }
+
+    async fn send(&self, req: reqwest::Request) -> Result<Response> {
+        let connection = self.connection(req.url()).await?;
+        let request_err = |err: h3::error::StreamError| Http3Error::Request(err.to_string());
+
+        let mut head = http::Request::builder()
+            .method(req.method().clone())
+            .uri(req.url().as_str())
+            .header(USER_AGENT, HeaderValue::from_str(&user_agent())?);
+        for (name, value) in Client::default_headers().iter().chain(req.headers()) {
+            head = head.header(name, value);
+        }
+        let body = req
+            .body()
+            .and_then(reqwest::Body::as_bytes)
+            .map(Bytes::copy_from_slice)
+            .unwrap_or_default();
+
+        let start = Instant::now();
+        let mut send_request = connection.send_request.clone();
+        let mut stream = send_request
+            .send_request(head.body(())?)
+            .await
+            .map_err(request_err)?;
+        if !body.is_empty() {
+            stream.send_data(body).await.map_err(request_err)?;
+        }
+        stream.finish().await.map_err(request_err)?;
+
+        let resp = stream.recv_response().await.map_err(request_err)?;
+        let ttfb = start.elapsed();
+
+        let start = Instant::now();
+        let mut body = BytesMut::new();
+        while let Some(chunk) = stream.recv_data().await.map_err(request_err)? {
+            body.put(chunk);
+        }
+        let download = start.elapsed();
+
+        if !self.keepalive {
+            connection.quic.close(0u32.into(), b"");
+        }
+
+        let headers = resp.headers().clone();
+        let header_size = Client::calculate_header_size(&headers).await;
+        Ok(Response {
+            status: resp.status().as_u16(),
+            size: header_size + body.len() as u64,
+            version: http::Version::HTTP_3,
+            timing: ResponseTiming { ttfb, download },
+            headers,
+            body: body.freeze(),
+        })
+    }
+
+    // The shared connection to the host of `url`, opened again once it is closed, e.g. by an
+    // idle timeout
+    async fn connection(&self, url: &url::Url) -> Result<Connection, Http3Error> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| Http3Error::Connect(format!("No host in {}", url)))?;
+        let port = url.port_or_known_default().unwrap_or(443);
+        if !self.keepalive {
+            return self.connect(host, port).await;
+        }
+
+        let mut connections = self.connections.lock().await;
+        let origin = (host.to_string(), port);
+        if let Some(connection) = connections.get(&origin)
+            && connection.quic.close_reason().is_none()
+        {
+            return Ok(connection.clone());
+        }
+        let opened = self.connect(host, port).await?;
+        connections.insert(origin, opened.clone());
+        Ok(opened)
+    }
+
+    async fn connect(&self, host: &str, port: u16) -> Result<Connection, Http3Error> {
+        let connect_err = |err: &dyn fmt::Display| Http3Error::Connect(err.to_string());
+        let handshake_err = |err: &dyn fmt::Display| Http3Error::Handshake(err.to_string());
+
+        let start = Instant::now();
+        let (addr, dns) = match &self.resolve {
+            Some((domain, ip)) if domain == host => (SocketAddr::new(*ip, port), None),
+            _ => {
+                let addr = tokio::net::lookup_host((host, port))
+                    .await
+                    .map_err(|err| Http3Error::Dns(err.to_string()))?
+                    .next()
+                    .ok_or_else(|| Http3Error::Dns(format!("No address for {}", host)))?;
+                (addr, Some(start.elapsed()))
+            }
+        };
+
+        let local = match addr {
+            SocketAddr::V4(_) => SocketAddr::new(Ipv4Addr::UNSPECIFIED.into(), 0),
+            SocketAddr::V6(_) => SocketAddr::new(Ipv6Addr::UNSPECIFIED.into(), 0),
+        };
+        let endpoint = quinn::Endpoint::client(local).map_err(|err| connect_err(&err))?;
+
+        // URLs keep brackets around IPv6 addresses, which are no valid server names
+        let server_name = host.trim_start_matches('[').trim_end_matches(']');
+        let start = Instant::now();
+        let quic = endpoint
+            .connect_with(self.config.clone(), addr, server_name)
+            .map_err(|err| handshake_err(&err))?
+            .await
+            .map_err(|err| handshake_err(&err))?;
+        // QUIC opens the connection in its TLS handshake, there is no TCP connect
+        if let Some(on_connect) = &self.on_connect {
+            on_connect(&ConnectTiming {
+                dns,
+                connect: None,
+                tls: Some(start.elapsed()),
+            });
+        }
+
+        // The settings of both ends are exchanged before the first request
+        let (mut driver, send_request) = h3::client::new(h3_quinn::Connection::new(quic.clone()))
+            .await
+            .map_err(|err| handshake_err(&err))?;
+        tokio::spawn(async move {
+            let err = driver.wait_idle().await;
+            debug!("HTTP/3 connection closed: {}", err);
+        });
+
+        Ok(Connection {
+            _endpoint: endpoint,
+            quic,
+            send_request,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{Client, HttpVersion, Method, Negotiation, error_phase};
+    use quinn::crypto::rustls::QuicServerConfig;
+    use rustls::pki_types::PrivatePkcs8KeyDer;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+    use std::time::Duration;
+
+    // An HTTP/3 server answering "Hello, world!" with a certificate for localhost, in PEM
+    fn server() -> Result<(SocketAddr, String)> {
+        server_answering(b"Hello, world!")
+    }
+
+    fn server_answering(body: &'static [u8]) -> Result<(SocketAddr, String)> {
+        let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+        let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+        let provider = Arc::new(rustls::crypto::ring::default_provider());
+        let mut tls = rustls::ServerConfig::builder_with_provider(provider)
+            .with_protocol_versions(&[&rustls::version::TLS13])?
+            .with_no_client_auth()
+            .with_single_cert(vec![certified.cert.der().clone()], key.into())?;
+        tls.alpn_protocols = vec![b"h3".to_vec()];
+
+        let config = quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls)?));
+        let endpoint = quinn::Endpoint::server(config, "127.0.0.1:0".parse()?)?;
+        let addr = endpoint.local_addr()?;
+
+        tokio::spawn(async move {
+            while let Some(incoming) = endpoint.accept().await {
+                tokio::spawn(async move {
+                    let Ok(quic) = incoming.await else { return };
+                    let mut conn =
+                        h3::server::Connection::<_, Bytes>::new(h3_quinn::Connection::new(quic))
+                            .await
+                            .unwrap();
+                    while let Ok(Some(resolver)) = conn.accept().await {
+                        let (_, mut stream) = resolver.resolve_request().await.unwrap();
+                        let resp = http::Response::builder().status(200).body(()).unwrap();
+                        stream.send_response(resp).await.unwrap();
+                        stream.send_data(Bytes::from_static(body)).await.unwrap();
+                        stream.finish().await.unwrap();
+                    }
+                });
+            }
+        });
+
+        Ok((addr, certified.cert.pem()))
+    }
+
+    fn request(url: String) -> Request {
+        Request {
+            http_version: HttpVersion::Http3,
+            negotiation: Negotiation::Auto,
+            url: url::Url::parse(&url).unwrap(),
+            method: Method::Get,
+            headers: HashMap::new(),
+            timeout: Duration::from_secs(5),
+            body: Vec::new(),
+            basic_auth: None,
+            insecure: false,
+            disable_keepalive: false,
+            root_cert: None,
+            resolve: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_http3_request() -> Result<()> {
+        let (addr, pem) = server()?;
+        let root_cert = std::env::temp_dir().join(format!("hb-h3-{}.pem", std::process::id()));
+        std::fs::write(&root_cert, pem)?;
+
+        let connections = Arc::new(AtomicU64::new(0));
+        let on_connect: OnConnect = {
+            let connections = Arc::clone(&connections);
+            Arc::new(move |_: &ConnectTiming| {
+                connections.fetch_add(1, Relaxed);
+            })
+        };
+
+        // Trusted through --root-cert, and sent to the server by --resolve
+        let client = Client::try_new(
+            Request {
+                root_cert: Some(root_cert.clone()),
+                resolve: Some(addr.ip()),
+                ..request(format!("https://localhost:{}/", addr.port()))
+            },
+            Some(Arc::clone(&on_connect)),
+        )?;
+        for _ in 0..2 {
+            let resp = client.clone().request().await?;
+            assert_eq!(resp.status, 200);
+            assert_eq!(resp.body, "Hello, world!");
+        }
+        std::fs::remove_file(&root_cert)?;
+        assert_eq!(connections.load(Relaxed), 1);
+
+        // A connection per request without keep-alive
+        let client = Client::try_new(
+            Request {
+                insecure: true,
+                disable_keepalive: true,
+                ..request(format!("https://{}/", addr))
+            },
+            Some(on_connect),
+        )?;
+        for _ in 0..2 {
+            assert_eq!(client.clone().request().await?.status, 200);
+        }
+        assert_eq!(connections.load(Relaxed), 3);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_http3_hosts() -> Result<()> {
+        let (first, _) = server_answering(b"first")?;
+        let (second, _) = server_answering(b"second")?;
+
+        let connections = Arc::new(AtomicU64::new(0));
+        let on_connect: OnConnect = {
+            let connections = Arc::clone(&connections);
+            Arc::new(move |_: &ConnectTiming| {
+                connections.fetch_add(1, Relaxed);
+            })
+        };
+
+        // Steps of a scenario share the client of the first one, whatever host they go to
+        let insecure = |url: String| Request {
+            insecure: true,
+            ..request(url)
+        };
+        let client = Client::try_new(insecure(format!("https://{}/", first)), Some(on_connect))?;
+        let other =
+            client.try_with_request(insecure(format!("https://localhost:{}/", second.port())))?;
+        for _ in 0..2 {
+            assert_eq!(client.clone().request().await?.body, "first");
+            assert_eq!(other.clone().request().await?.body, "second");
+        }
+        // A connection for each host, reused by the requests to it
+        assert_eq!(connections.load(Relaxed), 2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_http3_handshake_error() -> Result<()> {
+        let (addr, _) = server()?;
+
+        // The self-signed certificate is not trusted
+        let client = Client::try_new(
+            Request {
+                resolve: Some(addr.ip()),
+                ..request(format!("https://localhost:{}/", addr.port()))
+            },
+            None,
+        )?;
+        let err = client.request().await.unwrap_err();
+        assert_eq!(error_phase(&err), "quic_handshake");
+        assert!(err.to_string().starts_with("QUIC handshake failed"));
+
+        assert!(Client::try_new(request("http://localhost/".to_string()), None).is_err());
+        Ok(())
+    }
+}
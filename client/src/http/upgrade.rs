@@ -0,0 +1,508 @@
+use crate::http::category::{h2_category, io_category};
+use crate::http::{
+    Client, ConnectTiming, ErrorCategory, OnConnect, Response, ResponseTiming, user_agent,
+};
+use anyhow::Result;
+use bytes::{BufMut, Bytes, BytesMut};
+use h2::client::SendRequest;
+use reqwest::header::{HeaderValue, USER_AGENT};
+use std::collections::HashMap;
+use std::fmt;
+use std::io;
+use std::net::{IpAddr, SocketAddr};
+use std::sync::Arc;
+use std::time::Instant;
+use tokio::io::{AsyncBufReadExt, AsyncWriteExt, BufReader};
+use tokio::net::TcpStream;
+use tokio::sync::Mutex;
+use tokio::task::JoinHandle;
+use tracing::*;
+
+// SETTINGS frame payload sent in the HTTP2-Settings header, base64url encoded:
+// SETTINGS_ENABLE_PUSH (0x2) = 0
+const HTTP2_SETTINGS: &str = "AAIAAAAA";
+
+/// Failures of requests sent with `--h2-negotiation h2c-upgrade`, told apart for the error phase
+/// of the report
+#[derive(Debug)]
+pub enum UpgradeError {
+    /// The host could not be resolved
+    Dns(String),
+    /// No TCP connection could be opened
+    Connect(io::Error),
+    /// The upgrade request got no answer or the HTTP/2 handshake after it failed
+    Upgrade(String),
+    /// The request or its response failed on an upgraded connection
+    Request(h2::Error),
+    Timeout,
+}
+
+impl UpgradeError {
+    pub(crate) fn phase(&self) -> &'static str {
+        match self {
+            UpgradeError::Dns(_) | UpgradeError::Connect(_) => "connect",
+            UpgradeError::Upgrade(_) => "h2c_upgrade",
+            UpgradeError::Request(_) => "request",
+            UpgradeError::Timeout => "timeout",
+        }
+    }
+
+    pub(crate) fn category(&self) -> ErrorCategory {
+        match self {
+            UpgradeError::Dns(_) => ErrorCategory::Dns,
+            UpgradeError::Connect(err) => io_category(err).unwrap_or(ErrorCategory::Connect),
+            UpgradeError::Upgrade(_) => ErrorCategory::H2cUpgrade,
+            UpgradeError::Request(err) => h2_category(err).unwrap_or(ErrorCategory::Request),
+            UpgradeError::Timeout => ErrorCategory::Timeout,
+        }
+    }
+}
+
+impl fmt::Display for UpgradeError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            UpgradeError::Dns(message) => write!(f, "DNS lookup failed: {}", message),
+            UpgradeError::Connect(err) => write!(f, "Failed to connect: {}", err),
+            UpgradeError::Upgrade(message) => write!(f, "h2c upgrade failed: {}", message),
+            UpgradeError::Request(err) => write!(f, "HTTP/2 request failed: {}", err),
+            UpgradeError::Timeout => write!(f, "HTTP/2 request timed out"),
+        }
+    }
+}
+
+impl std::error::Error for UpgradeError {}
+
+/// Upgrades a cleartext HTTP/1.1 connection to HTTP/2 with `Upgrade: h2c` and sends the
+/// requests of every clone over it, one connection per host and port. Once a server declines,
+/// the requests to it are sent over HTTP/1.1.
+#[derive(Clone)]
+pub(crate) struct H2cUpgradeClient {
+    // Sends over HTTP/1.1 to the servers that declined the upgrade
+    fallback: reqwest::Client,
+    // --resolve applies to the domain of the URL of the run only, like with reqwest
+    resolve: Option<(String, IpAddr)>,
+    keepalive: bool,
+    on_connect: Option<OnConnect>,
+    states: Arc<Mutex<HashMap<(String, u16), Upgrade>>>,
+}
+
+#[derive(Clone)]
+enum Upgrade {
+    Upgraded(Connection),
+    Declined,
+}
+
+impl Upgrade {
+    fn send_request(&self) -> Option<SendRequest<Bytes>> {
+        match self {
+            Upgrade::Upgraded(connection) => Some(connection.send_request.clone()),
+            Upgrade::Declined => None,
+        }
+    }
+}
+
+#[derive(Clone)]
+struct Connection {
+    send_request: SendRequest<Bytes>,
+    // Finished once the connection is closed
+    driver: Arc<JoinHandle<()>>,
+}
+
+impl fmt::Debug for H2cUpgradeClient {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("H2cUpgradeClient")
+            .field("resolve", &self.resolve)
+            .field("keepalive", &self.keepalive)
+            .finish_non_exhaustive()
+    }
+}
+
+impl H2cUpgradeClient {
+    pub(crate) fn new(
+        fallback: reqwest::Client,
+        resolve: Option<(String, IpAddr)>,
+        keepalive: bool,
+        on_connect: Option<OnConnect>,
+    ) -> Self {
+        Self {
+            fallback,
+            resolve,
+            keepalive,
+            on_connect,
+            states: Arc::default(),
+        }
+    }
+
+    pub(crate) async fn request(&self, req: reqwest::Request) -> Result<Response> {
+        match req.timeout().copied() {
+            Some(timeout) => tokio::time::timeout(timeout, self.send(req))
+                .await
+                .map_err(|_| UpgradeError::Timeout)?,
+            None => self.send(req).await,
+        }
+    }
+
+    async fn send(&self, req: reqwest::Request) -> Result<Response> {
+        let Some(send_request) = self.connection(req.url()).await? else {
+            return Client::execute(&self.fallback, req).await;
+        };
+        let request_err = UpgradeError::Request;
+
+        let mut head = http::Request::builder()
+            .method(req.method().clone())
+            .uri(req.url().as_str())
+            .header(USER_AGENT, HeaderValue::from_str(&user_agent())?);
+        for (name, value) in Client::default_headers().iter().chain(req.headers()) {
+            head = head.header(name, value);
+        }
+        let body = req
+            .body()
+            .and_then(reqwest::Body::as_bytes)
+            .map(Bytes::copy_from_slice)
+            .unwrap_or_default();
+
+        let start = Instant::now();
+        let mut send_request = send_request.ready().await.map_err(request_err)?;
+        let (resp, mut stream) = send_request
+            .send_request(head.body(())?, body.is_empty())
+            .map_err(request_err)?;
+        if !body.is_empty() {
+            stream.send_data(body, true).map_err(request_err)?;
+        }
+
+        let resp = resp.await.map_err(request_err)?;
+        let ttfb = start.elapsed();
+
+        let start = Instant::now();
+        let (head, mut recv) = resp.into_parts();
+        let mut body = BytesMut::new();
+        while let Some(chunk) = recv.data().await {
+            let chunk = chunk.map_err(request_err)?;
+            let _ = recv.flow_control().release_capacity(chunk.len());
+            body.put(chunk);
+        }
+
+        let header_size = Client::calculate_header_size(&head.headers).await;
+        Ok(Response {
+            status: head.status.as_u16(),
+            size: header_size + body.len() as u64,
+            version: head.version,
+            timing: ResponseTiming {
+                ttfb,
+                download: start.elapsed(),
+            },
+            headers: head.headers,
+            body: body.freeze(),
+        })
+    }
+
+    // The shared connection to the host of `url`, upgraded again once it is closed. None once
+    // the server declined.
+    async fn connection(&self, url: &url::Url) -> Result<Option<SendRequest<Bytes>>, UpgradeError> {
+        let host = url
+            .host_str()
+            .ok_or_else(|| UpgradeError::Dns(format!("No host in {}", url)))?;
+        let origin = (host.to_string(), url.port_or_known_default().unwrap_or(80));
+        if !self.keepalive {
+            if matches!(
+                self.states.lock().await.get(&origin),
+                Some(Upgrade::Declined)
+            ) {
+                return Ok(None);
+            }
+            let upgrade = self.connect(url).await?;
+            if let Upgrade::Declined = upgrade {
+                self.states.lock().await.insert(origin, Upgrade::Declined);
+            }
+            return Ok(upgrade.send_request());
+        }
+
+        let mut states = self.states.lock().await;
+        match states.get(&origin) {
+            Some(Upgrade::Upgraded(connection)) if connection.driver.is_finished() => {}
+            Some(upgrade) => return Ok(upgrade.send_request()),
+            None => {}
+        }
+        let upgrade = self.connect(url).await?;
+        let send_request = upgrade.send_request();
+        states.insert(origin, upgrade);
+        Ok(send_request)
+    }
+
+    async fn connect(&self, url: &url::Url) -> Result<Upgrade, UpgradeError> {
+        let upgrade_err = |err: &dyn fmt::Display| UpgradeError::Upgrade(err.to_string());
+
+        let host = url
+            .host_str()
+            .ok_or_else(|| UpgradeError::Dns(format!("No host in {}", url)))?;
+        let port = url.port_or_known_default().unwrap_or(80);
+        let start = Instant::now();
+        let (addr, dns) = match &self.resolve {
+            Some((domain, ip)) if domain == host => (SocketAddr::new(*ip, port), None),
+            _ => {
+                // URLs keep brackets around IPv6 addresses
+                let host = host.trim_start_matches('[').trim_end_matches(']');
+                let addr = tokio::net::lookup_host((host, port))
+                    .await
+                    .map_err(|err| UpgradeError::Dns(err.to_string()))?
+                    .next()
+                    .ok_or_else(|| UpgradeError::Dns(format!("No address for {}", host)))?;
+                (addr, Some(start.elapsed()))
+            }
+        };
+
+        let start = Instant::now();
+        let tcp = TcpStream::connect(addr)
+            .await
+            .map_err(UpgradeError::Connect)?;
+        if let Some(on_connect) = &self.on_connect {
+            on_connect(&ConnectTiming {
+                dns,
+                connect: Some(start.elapsed()),
+                tls: None,
+            });
+        }
+
+        // Asked with OPTIONS, which has no body to send before the HTTP/2 frames
+        let path = &url[url::Position::BeforePath..url::Position::AfterQuery];
+        let authority = &url[url::Position::BeforeHost..url::Position::AfterPort];
+        let upgrade = format!(
+            "OPTIONS {} HTTP/1.1\r\nHost: {}\r\nUser-Agent: {}\r\n\
+             Connection: Upgrade, HTTP2-Settings\r\nUpgrade: h2c\r\nHTTP2-Settings: {}\r\n\r\n",
+            path,
+            authority,
+            user_agent(),
+            HTTP2_SETTINGS,
+        );
+        let mut io = BufReader::new(tcp);
+        io.write_all(upgrade.as_bytes())
+            .await
+            .map_err(|err| upgrade_err(&err))?;
+
+        // Read up to the end of the head, the frames of the server follow right after it
+        let mut status_line = String::new();
+        io.read_line(&mut status_line)
+            .await
+            .map_err(|err| upgrade_err(&err))?;
+        loop {
+            let mut line = String::new();
+            match io.read_line(&mut line).await {
+                Ok(0) => return Err(upgrade_err(&"connection closed during the upgrade")),
+                Ok(_) if line.trim_end().is_empty() => break,
+                Ok(_) => {}
+                Err(err) => return Err(upgrade_err(&err)),
+            }
+        }
+
+        let status = status_line.split_whitespace().nth(1).unwrap_or_default();
+        if status != "101" {
+            debug!("h2c upgrade declined: {}", status_line.trim_end());
+            return Ok(Upgrade::Declined);
+        }
+
+        // The response to the upgrade request comes on stream 1, so the requests start at 3
+        let (send_request, connection) = h2::client::Builder::new()
+            .initial_stream_id(3)
+            .handshake(io)
+            .await
+            .map_err(|err| upgrade_err(&err))?;
+        let driver = tokio::spawn(async move {
+            if let Err(err) = connection.await {
+                debug!("h2c connection closed: {}", err);
+            }
+        });
+
+        Ok(Upgrade::Upgraded(Connection {
+            send_request,
+            driver: Arc::new(driver),
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{HttpVersion, Method, Negotiation, Request};
+    use mockito::Server;
+    use std::collections::HashMap;
+    use std::sync::atomic::{AtomicU64, Ordering::Relaxed};
+    use std::time::Duration;
+    use tokio::io::AsyncReadExt;
+    use tokio::net::TcpListener;
+
+    // Accepts the upgrade and answers "Hello, world!" over HTTP/2. Unlike a real server it does
+    // not answer the upgrade request on stream 1, which the client ignores anyway.
+    async fn h2c_server() -> SocketAddr {
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            while let Ok((tcp, _)) = listener.accept().await {
+                tokio::spawn(async move {
+                    let mut io = BufReader::new(tcp);
+                    let mut head = String::new();
+                    while !head.ends_with("\r\n\r\n") {
+                        head.push(io.read_u8().await.unwrap() as char);
+                    }
+                    assert!(head.contains("Upgrade: h2c\r\n"));
+                    io.write_all(
+                        b"HTTP/1.1 101 Switching Protocols\r\nConnection: Upgrade\r\nUpgrade: h2c\r\n\r\n",
+                    )
+                    .await
+                    .unwrap();
+
+                    let mut conn = h2::server::handshake(io).await.unwrap();
+                    while let Some(Ok((_, mut respond))) = conn.accept().await {
+                        let resp = http::Response::new(());
+                        let mut stream = respond.send_response(resp, false).unwrap();
+                        stream
+                            .send_data(Bytes::from_static(b"Hello, world!"), true)
+                            .unwrap();
+                    }
+                });
+            }
+        });
+        addr
+    }
+
+    fn request(url: &str) -> Request {
+        Request {
+            http_version: HttpVersion::Http2,
+            negotiation: Negotiation::H2cUpgrade,
+            url: url::Url::parse(url).unwrap(),
+            method: Method::Get,
+            headers: HashMap::new(),
+            timeout: Duration::from_secs(5),
+            body: Vec::new(),
+            basic_auth: None,
+            insecure: false,
+            disable_keepalive: false,
+            root_cert: None,
+            resolve: None,
+        }
+    }
+
+    #[tokio::test]
+    async fn test_h2c_upgrade() -> Result<()> {
+        let addr = h2c_server().await;
+        let opened = Arc::new(AtomicU64::new(0));
+        let on_connect: OnConnect = {
+            let opened = Arc::clone(&opened);
+            Arc::new(move |_: &ConnectTiming| {
+                opened.fetch_add(1, Relaxed);
+            })
+        };
+
+        let client = Client::try_new(request(&format!("http://{}/", addr)), Some(on_connect))?;
+        for _ in 0..3 {
+            let resp = client.clone().request().await?;
+            assert_eq!(resp.status, 200);
+            assert_eq!(resp.version, http::Version::HTTP_2);
+            assert_eq!(resp.body, "Hello, world!");
+        }
+        // Every request went over the one upgraded connection
+        assert_eq!(opened.load(Relaxed), 1);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_auto_without_upgrade() -> Result<()> {
+        // Speaks HTTP/2 right away, like a server that takes prior knowledge
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (tcp, _) = listener.accept().await.unwrap();
+            let mut conn = h2::server::handshake(tcp).await.unwrap();
+            while let Some(Ok((_, mut respond))) = conn.accept().await {
+                respond
+                    .send_response(http::Response::new(()), true)
+                    .unwrap();
+            }
+        });
+
+        let req = Request {
+            negotiation: Negotiation::Auto,
+            ..request(&format!("http://{}/", addr))
+        };
+        let client = Client::try_new(req, None)?;
+        assert!(client.h2c_upgrade.is_none());
+        let resp = client.request().await?;
+        assert_eq!(resp.status, 200);
+        assert_eq!(resp.version, http::Version::HTTP_2);
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_h2c_upgrade_declined() -> Result<()> {
+        let mut server = Server::new_async().await;
+        let mock = server
+            .mock("GET", "/")
+            .with_status(200)
+            .expect(2)
+            .create_async()
+            .await;
+
+        let client = Client::try_new(request(&server.url()), None)?;
+        for _ in 0..2 {
+            let resp = client.clone().request().await?;
+            assert_eq!(resp.status, 200);
+            assert_eq!(resp.version, http::Version::HTTP_11);
+        }
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_h2c_upgrade_hosts() -> Result<()> {
+        let mut declining = Server::new_async().await;
+        let mock = declining
+            .mock("GET", "/")
+            .with_status(200)
+            .expect(2)
+            .create_async()
+            .await;
+        let upgrading = h2c_server().await;
+
+        // Steps of a scenario share the client of the first one, whatever host they go to
+        let client = Client::try_new(request(&declining.url()), None)?;
+        let other = client.try_with_request(request(&format!("http://{}/", upgrading)))?;
+        for _ in 0..2 {
+            let resp = client.clone().request().await?;
+            assert_eq!(resp.version, http::Version::HTTP_11);
+            let resp = other.clone().request().await?;
+            assert_eq!(resp.version, http::Version::HTTP_2);
+            assert_eq!(resp.body, "Hello, world!");
+        }
+        mock.assert_async().await;
+        Ok(())
+    }
+
+    #[tokio::test]
+    async fn test_h2c_upgrade_connect_error() {
+        // Nothing listens on the port once the listener is dropped
+        let addr = TcpListener::bind("127.0.0.1:0")
+            .await
+            .unwrap()
+            .local_addr()
+            .unwrap();
+        let client = Client::try_new(request(&format!("http://{}/", addr)), None).unwrap();
+        let err = client.request().await.unwrap_err();
+        assert_eq!(crate::http::error_phase(&err), "connect");
+        assert_eq!(
+            crate::http::error_category(&err),
+            ErrorCategory::ConnectionRefused
+        );
+
+        // The server closes the connection without answering the upgrade
+        let listener = TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move {
+            let (mut tcp, _) = listener.accept().await.unwrap();
+            let mut buf = [0; 1024];
+            let _ = tcp.read(&mut buf).await;
+        });
+        let client = Client::try_new(request(&format!("http://{}/", addr)), None).unwrap();
+        let err = client.request().await.unwrap_err();
+        assert_eq!(crate::http::error_phase(&err), "h2c_upgrade");
+        assert_eq!(crate::http::error_category(&err), ErrorCategory::H2cUpgrade);
+    }
+}
@@ -1,6 +1,18 @@
 pub mod bench;
+pub mod check;
+pub mod control;
+pub mod error_log;
+pub mod extract;
+pub mod feeder;
+pub mod histogram;
 pub mod http;
+pub mod limiter;
 pub mod otlp;
+pub mod profile;
+pub mod replay;
 pub mod reporter;
+pub mod scenario;
 pub mod stats;
+pub mod template;
+pub mod threshold;
 pub mod time;
@@ -0,0 +1,254 @@
+use crate::profile::{LoadProfile, StageUnit};
+use anyhow::{Result, bail};
+use std::sync::atomic::{AtomicU64, Ordering::AcqRel, Ordering::Acquire, Ordering::Release};
+use std::time::Duration;
+use tokio::time::Instant;
+
+/// Global rate limiter shared by all clients, based on the generic cell rate algorithm (GCRA)
+///
+/// Every reservation advances a single theoretical arrival time (TAT) by the emission interval,
+/// so the n-th request is always scheduled at `origin + n / rps` and the aggregate rate does not
+/// drift with the number of clients or with timer granularity.
+/// Callers that fall behind the schedule receive slots in the past and proceed immediately,
+/// which keeps the intended send times intact for latency correction.
+/// With a load profile the emission interval follows the stage targets instead.
+/// A constant rate can be changed while the limiter is in use, the next slots follow the new one.
+#[derive(Debug)]
+pub struct RateLimiter {
+    origin: Instant,
+    schedule: Schedule,
+    // Seconds from origin stored as f64 bits, infinite once the schedule has ended
+    tat: AtomicU64,
+}
+
+#[derive(Debug)]
+enum Schedule {
+    // Seconds stored as f64 bits
+    Constant { emission_interval: AtomicU64 },
+    Profile(LoadProfile),
+}
+
+impl Schedule {
+    fn next(&self, tat: f64) -> f64 {
+        match self {
+            Schedule::Constant { emission_interval } => {
+                tat + f64::from_bits(emission_interval.load(Acquire))
+            }
+            Schedule::Profile(profile) => profile.next_arrival(tat).unwrap_or(f64::INFINITY),
+        }
+    }
+}
+
+impl RateLimiter {
+    pub fn try_new(rps: u64) -> Result<Self> {
+        if rps == 0 {
+            bail!("RPS must be greater than zero");
+        }
+
+        let emission_interval = AtomicU64::new((1.0 / rps as f64).to_bits());
+        Ok(Self::with_schedule(Schedule::Constant {
+            emission_interval,
+        }))
+    }
+
+    /// Change a constant rate, the schedule of a load profile cannot be changed
+    pub fn set_rps(&self, rps: u64) -> Result<()> {
+        if rps == 0 {
+            bail!("RPS must be greater than zero");
+        }
+        let Schedule::Constant { emission_interval } = &self.schedule else {
+            bail!("The rate of a load profile cannot be changed");
+        };
+        emission_interval.store((1.0 / rps as f64).to_bits(), Release);
+        Ok(())
+    }
+
+    /// Move the schedule up to now, e.g. after a pause, so that the slots missed meanwhile
+    /// are skipped instead of being sent in a burst
+    pub fn catch_up(&self) {
+        let now = self.origin.elapsed().as_secs_f64();
+        // An ended schedule stays infinite
+        let _ = self.tat.fetch_update(AcqRel, Acquire, |tat| {
+            (f64::from_bits(tat) < now).then_some(now.to_bits())
+        });
+    }
+
+    pub fn try_from_profile(profile: LoadProfile) -> Result<Self> {
+        if profile.unit() != StageUnit::Rps {
+            bail!("Rate limiter requires a profile in rps");
+        }
+
+        Ok(Self::with_schedule(Schedule::Profile(profile)))
+    }
+
+    fn with_schedule(schedule: Schedule) -> Self {
+        Self {
+            origin: Instant::now(),
+            schedule,
+            tat: AtomicU64::new(0.0_f64.to_bits()),
+        }
+    }
+
+    /// Reserve the next slot without waiting for it, None once the schedule has ended
+    pub fn reserve(&self) -> Option<Instant> {
+        let advance = |tat: u64| Some(self.schedule.next(f64::from_bits(tat)).to_bits());
+        // The closure always returns Some, so the update never fails
+        let tat = match self.tat.fetch_update(AcqRel, Acquire, advance) {
+            Ok(tat) | Err(tat) => f64::from_bits(tat),
+        };
+
+        tat.is_finite()
+            .then(|| self.origin + Duration::from_secs_f64(tat))
+    }
+
+    /// Wait for the next slot and return its intended send time, None once the schedule has ended
+    pub async fn acquire(&self) -> Option<Instant> {
+        let slot = self.reserve()?;
+        tokio::time::sleep_until(slot).await;
+        Some(slot)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::profile::Stage;
+    use std::sync::Arc;
+
+    // Count the acquisitions made by `clients` tasks within `window`
+    async fn achieved(rps: u64, clients: usize, window: Duration) -> u64 {
+        let limiter = Arc::new(RateLimiter::try_new(rps).unwrap());
+        let deadline = Instant::now() + window;
+
+        let mut handles = Vec::new();
+        for _ in 0..clients {
+            let limiter = Arc::clone(&limiter);
+            handles.push(tokio::spawn(async move {
+                let mut count = 0;
+                loop {
+                    let Some(slot) = limiter.reserve().filter(|slot| *slot < deadline) else {
+                        break count;
+                    };
+                    tokio::time::sleep_until(slot).await;
+                    count += 1;
+                }
+            }));
+        }
+
+        let mut total = 0;
+        for handle in handles {
+            total += handle.await.unwrap();
+        }
+        total
+    }
+
+    fn assert_within_one_percent(actual: u64, expected: u64) {
+        let diff = actual.abs_diff(expected) as f64;
+        assert!(
+            diff <= expected as f64 * 0.01,
+            "achieved {} requests, expected {}",
+            actual,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_rate_limiter_rejects_zero() {
+        assert!(RateLimiter::try_new(0).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_high_rate() {
+        let total = achieved(50_000, 8, Duration::from_secs(2)).await;
+        assert_within_one_percent(total, 100_000);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_not_divisible_by_clients() {
+        // Used to run at 100 rps because the rate was split per client with integer division
+        let total = achieved(105, 10, Duration::from_secs(10)).await;
+        assert_within_one_percent(total, 1050);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_fewer_rps_than_clients() {
+        let total = achieved(5, 10, Duration::from_secs(10)).await;
+        assert_eq!(total, 50);
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_keeps_schedule_when_caller_stalls() {
+        let limiter = RateLimiter::try_new(10).unwrap();
+        let start = Instant::now();
+
+        let first = limiter.acquire().await;
+        // The caller stalls for a second, e.g. waiting on a slow response
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        let second = limiter.acquire().await;
+
+        assert_eq!(first, Some(start));
+        // The second request was due 100ms after the first, not when the caller came back
+        assert_eq!(second, Some(start + Duration::from_millis(100)));
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_set_rps_and_catch_up() {
+        let limiter = RateLimiter::try_new(10).unwrap();
+        let start = Instant::now();
+
+        assert_eq!(limiter.acquire().await, Some(start));
+        limiter.set_rps(100).unwrap();
+        // The slot after the first one was already due at the old rate
+        assert_eq!(
+            limiter.acquire().await,
+            Some(start + Duration::from_millis(100))
+        );
+        assert_eq!(
+            limiter.acquire().await,
+            Some(start + Duration::from_millis(110))
+        );
+
+        // Paused for a second, the slots meanwhile are skipped
+        tokio::time::sleep(Duration::from_secs(1)).await;
+        limiter.catch_up();
+        let resumed = limiter.acquire().await.unwrap();
+        assert!(resumed >= start + Duration::from_millis(1110));
+
+        assert!(limiter.set_rps(0).is_err());
+    }
+
+    #[tokio::test(start_paused = true)]
+    async fn test_rate_limiter_follows_profile() {
+        let stage = |secs, from, to| Stage {
+            duration: Duration::from_secs(secs),
+            from,
+            to,
+            unit: StageUnit::Rps,
+        };
+        let profile = LoadProfile::try_new(vec![
+            stage(10, 0.0, 200.0),
+            stage(10, 200.0, 200.0),
+            stage(2, 1000.0, 1000.0),
+        ])
+        .unwrap();
+        let limiter = RateLimiter::try_from_profile(profile).unwrap();
+        let start = Instant::now();
+
+        let mut per_stage = [0_u64; 3];
+        while let Some(slot) = limiter.acquire().await {
+            let elapsed = slot.duration_since(start).as_secs_f64();
+            let stage = if elapsed < 10.0 {
+                0
+            } else if elapsed < 20.0 {
+                1
+            } else {
+                2
+            };
+            per_stage[stage] += 1;
+        }
+
+        assert_within_one_percent(per_stage[0], 1000);
+        assert_within_one_percent(per_stage[1], 2000);
+        assert_within_one_percent(per_stage[2], 2000);
+    }
+}
@@ -1,14 +1,22 @@
-use crate::http::Request;
+use crate::control::LoadControl;
+use crate::error_log::ErrorLog;
+use crate::histogram::{LatencyRecorder, Phase, RequestKey, TimingRecorder};
+use crate::http::{ConnectTiming, ErrorCategory};
+use crate::profile::{LoadProfile, StageProgress};
 use crate::reporter::ExecMode;
-use crate::reporter::REPORT_INTERVAL;
+use crate::reporter::abort::AbortExporter;
 use crate::reporter::exporter::StdoutExporter;
 use crate::reporter::formatter::OutputFormat;
+use crate::reporter::timeseries::{Timeseries, TimeseriesExporter};
+use crate::reporter::{GroupBy, REPORT_INTERVAL};
+use crate::scenario::{Scenario, Step};
+use crate::threshold::{AbortCondition, AbortSignal, Threshold, Verdict};
 use crate::time::now_ts;
 use anyhow::{Result, bail};
 use clap::ValueEnum;
 use opentelemetry::{
     InstrumentationScope, KeyValue, global,
-    metrics::{Counter, Gauge, Histogram},
+    metrics::{Counter, Gauge, Histogram, Meter},
 };
 use opentelemetry_otlp::{MetricExporter, Protocol, WithExportConfig};
 use opentelemetry_sdk::runtime;
@@ -20,6 +28,13 @@ use std::time::Duration;
 use tracing::*;
 
 const OTLP_SERVICE_NAME: &str = "hb";
+const DURATION_BOUNDARIES: [f64; 12] = [
+    0.001, 0.005, 0.01, 0.025, 0.05, 0.07, 0.1, 0.25, 0.5, 1.0, 5.0, 10.0,
+];
+// Phases of a request are shorter than the request, so they start finer
+const PHASE_BOUNDARIES: [f64; 14] = [
+    0.0001, 0.0005, 0.001, 0.0025, 0.005, 0.01, 0.025, 0.05, 0.1, 0.25, 0.5, 1.0, 5.0, 10.0,
+];
 
 #[derive(Debug, Clone, ValueEnum)]
 pub enum OtlpProtocol {
@@ -44,26 +59,237 @@ pub struct Metrics {
     duration_seconds: Histogram<f64>,
     response_size_bytes: Histogram<u64>,
     error_total: Counter<u64>,
+    dropped_total: Counter<u64>,
+    connection_total: Counter<u64>,
+    dns_duration_seconds: Histogram<f64>,
+    connect_duration_seconds: Histogram<f64>,
+    tls_duration_seconds: Histogram<f64>,
+    ttfb_duration_seconds: Histogram<f64>,
+    download_duration_seconds: Histogram<f64>,
+    check_total: Counter<u64>,
+    check_failure_total: Counter<u64>,
+    corrected_duration_seconds: Histogram<f64>,
+    stage: Gauge<u64>,
+    stage_target: Gauge<f64>,
+    latency: Option<LatencyRecorder>,
+    corrected_latency: Option<LatencyRecorder>,
+    timing: Option<TimingRecorder>,
+    error_log: Option<ErrorLog>,
+    // Whether the errors of the warm-up are kept in `error_log`, like in the counts of the report
+    include_warmup: bool,
+    scenario: String,
+    step: String,
     method: String,
     path: String,
+    warmup: bool,
 }
 
 impl Metrics {
-    pub fn new(
-        start_time: Gauge<f64>,
-        duration_seconds: Histogram<f64>,
-        response_size_bytes: Histogram<u64>,
-        error_total: Counter<u64>,
-        method: String,
-        path: String,
-    ) -> Self {
+    pub fn new(meter: &Meter) -> Self {
+        let duration_seconds = meter
+            .f64_histogram("http_client_request_duration")
+            .with_boundaries(DURATION_BOUNDARIES.to_vec())
+            .with_description("Histogram of latencies for HTTP client requests.")
+            .with_unit("s")
+            .build();
+
+        let corrected_duration_seconds = meter
+            .f64_histogram("http_client_request_corrected_duration")
+            .with_boundaries(DURATION_BOUNDARIES.to_vec())
+            .with_description(
+                "Histogram of latencies for HTTP client requests, measured from the intended send time.",
+            )
+            .with_unit("s")
+            .build();
+
+        let error_total = meter
+            .u64_counter("http_client_errors")
+            .with_description("Total number of HTTP client errors.")
+            .build();
+
+        let dropped_total = meter
+            .u64_counter("http_client_dropped_requests")
+            .with_description(
+                "Total number of requests dropped because the in-flight limit was reached.",
+            )
+            .build();
+
+        let connection_total = meter
+            .u64_counter("http_client_connections")
+            .with_description("Total number of connections opened by the HTTP clients.")
+            .build();
+
+        let phase_histogram = |name: &'static str, description: &'static str| {
+            meter
+                .f64_histogram(name)
+                .with_boundaries(PHASE_BOUNDARIES.to_vec())
+                .with_description(description)
+                .with_unit("s")
+                .build()
+        };
+        let dns_duration_seconds = phase_histogram(
+            "http_client_dns_duration",
+            "Histogram of DNS resolve times for new connections.",
+        );
+        let connect_duration_seconds = phase_histogram(
+            "http_client_connect_duration",
+            "Histogram of TCP connect times for new connections.",
+        );
+        let tls_duration_seconds = phase_histogram(
+            "http_client_tls_duration",
+            "Histogram of TLS handshake times for new connections.",
+        );
+        let ttfb_duration_seconds = phase_histogram(
+            "http_client_ttfb_duration",
+            "Histogram of times to the first byte of responses, without opening connections.",
+        );
+        let download_duration_seconds = phase_histogram(
+            "http_client_download_duration",
+            "Histogram of times to download response bodies.",
+        );
+
+        let check_total = meter
+            .u64_counter("http_client_checks")
+            .with_description("Total number of response checks evaluated.")
+            .build();
+
+        let check_failure_total = meter
+            .u64_counter("http_client_check_failures")
+            .with_description("Total number of failed response checks.")
+            .build();
+
+        let response_size_bytes = meter
+            .u64_histogram("http_client_response_size")
+            .with_boundaries(vec![0.0, 100.0, 1024.0, 1024.0 * 100.0, 1024.0 * 1024.0])
+            .with_description("Histogram of response sizes for HTTP client requests.")
+            .with_unit("bytes")
+            .build();
+
+        let start_time = meter
+            .f64_gauge("http_client_start_time")
+            .with_description("Start time of the HTTP client.")
+            .with_unit("s")
+            .build();
+
+        let stage = meter
+            .u64_gauge("http_client_stage")
+            .with_description("Current stage of the load profile, starting at 1.")
+            .build();
+
+        let stage_target = meter
+            .f64_gauge("http_client_stage_target")
+            .with_description("Target of the load profile at the current stage.")
+            .build();
+
         Self {
             start_time,
             duration_seconds,
             response_size_bytes,
             error_total,
-            method,
-            path,
+            dropped_total,
+            connection_total,
+            dns_duration_seconds,
+            connect_duration_seconds,
+            tls_duration_seconds,
+            ttfb_duration_seconds,
+            download_duration_seconds,
+            check_total,
+            check_failure_total,
+            corrected_duration_seconds,
+            stage,
+            stage_target,
+            latency: None,
+            corrected_latency: None,
+            timing: None,
+            error_log: None,
+            include_warmup: false,
+            scenario: String::new(),
+            step: String::new(),
+            method: String::new(),
+            path: String::new(),
+            warmup: false,
+        }
+    }
+
+    /// Latencies are recorded into `latency` and `corrected_latency` as well, for exact percentiles
+    pub fn with_latency_recorders(
+        self,
+        latency: LatencyRecorder,
+        corrected_latency: LatencyRecorder,
+    ) -> Self {
+        Self {
+            latency: Some(latency),
+            corrected_latency: Some(corrected_latency),
+            ..self
+        }
+    }
+
+    /// Phases of the requests are recorded into `timing` as well, for exact percentiles
+    pub fn with_timing_recorder(self, timing: TimingRecorder) -> Self {
+        Self {
+            timing: Some(timing),
+            ..self
+        }
+    }
+
+    /// Errors are kept in `error_log` as well, with their messages, those of the warm-up only
+    /// with `include_warmup`
+    pub fn with_error_log(self, error_log: ErrorLog, include_warmup: bool) -> Self {
+        Self {
+            error_log: Some(error_log),
+            include_warmup,
+            ..self
+        }
+    }
+
+    /// Metrics for the requests of a scenario step, labelled with their names, method and path
+    pub fn with_step(&self, scenario: &Scenario, step: &Step) -> Self {
+        Self {
+            scenario: scenario.name.clone(),
+            step: step.name.clone(),
+            method: step.request.method.as_ref().to_string(),
+            path: step.path.clone(),
+            ..self.clone()
+        }
+    }
+
+    /// Metrics for requests sent during warm-up, recorded with `phase="warmup"`
+    pub fn warmup(&self) -> Self {
+        Self {
+            warmup: true,
+            ..self.clone()
+        }
+    }
+
+    fn phase_attributes(&self) -> Vec<KeyValue> {
+        if self.warmup {
+            vec![KeyValue::new("phase", "warmup")]
+        } else {
+            Vec::new()
+        }
+    }
+
+    fn request_attributes(&self, status: u16, version: &'static str) -> Vec<KeyValue> {
+        let mut attrs = vec![
+            KeyValue::new("scenario", self.scenario.clone()),
+            KeyValue::new("step", self.step.clone()),
+            KeyValue::new("method", self.method.clone()),
+            KeyValue::new("path", self.path.clone()),
+            KeyValue::new("status", status.to_string()),
+            KeyValue::new("version", version),
+        ];
+        attrs.extend(self.phase_attributes());
+        attrs
+    }
+
+    fn request_key(&self, status: u16, version: &'static str) -> RequestKey {
+        RequestKey {
+            scenario: self.scenario.clone(),
+            step: self.step.clone(),
+            method: self.method.clone(),
+            path: self.path.clone(),
+            status,
+            version,
         }
     }
 
@@ -72,24 +298,109 @@ impl Metrics {
         self.start_time.record(now, &[]);
     }
 
-    pub fn record_duration(&self, duration: f64, status: u16) {
-        self.duration_seconds.record(
-            duration,
-            &[
-                KeyValue::new("method", self.method.clone()),
-                KeyValue::new("path", self.path.clone()),
-                KeyValue::new("status", status.to_string()),
-            ],
-        );
+    /// `version` is the protocol the response came over, e.g. "HTTP/2"
+    pub fn record_duration(&self, duration: f64, status: u16, version: &'static str) {
+        self.duration_seconds
+            .record(duration, &self.request_attributes(status, version));
+        if let Some(latency) = &self.latency {
+            latency.record(duration, &self.request_key(status, version), self.warmup);
+        }
+    }
+
+    /// Record a latency measured from the intended send time instead of the actual one
+    pub fn record_corrected_duration(&self, duration: f64, status: u16, version: &'static str) {
+        self.corrected_duration_seconds
+            .record(duration, &self.request_attributes(status, version));
+        if let Some(latency) = &self.corrected_latency {
+            latency.record(duration, &self.request_key(status, version), self.warmup);
+        }
     }
 
     pub fn record_response_size(&self, size: u64) {
-        self.response_size_bytes.record(size, &[]);
+        self.response_size_bytes
+            .record(size, &self.phase_attributes());
+    }
+
+    // Warm-up errors are recorded with phase="warmup" like the other metrics, in place of the
+    // phase they failed in
+    pub fn record_error(&self, phase: &str, category: ErrorCategory, err: &anyhow::Error) {
+        let phase = if self.warmup { "warmup" } else { phase };
+        let attrs = [
+            KeyValue::new("scenario", self.scenario.clone()),
+            KeyValue::new("step", self.step.clone()),
+            KeyValue::new("method", self.method.clone()),
+            KeyValue::new("path", self.path.clone()),
+            KeyValue::new("phase", phase.to_string()),
+            KeyValue::new("category", category.as_ref().to_string()),
+        ];
+        self.error_total.add(1, &attrs);
+
+        if let Some(error_log) = &self.error_log
+            && (!self.warmup || self.include_warmup)
+        {
+            let endpoint = format!("{} {}", self.method, self.path);
+            error_log.push(endpoint, category.as_ref(), format!("{:#}", err));
+        }
+    }
+
+    pub fn record_check(&self, check: &str, passed: bool) {
+        let mut attrs = vec![
+            KeyValue::new("scenario", self.scenario.clone()),
+            KeyValue::new("step", self.step.clone()),
+            KeyValue::new("check", check.to_string()),
+        ];
+        attrs.extend(self.phase_attributes());
+
+        self.check_total.add(1, &attrs);
+        if !passed {
+            self.check_failure_total.add(1, &attrs);
+        }
+    }
+
+    pub fn record_dropped(&self) {
+        self.dropped_total.add(1, &[]);
+    }
+
+    /// Connections are shared by the requests of a client, so they carry no request attributes,
+    /// only phase="warmup" for those opened during warm-up
+    pub fn record_connection(&self, timing: &ConnectTiming) {
+        self.connection_total.add(1, &[]);
+        let attrs = self.phase_attributes();
+        let phases = [
+            (Phase::Dns, &self.dns_duration_seconds, timing.dns),
+            (
+                Phase::Connect,
+                &self.connect_duration_seconds,
+                timing.connect,
+            ),
+            (Phase::Tls, &self.tls_duration_seconds, timing.tls),
+        ];
+        for (phase, histogram, duration) in phases {
+            if let Some(duration) = duration {
+                histogram.record(duration.as_secs_f64(), &attrs);
+                if let Some(recorder) = &self.timing {
+                    recorder.record(phase, duration, self.warmup);
+                }
+            }
+        }
+    }
+
+    pub fn record_response_timing(&self, ttfb: Duration, download: Duration) {
+        let attrs = self.phase_attributes();
+        self.ttfb_duration_seconds
+            .record(ttfb.as_secs_f64(), &attrs);
+        self.download_duration_seconds
+            .record(download.as_secs_f64(), &attrs);
+        if let Some(recorder) = &self.timing {
+            recorder.record(Phase::Ttfb, ttfb, self.warmup);
+            recorder.record(Phase::Download, download, self.warmup);
+        }
     }
 
-    pub fn record_error(&self, phase: &str) {
-        self.error_total
-            .add(1, &[KeyValue::new("phase", phase.to_string())]);
+    pub fn record_stage(&self, progress: &StageProgress) {
+        let attrs = [KeyValue::new("unit", progress.unit.as_ref().to_string())];
+        self.stage.record(progress.number as u64, &attrs);
+        self.stage_target.record(progress.target, &attrs);
     }
 }
 
@@ -100,23 +411,54 @@ pub struct OtlpOptions {
     pub interval: Duration,
     pub requests: Option<u64>,
     pub duration: Option<Duration>,
+    pub profile: Option<LoadProfile>,
+    pub include_warmup: bool,
     pub output_format: OutputFormat,
     pub no_interactive: bool,
     pub no_clear_console: bool,
+    pub thresholds: Vec<Threshold>,
+    pub verdict: Verdict,
+    pub abort_on: Vec<AbortCondition>,
+    pub abort: AbortSignal,
+    /// Percentiles of the latencies in the report, from 0 to 100
+    pub percentiles: Vec<f64>,
+    /// Significant digits of the recorded latencies
+    pub latency_precision: u8,
+    /// Attributes the endpoint breakdown is grouped on
+    pub group_by: Vec<GroupBy>,
+    /// Filled with a point per report interval
+    pub timeseries: Timeseries,
+    /// Changed from the keyboard of the interactive screen
+    pub control: LoadControl,
 }
 
-pub async fn setup_metrics(
-    opts: OtlpOptions,
-    req: &Request,
-) -> Result<(SdkMeterProvider, Metrics)> {
-    let readers = create_periodic_readers(&opts)?;
+pub async fn setup_metrics(opts: OtlpOptions) -> Result<(SdkMeterProvider, Metrics)> {
+    let latency = LatencyRecorder::try_new(opts.latency_precision, opts.include_warmup)?
+        .with_group_by(opts.group_by.clone());
+    let corrected_latency = LatencyRecorder::try_new(opts.latency_precision, opts.include_warmup)?;
+
+    let timing = TimingRecorder::try_new(opts.latency_precision, opts.include_warmup)?;
+
+    let error_log = ErrorLog::default();
+
+    let readers =
+        create_periodic_readers(&opts, &latency, &corrected_latency, &timing, &error_log)?;
     let provider = create_provider(readers);
-    let metrics = create_metrics(req);
+    let metrics = create_metrics()
+        .with_latency_recorders(latency, corrected_latency)
+        .with_timing_recorder(timing)
+        .with_error_log(error_log, opts.include_warmup);
 
     Ok((provider, metrics))
 }
 
-fn create_periodic_readers(opts: &OtlpOptions) -> Result<Vec<PeriodicReader>> {
+fn create_periodic_readers(
+    opts: &OtlpOptions,
+    latency: &LatencyRecorder,
+    corrected_latency: &LatencyRecorder,
+    timing: &TimingRecorder,
+    error_log: &ErrorLog,
+) -> Result<Vec<PeriodicReader>> {
     let stdout_meter_interval = if opts.no_interactive {
         // If we're not in interactive mode, we don't need to update the console
         // NOTE: However, when the shutdown call is made, the display
@@ -125,24 +467,63 @@ fn create_periodic_readers(opts: &OtlpOptions) -> Result<Vec<PeriodicReader>> {
         REPORT_INTERVAL
     };
 
-    let exec_mode = match (opts.requests, opts.duration) {
-        (Some(num), None) => ExecMode::ByCount(num),
-        (None, Some(duration)) => ExecMode::ByDuration(duration),
-        _ => bail!("Either num or duration must be specified"),
+    let exec_mode = match (opts.requests, opts.duration, &opts.profile) {
+        (Some(num), None, None) => ExecMode::ByCount(num),
+        (None, Some(duration), None) => ExecMode::ByDuration(duration),
+        (None, None, Some(profile)) => ExecMode::ByProfile(profile.clone()),
+        _ => bail!("Either num, duration or stages must be specified"),
     };
     let exporter = StdoutExporter::new(
         opts.output_format.to_owned(),
         exec_mode,
+        opts.include_warmup,
         opts.no_interactive,
         opts.no_clear_console,
-    );
+    )
+    .with_thresholds(opts.thresholds.clone(), opts.verdict.clone())
+    .with_abort(opts.abort.clone())
+    .with_latencies(
+        latency.clone(),
+        corrected_latency.clone(),
+        opts.percentiles.clone(),
+    )
+    .with_timing(timing.clone())
+    .with_group_by(opts.group_by.clone())
+    .with_timeseries(opts.timeseries.clone())
+    .with_control(opts.control.clone(), error_log.clone());
 
     let mut readers = Vec::new();
+
+    // Ahead of the stdout reader so that the final report has the last interval
+    let timeseries_exporter = TimeseriesExporter::new(
+        opts.timeseries.clone(),
+        latency.clone(),
+        opts.include_warmup,
+    );
+    let reader = PeriodicReader::builder(timeseries_exporter, runtime::Tokio)
+        .with_interval(REPORT_INTERVAL)
+        .build();
+    readers.push(reader);
+
     let reader = PeriodicReader::builder(exporter, runtime::Tokio)
         .with_interval(stdout_meter_interval)
         .build();
     readers.push(reader);
 
+    // Evaluated on its own interval, the console may only be updated on shutdown
+    if !opts.abort_on.is_empty() {
+        let exporter = AbortExporter::new(
+            opts.abort_on.clone(),
+            opts.abort.clone(),
+            opts.include_warmup,
+            latency,
+        );
+        let reader = PeriodicReader::builder(exporter, runtime::Tokio)
+            .with_interval(REPORT_INTERVAL)
+            .build();
+        readers.push(reader);
+    }
+
     if let Some(endpoint) = opts.endpoint.clone() {
         let exporter = match opts.protocol {
             OtlpProtocol::Grpc => MetricExporter::builder()
@@ -193,44 +574,34 @@ fn create_provider(readers: Vec<PeriodicReader>) -> SdkMeterProvider {
     provider
 }
 
-fn create_metrics(req: &Request) -> Metrics {
+fn create_metrics() -> Metrics {
     let scope = InstrumentationScope::builder("client")
         .with_version("0.1.0")
         .build();
 
-    let duration_seconds = global::meter_with_scope(scope.clone())
-        .f64_histogram("http_client_request_duration")
-        .with_boundaries(vec![
-            0.001, 0.005, 0.01, 0.025, 0.05, 0.07, 0.1, 0.25, 0.5, 1.0, 5.0, 10.0,
-        ])
-        .with_description("Histogram of latencies for HTTP client requests.")
-        .with_unit("s")
-        .build();
-
-    let error_total = global::meter_with_scope(scope.clone())
-        .u64_counter("http_client_errors")
-        .with_description("Total number of HTTP client errors.")
-        .build();
+    Metrics::new(&global::meter_with_scope(scope))
+}
 
-    let response_size_bytes = global::meter_with_scope(scope.clone())
-        .u64_histogram("http_client_response_size")
-        .with_boundaries(vec![0.0, 100.0, 1024.0, 1024.0 * 100.0, 1024.0 * 1024.0])
-        .with_description("Histogram of response sizes for HTTP client requests.")
-        .with_unit("bytes")
-        .build();
+#[cfg(test)]
+mod tests {
+    use super::*;
 
-    let start_time = global::meter_with_scope(scope.clone())
-        .f64_gauge("http_client_start_time")
-        .with_description("Start time of the HTTP client.")
-        .with_unit("s")
-        .build();
+    #[test]
+    fn test_warmup_errors() {
+        let meter = global::meter("test");
+        let err = anyhow::anyhow!("connection refused");
+        let logged = |include_warmup| {
+            let error_log = ErrorLog::default();
+            let metrics = Metrics::new(&meter).with_error_log(error_log.clone(), include_warmup);
+            metrics
+                .warmup()
+                .record_error("connect", ErrorCategory::ConnectionRefused, &err);
+            metrics.record_error("connect", ErrorCategory::ConnectionRefused, &err);
+            error_log.latest(10).len()
+        };
 
-    Metrics::new(
-        start_time,
-        duration_seconds,
-        response_size_bytes,
-        error_total,
-        req.method.as_ref().to_string(),
-        req.url.path().to_string(),
-    )
+        // Left out of the log like they are of the error counts of the report
+        assert_eq!(logged(false), 1);
+        assert_eq!(logged(true), 2);
+    }
 }
@@ -0,0 +1,281 @@
+use anyhow::{Result, bail};
+use clap::ValueEnum;
+use std::fmt::Display;
+use std::time::Duration;
+use strum::AsRefStr;
+
+/// What a stage target controls
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, AsRefStr)]
+#[strum(serialize_all = "lowercase")]
+pub enum StageUnit {
+    /// Aggregate requests per second
+    Rps,
+    /// Number of active clients
+    Clients,
+}
+
+/// A single stage of a load profile, moving linearly from `from` to `to` over `duration`
+///
+/// A stage with `from == to` holds the load, consecutive holds form steps,
+/// and a short high stage between two lower ones forms a spike.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Stage {
+    pub duration: Duration,
+    pub from: f64,
+    pub to: f64,
+    pub unit: StageUnit,
+}
+
+impl Stage {
+    fn secs(&self) -> f64 {
+        self.duration.as_secs_f64()
+    }
+
+    // Target at `offset` seconds into this stage
+    fn target_at(&self, offset: f64) -> f64 {
+        self.from + (self.to - self.from) * (offset / self.secs()).clamp(0.0, 1.0)
+    }
+
+    // Increase of the target per second
+    fn slope(&self) -> f64 {
+        (self.to - self.from) / self.secs()
+    }
+}
+
+impl Display for Stage {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.from == self.to {
+            write!(f, "{}{}", self.to, self.unit.as_ref())
+        } else {
+            write!(f, "{}->{}{}", self.from, self.to, self.unit.as_ref())
+        }
+    }
+}
+
+/// Position of a run within its load profile
+#[derive(Debug, Clone, PartialEq)]
+pub struct StageProgress {
+    /// 1-based stage number
+    pub number: usize,
+    pub count: usize,
+    pub target: f64,
+    pub unit: StageUnit,
+}
+
+impl Display for StageProgress {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "stage {}/{} target {:.0}{}",
+            self.number,
+            self.count,
+            self.target,
+            self.unit.as_ref()
+        )
+    }
+}
+
+/// Ordered list of stages that drive either the request rate or the number of clients
+#[derive(Debug, Clone, PartialEq)]
+pub struct LoadProfile {
+    stages: Vec<Stage>,
+}
+
+impl LoadProfile {
+    pub fn try_new(stages: Vec<Stage>) -> Result<Self> {
+        let Some(first) = stages.first() else {
+            bail!("At least one stage must be specified");
+        };
+
+        if stages.iter().any(|stage| stage.unit != first.unit) {
+            bail!("All stages must use the same unit, either rps or clients");
+        }
+
+        if stages.iter().any(|stage| stage.duration.is_zero()) {
+            bail!("Stage duration must be greater than 0");
+        }
+
+        if stages
+            .iter()
+            .all(|stage| stage.from == 0.0 && stage.to == 0.0)
+        {
+            bail!("At least one stage must have a target greater than 0");
+        }
+
+        Ok(Self { stages })
+    }
+
+    pub fn unit(&self) -> StageUnit {
+        self.stages[0].unit
+    }
+
+    pub fn duration(&self) -> Duration {
+        self.stages.iter().map(|stage| stage.duration).sum()
+    }
+
+    /// Highest target over the whole profile
+    pub fn peak(&self) -> f64 {
+        self.stages
+            .iter()
+            .map(|stage| stage.from.max(stage.to))
+            .fold(0.0, f64::max)
+    }
+
+    /// Stage and target at `elapsed` seconds from the start, None once the profile is over
+    pub fn progress(&self, elapsed: f64) -> Option<StageProgress> {
+        let mut start = 0.0;
+        for (i, stage) in self.stages.iter().enumerate() {
+            let end = start + stage.secs();
+            if elapsed < end {
+                return Some(StageProgress {
+                    number: i + 1,
+                    count: self.stages.len(),
+                    target: stage.target_at(elapsed - start),
+                    unit: stage.unit,
+                });
+            }
+            start = end;
+        }
+        None
+    }
+
+    /// Target at `elapsed` seconds from the start, 0 once the profile is over
+    pub fn target(&self, elapsed: f64) -> f64 {
+        self.progress(elapsed)
+            .map_or(0.0, |progress| progress.target)
+    }
+
+    /// Time of the arrival following the one at `from` seconds, i.e. the point where
+    /// the integral of the rate over `[from, next]` reaches exactly one request.
+    /// Returns None when the profile ends before that.
+    pub fn next_arrival(&self, from: f64) -> Option<f64> {
+        let mut remaining = 1.0;
+        let mut stage_start = 0.0;
+
+        for stage in &self.stages {
+            let stage_end = stage_start + stage.secs();
+            if from >= stage_end {
+                stage_start = stage_end;
+                continue;
+            }
+
+            let t = from.max(stage_start);
+            let rate = stage.target_at(t - stage_start);
+            let slope = stage.slope();
+            let span = stage_end - t;
+
+            // Solve rate * d + slope / 2 * d^2 = remaining for d
+            let d = if slope == 0.0 {
+                (rate > 0.0).then(|| remaining / rate)
+            } else {
+                let discriminant = rate * rate + 2.0 * slope * remaining;
+                (discriminant >= 0.0).then(|| (discriminant.sqrt() - rate) / slope)
+            };
+
+            match d {
+                Some(d) if d >= 0.0 && d < span => return Some(t + d),
+                _ => remaining -= rate * span + slope / 2.0 * span * span,
+            }
+
+            stage_start = stage_end;
+        }
+
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn stage(secs: u64, from: f64, to: f64, unit: StageUnit) -> Stage {
+        Stage {
+            duration: Duration::from_secs(secs),
+            from,
+            to,
+            unit,
+        }
+    }
+
+    fn profile() -> LoadProfile {
+        LoadProfile::try_new(vec![
+            stage(10, 0.0, 100.0, StageUnit::Rps),
+            stage(10, 100.0, 100.0, StageUnit::Rps),
+            stage(2, 500.0, 500.0, StageUnit::Rps),
+        ])
+        .unwrap()
+    }
+
+    #[test]
+    fn test_profile_validation() {
+        assert!(LoadProfile::try_new(vec![]).is_err());
+        assert!(
+            LoadProfile::try_new(vec![
+                stage(10, 0.0, 100.0, StageUnit::Rps),
+                stage(10, 10.0, 10.0, StageUnit::Clients),
+            ])
+            .is_err()
+        );
+        assert!(LoadProfile::try_new(vec![stage(10, 0.0, 0.0, StageUnit::Rps)]).is_err());
+        assert!(LoadProfile::try_new(vec![stage(0, 1.0, 1.0, StageUnit::Rps)]).is_err());
+    }
+
+    #[test]
+    fn test_profile_duration_and_peak() {
+        let profile = profile();
+        assert_eq!(profile.duration(), Duration::from_secs(22));
+        assert_eq!(profile.peak(), 500.0);
+        assert_eq!(profile.unit(), StageUnit::Rps);
+    }
+
+    #[test]
+    fn test_profile_progress() {
+        let profile = profile();
+
+        let progress = profile.progress(5.0).unwrap();
+        assert_eq!(progress.number, 1);
+        assert_eq!(progress.target, 50.0);
+        assert_eq!(progress.to_string(), "stage 1/3 target 50rps");
+
+        assert_eq!(profile.progress(15.0).unwrap().target, 100.0);
+        assert_eq!(profile.progress(21.0).unwrap().number, 3);
+        assert_eq!(profile.progress(22.0), None);
+        assert_eq!(profile.target(22.0), 0.0);
+    }
+
+    #[test]
+    fn test_profile_next_arrival_hold() {
+        let profile = LoadProfile::try_new(vec![stage(10, 4.0, 4.0, StageUnit::Rps)]).unwrap();
+        assert_eq!(profile.next_arrival(0.0), Some(0.25));
+        assert_eq!(profile.next_arrival(9.75), None);
+    }
+
+    #[test]
+    fn test_profile_next_arrival_counts_match_integral() {
+        let profile = profile();
+
+        let mut arrivals = 1;
+        let mut t = 0.0;
+        while let Some(next) = profile.next_arrival(t) {
+            t = next;
+            arrivals += 1;
+        }
+
+        // 500 during the ramp, 1000 during the hold and 1000 during the spike
+        assert!((2499..=2501).contains(&arrivals), "{}", arrivals);
+    }
+
+    #[test]
+    fn test_profile_next_arrival_ramp_down() {
+        let profile = LoadProfile::try_new(vec![stage(10, 100.0, 0.0, StageUnit::Rps)]).unwrap();
+
+        let mut arrivals = 1;
+        let mut t = 0.0;
+        while let Some(next) = profile.next_arrival(t) {
+            t = next;
+            arrivals += 1;
+        }
+
+        assert!((499..=501).contains(&arrivals), "{}", arrivals);
+    }
+}
@@ -0,0 +1,385 @@
+use crate::http::{Method, Request};
+use crate::scenario::{Scenario, Step};
+use anyhow::{Context, Result, bail};
+use chrono::{DateTime, FixedOffset};
+use clap::ValueEnum;
+use regex::Regex;
+use serde::Deserialize;
+use std::collections::HashMap;
+use std::path::Path;
+use std::time::Duration;
+use tracing::*;
+use url::Url;
+
+/// Name of the scenario the recorded requests are reported under
+pub const REPLAY_SCENARIO: &str = "replay";
+
+// Set by the client itself for the target of the replay
+const SKIPPED_HEADERS: [&str; 5] = [
+    "host",
+    "content-length",
+    "connection",
+    "transfer-encoding",
+    "keep-alive",
+];
+
+/// How fast the recorded requests are sent
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ReplaySpeed {
+    /// The recorded inter-arrival times divided by the multiplier
+    Timed(f64),
+    /// Each request as soon as a client is free
+    Max,
+}
+
+/// Requests recorded in an access log or a HAR file, sent against the URL of the run
+#[derive(Debug, Clone)]
+pub struct Replay {
+    /// The recorded requests as the steps of a single scenario, in the order they arrived
+    pub scenario: Scenario,
+    // Arrival time of each request since the first one
+    offsets: Vec<Duration>,
+    pub speed: ReplaySpeed,
+}
+
+struct Recorded {
+    time: DateTime<FixedOffset>,
+    method: Method,
+    url: String,
+    headers: Vec<(String, String)>,
+    body: Option<String>,
+}
+
+impl Replay {
+    /// Read a HAR file when it has the .har extension, an nginx combined access log otherwise.
+    /// The recorded paths and queries are sent to the base URL, the base request supplies the rest.
+    pub fn from_path(path: &Path, base: &Request, speed: ReplaySpeed) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read replay file {}", path.display()))?;
+
+        let recorded = match path.extension().and_then(|ext| ext.to_str()) {
+            Some("har") => parse_har(&content)?,
+            _ => parse_access_log(&content)?,
+        };
+
+        Self::try_new(recorded, base, speed)
+    }
+
+    fn try_new(mut recorded: Vec<Recorded>, base: &Request, speed: ReplaySpeed) -> Result<Self> {
+        if recorded.is_empty() {
+            bail!("Replay file has no requests");
+        }
+
+        recorded.sort_by_key(|recorded| recorded.time);
+        let first = recorded[0].time;
+
+        let mut offsets = Vec::with_capacity(recorded.len());
+        let mut steps = Vec::with_capacity(recorded.len());
+        for recorded in recorded {
+            offsets.push((recorded.time - first).to_std().unwrap_or_default());
+
+            let url = target_url(&base.url, &recorded.url)
+                .with_context(|| format!("Invalid recorded URL '{}'", recorded.url))?;
+
+            // Headers given on the command line take precedence over the recorded ones
+            let mut headers: HashMap<String, String> = recorded.headers.into_iter().collect();
+            headers.extend(base.headers.clone());
+
+            let request = Request {
+                url,
+                method: recorded.method,
+                headers,
+                body: recorded
+                    .body
+                    .map(String::into_bytes)
+                    .unwrap_or_else(|| base.body.clone()),
+                ..base.clone()
+            };
+            steps.push(Step::fixed(REPLAY_SCENARIO.to_string(), request));
+        }
+
+        Ok(Self {
+            scenario: Scenario {
+                name: REPLAY_SCENARIO.to_string(),
+                weight: 1,
+                steps,
+            },
+            offsets,
+            speed,
+        })
+    }
+
+    pub fn len(&self) -> usize {
+        self.offsets.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.offsets.is_empty()
+    }
+
+    /// When the `index`-th request is sent since the start of the run, None as fast as possible
+    pub fn send_at(&self, index: usize) -> Option<Duration> {
+        match self.speed {
+            ReplaySpeed::Timed(multiplier) => Some(self.offsets[index].div_f64(multiplier)),
+            ReplaySpeed::Max => None,
+        }
+    }
+}
+
+// Only the path and query of a recorded target are kept, the host is the one of the run,
+// also for absolute URLs such as "http://other/items" and paths such as "//other/items"
+fn target_url(base: &Url, target: &str) -> Result<Url> {
+    let recorded = Url::parse("http://recorded.invalid/")?.join(target)?;
+    let mut url = base.clone();
+    url.set_path(recorded.path());
+    url.set_query(recorded.query());
+    url.set_fragment(None);
+    Ok(url)
+}
+
+// nginx combined format:
+// $remote_addr - $remote_user [$time_local] "$request" $status $body_bytes_sent "$http_referer" "$http_user_agent"
+fn parse_access_log(content: &str) -> Result<Vec<Recorded>> {
+    let re = Regex::new(
+        r#"^\S+ \S+ \S+ \[(?P<time>[^\]]+)\] "(?P<method>[A-Za-z]+) (?P<url>\S+)[^"]*" \d{3} \S+(?: "(?P<referer>[^"]*)" "(?P<agent>[^"]*)")?"#,
+    )?;
+
+    let mut recorded = Vec::new();
+    for (n, line) in content.lines().enumerate() {
+        if line.trim().is_empty() {
+            continue;
+        }
+
+        // Malformed requests are logged as well, they cannot be replayed
+        let Some(caps) = re.captures(line) else {
+            debug!("Skipping line {} of the access log: {}", n + 1, line);
+            continue;
+        };
+        let Ok(method) = Method::from_str(&caps["method"], true) else {
+            debug!("Skipping line {} with an unsupported method", n + 1);
+            continue;
+        };
+
+        let time = DateTime::parse_from_str(&caps["time"], "%d/%b/%Y:%H:%M:%S %z")
+            .with_context(|| format!("Invalid time on line {}", n + 1))?;
+
+        let headers = [
+            ("referer", caps.name("referer")),
+            ("user-agent", caps.name("agent")),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| {
+            let value = value?.as_str();
+            (!value.is_empty() && value != "-").then(|| (name.to_string(), value.to_string()))
+        })
+        .collect();
+
+        recorded.push(Recorded {
+            time,
+            method,
+            url: caps["url"].to_string(),
+            headers,
+            body: None,
+        });
+    }
+
+    Ok(recorded)
+}
+
+#[derive(Debug, Deserialize)]
+struct Har {
+    log: HarLog,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarLog {
+    entries: Vec<HarEntry>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarEntry {
+    started_date_time: String,
+    request: HarRequest,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct HarRequest {
+    method: String,
+    url: String,
+    #[serde(default)]
+    headers: Vec<HarHeader>,
+    post_data: Option<HarPostData>,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarHeader {
+    name: String,
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct HarPostData {
+    text: Option<String>,
+}
+
+fn parse_har(content: &str) -> Result<Vec<Recorded>> {
+    let har: Har = serde_json::from_str(content).context("Invalid HAR file")?;
+
+    har.log
+        .entries
+        .into_iter()
+        .map(|entry| {
+            let request = entry.request;
+            let time =
+                DateTime::parse_from_rfc3339(&entry.started_date_time).with_context(|| {
+                    format!("Invalid startedDateTime '{}'", entry.started_date_time)
+                })?;
+            let method = Method::from_str(&request.method, true)
+                .map_err(|_| anyhow::anyhow!("Unsupported method '{}'", request.method))?;
+
+            // HTTP/2 pseudo-headers and connection headers are left to the client
+            let headers = request
+                .headers
+                .into_iter()
+                .map(|header| (header.name.to_lowercase(), header.value))
+                .filter(|(name, _)| {
+                    !name.starts_with(':') && !SKIPPED_HEADERS.contains(&name.as_str())
+                })
+                .collect();
+
+            Ok(Recorded {
+                time,
+                method,
+                url: request.url,
+                headers,
+                body: request.post_data.and_then(|data| data.text),
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{HttpVersion, Negotiation};
+
+    fn base() -> Request {
+        Request {
+            http_version: HttpVersion::Http11,
+            negotiation: Negotiation::Auto,
+            url: url::Url::parse("http://staging:8080/").unwrap(),
+            method: Method::Get,
+            headers: HashMap::from([("authorization".to_string(), "Bearer t".to_string())]),
+            timeout: Duration::from_secs(3),
+            body: Vec::new(),
+            basic_auth: None,
+            insecure: false,
+            disable_keepalive: false,
+            root_cert: None,
+            resolve: None,
+        }
+    }
+
+    #[test]
+    fn test_replay_access_log() {
+        let content = r#"
+10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] "GET /items?page=2 HTTP/1.1" 200 512 "https://example.com/" "curl/8.0"
+10.0.0.2 - alice [10/Oct/2026:13:55:38 +0000] "POST /orders HTTP/1.1" 201 12 "-" "-"
+10.0.0.3 - - [10/Oct/2026:13:55:37 +0000] "\x16\x03\x01" 400 0 "-" "-"
+10.0.0.4 - - [10/Oct/2026:13:55:37 +0000] "DELETE /items/1 HTTP/2.0" 204 0
+"#;
+        let replay = Replay::try_new(
+            parse_access_log(content).unwrap(),
+            &base(),
+            ReplaySpeed::Timed(2.0),
+        )
+        .unwrap();
+        let steps = &replay.scenario.steps;
+
+        assert_eq!(replay.len(), 3);
+        assert_eq!(replay.scenario.name, REPLAY_SCENARIO);
+
+        assert_eq!(
+            steps[0].request.url.as_str(),
+            "http://staging:8080/items?page=2"
+        );
+        assert_eq!(steps[0].path, "/items");
+        assert_eq!(steps[0].request.headers["user-agent"], "curl/8.0");
+        assert_eq!(steps[0].request.headers["referer"], "https://example.com/");
+        assert_eq!(steps[0].request.headers["authorization"], "Bearer t");
+
+        assert_eq!(steps[1].request.method, Method::Delete);
+        assert_eq!(steps[2].request.method, Method::Post);
+        assert!(!steps[2].request.headers.contains_key("user-agent"));
+
+        assert_eq!(replay.send_at(0), Some(Duration::ZERO));
+        assert_eq!(replay.send_at(1), Some(Duration::from_millis(500)));
+        assert_eq!(replay.send_at(2), Some(Duration::from_secs(1)));
+    }
+
+    #[test]
+    fn test_replay_har() {
+        let content = r#"{"log": {"entries": [
+            {"startedDateTime": "2026-10-10T13:55:36.000Z", "request": {
+                "method": "GET", "url": "https://example.com/items/1?full=true",
+                "headers": [{"name": ":authority", "value": "example.com"},
+                            {"name": "Host", "value": "example.com"},
+                            {"name": "Accept", "value": "application/json"},
+                            {"name": "Authorization", "value": "Bearer recorded"}]}},
+            {"startedDateTime": "2026-10-10T13:55:36.250Z", "request": {
+                "method": "POST", "url": "https://example.com/orders",
+                "headers": [], "postData": {"mimeType": "application/json", "text": "{\"sku\": \"A-1\"}"}}}
+        ]}}"#;
+        let replay =
+            Replay::try_new(parse_har(content).unwrap(), &base(), ReplaySpeed::Max).unwrap();
+        let steps = &replay.scenario.steps;
+
+        assert_eq!(replay.len(), 2);
+        assert_eq!(
+            steps[0].request.url.as_str(),
+            "http://staging:8080/items/1?full=true"
+        );
+        assert_eq!(steps[0].request.headers["accept"], "application/json");
+        assert_eq!(steps[0].request.headers["authorization"], "Bearer t");
+        assert!(!steps[0].request.headers.contains_key("host"));
+        assert!(!steps[0].request.headers.contains_key(":authority"));
+
+        assert_eq!(steps[1].request.method, Method::Post);
+        assert_eq!(steps[1].request.body, br#"{"sku": "A-1"}"#);
+        assert_eq!(replay.send_at(1), None);
+    }
+
+    #[test]
+    fn test_replay_other_hosts() {
+        let content = r#"
+10.0.0.1 - - [10/Oct/2026:13:55:36 +0000] "GET http://other:9000/items?page=2 HTTP/1.1" 200 512
+10.0.0.2 - - [10/Oct/2026:13:55:37 +0000] "GET //other/orders HTTP/1.1" 200 12
+"#;
+        let replay = Replay::try_new(
+            parse_access_log(content).unwrap(),
+            &base(),
+            ReplaySpeed::Max,
+        )
+        .unwrap();
+        let steps = &replay.scenario.steps;
+
+        // Sent to the host of the run whatever host was recorded
+        assert_eq!(
+            steps[0].request.url.as_str(),
+            "http://staging:8080/items?page=2"
+        );
+        assert_eq!(steps[1].request.url.as_str(), "http://staging:8080/orders");
+    }
+
+    #[test]
+    fn test_replay_invalid() {
+        assert!(Replay::try_new(Vec::new(), &base(), ReplaySpeed::Max).is_err());
+        assert!(parse_har("{}").is_err());
+        assert!(
+            parse_har(r#"{"log": {"entries": [{"startedDateTime": "yesterday", "request": {"method": "GET", "url": "http://a/"}}]}}"#)
+                .is_err()
+        );
+    }
+}
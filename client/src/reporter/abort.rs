@@ -0,0 +1,160 @@
+use crate::histogram::{Latencies, LatencyRecorder};
+use crate::reporter::formatter::format_metric;
+use crate::reporter::metrics::{
+    CheckMetrics, ErrorMetrics, RequestDurationSecondsMetrics, Snapshot,
+};
+use crate::threshold::{AbortCondition, AbortSignal, ThresholdMetric};
+use async_trait::async_trait;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::metrics::{
+    Temporality, data::ResourceMetrics, exporter::PushMetricExporter,
+};
+use std::collections::VecDeque;
+use std::sync::Mutex;
+use tracing::*;
+
+/// Evaluates the abort conditions on every export and aborts the run once one holds
+#[derive(Debug)]
+pub struct AbortExporter {
+    conditions: Vec<AbortCondition>,
+    signal: AbortSignal,
+    include_warmup: bool,
+    // Latencies of every export are taken apart from those of the other exporters
+    latency: LatencyRecorder,
+    // With the latencies recorded since the previous one
+    snapshots: Mutex<VecDeque<(Snapshot, Option<Latencies>)>>,
+}
+
+impl AbortExporter {
+    pub(crate) fn new(
+        conditions: Vec<AbortCondition>,
+        signal: AbortSignal,
+        include_warmup: bool,
+        latency: &LatencyRecorder,
+    ) -> Self {
+        Self {
+            conditions,
+            signal,
+            include_warmup,
+            latency: latency.with_interval(),
+            snapshots: Mutex::new(VecDeque::new()),
+        }
+    }
+
+    fn evaluate(&self, meter: &ResourceMetrics) -> anyhow::Result<()> {
+        let Some(duration_meter) =
+            RequestDurationSecondsMetrics::try_find(meter, 0, 0.0, self.include_warmup)?
+        else {
+            return Ok(());
+        };
+        let error_meter = ErrorMetrics::find(meter, self.include_warmup);
+        let check_meter = CheckMetrics::find(meter, self.include_warmup);
+        let latest = Snapshot::new(&duration_meter, error_meter.as_ref(), check_meter.as_ref());
+
+        let mut snapshots = self.snapshots.lock().unwrap();
+        snapshots.push_back((latest.clone(), self.latency.take_interval()));
+
+        for condition in &self.conditions {
+            // The latest snapshot that is at least a window old, none until the run is that long
+            let Some(start) = snapshots.iter().rposition(|(snapshot, _)| {
+                latest.ts - snapshot.ts >= condition.window.as_secs_f64()
+            }) else {
+                continue;
+            };
+            let actual = match condition.condition.metric {
+                ThresholdMetric::Percentile(percent) => {
+                    window_latencies(snapshots.range(start + 1..))
+                        .map(|latencies| latencies.percentile(percent))
+                }
+                metric => latest.since(&snapshots[start].0, metric),
+            };
+            let Some(actual) = actual else {
+                continue;
+            };
+
+            if condition.condition.passes(actual) {
+                let reason = format!(
+                    "{} (actual {})",
+                    condition,
+                    format_metric(condition.condition.metric, &actual)
+                );
+                warn!("Aborting the run: {}", reason);
+                self.signal.abort(reason);
+                break;
+            }
+        }
+
+        // Keep what the longest window needs
+        let longest = self
+            .conditions
+            .iter()
+            .map(|condition| condition.window.as_secs_f64())
+            .fold(0.0, f64::max);
+        while snapshots
+            .get(1)
+            .is_some_and(|(next, _)| latest.ts - next.ts >= longest)
+        {
+            snapshots.pop_front();
+        }
+
+        Ok(())
+    }
+}
+
+// Latencies of the exports after the start of a window, None without any
+fn window_latencies<'a>(
+    snapshots: impl Iterator<Item = &'a (Snapshot, Option<Latencies>)>,
+) -> Option<Latencies> {
+    snapshots
+        .filter_map(|(_, latencies)| latencies.as_ref())
+        .fold(None, |window, latencies| match window {
+            Some(mut window) => {
+                window.add(latencies);
+                Some(window)
+            }
+            None => Some(latencies.clone()),
+        })
+}
+
+#[async_trait]
+impl PushMetricExporter for AbortExporter {
+    async fn export(&self, metrics: &mut ResourceMetrics) -> OTelSdkResult {
+        self.evaluate(metrics)
+            .map_err(|e| OTelSdkError::InternalFailure(e.to_string()))
+    }
+
+    async fn force_flush(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn temporality(&self) -> Temporality {
+        Temporality::Cumulative
+    }
+
+    fn shutdown(&self) -> OTelSdkResult {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::histogram::RequestKey;
+
+    #[test]
+    fn test_window_latencies() {
+        let recorder = LatencyRecorder::try_new(3, false).unwrap();
+        let mut snapshots = Vec::new();
+        for (ts, seconds) in [(1.0, 5.0), (2.0, 0.1), (3.0, 0.2)] {
+            recorder.record(seconds, &RequestKey::default(), false);
+            snapshots.push((Snapshot::empty(ts), recorder.take_interval()));
+        }
+        snapshots.push((Snapshot::empty(4.0), recorder.take_interval()));
+
+        // The slow response before the start of the window is left out
+        let window = window_latencies(snapshots[1..].iter()).unwrap();
+        assert_eq!(window.count(), 2);
+        assert!((window.percentile(100.0) - 0.2).abs() < 0.001);
+        assert!(window_latencies(snapshots[3..].iter()).is_none());
+    }
+}
@@ -1,10 +1,25 @@
-use crate::reporter::ExecMode;
-use crate::reporter::formatter::{format_dynamic_precision, format_iec, format_percent};
+use crate::control::{LoadChange, LoadControl, LoadEvent, Source};
+use crate::error_log::ErrorLog;
+use crate::histogram::{Latencies, LatencyRecorder, Phase, TimingRecorder};
+use crate::profile::StageProgress;
+use crate::reporter::formatter::{
+    format_bar, format_dynamic_precision, format_iec, format_message, format_metric,
+    format_percent, format_result, format_sparkline,
+};
 use crate::reporter::metrics::{
-    ErrorMetrics, RequestDurationSecondsMetrics, ResponseSizeBytesMetrics, StartTimeMetrics,
+    CheckMetrics, CheckResult, ClassLatency, ConnectionMetrics, DroppedMetrics, ErrorMetrics,
+    GroupLatency, RequestDurationSecondsMetrics, ResponseSizeBytesMetrics, StartTimeMetrics,
+    StepLatency,
 };
+use crate::reporter::timeseries::{Timeseries, TimeseriesPoint};
+use crate::reporter::tui::{Dashboard, Panel, columns, entry, latency_entry};
+use crate::reporter::{ExecMode, GroupBy};
+use crate::threshold::{Threshold, ThresholdMetric};
 use anyhow::Result;
-use serde::Serialize;
+use serde::ser::SerializeMap;
+use serde::{Serialize, Serializer};
+use std::collections::BTreeMap;
+use tabled::builder::Builder;
 use tabled::settings::object::{Cell, Rows};
 use tabled::settings::{Alignment, Border, Style};
 use tabled::{Table, Tabled};
@@ -15,38 +30,314 @@ const TITLE_PADDING_SPACES: usize = 13;
 pub(crate) struct Reporter {
     #[serde(skip)]
     pub bar: ProgressBar,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub aborted: Option<AbortReport>,
     pub summary: SummaryReport,
     pub counter: CounterReport,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub protocols: Vec<ProtocolReport>,
     pub duration: DurationReport,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub corrected_duration: Option<DurationReport>,
+    // The percentiles carry the same information in JSON
+    #[serde(skip)]
+    pub histogram: Vec<HistogramReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub timing: Vec<TimingReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub statuses: Vec<StatusReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub endpoints: Vec<EndpointReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub steps: Vec<StepReport>,
     pub response_size: ResponseSizeReport,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub checks: Vec<CheckReport>,
     pub errors: Vec<ErrorReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<EventReport>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub thresholds: Vec<ThresholdReport>,
+    // Too long for the text report
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub timeseries: Vec<TimeseriesPoint>,
     #[serde(skip)]
     horizontal_rule: HorizontalRule,
+    // Only used to evaluate the thresholds
+    #[serde(skip)]
+    run_rps: f64,
+    #[serde(skip)]
+    error_count: u64,
+    #[serde(skip)]
+    elapsed: f64,
 }
 
 impl Reporter {
     pub(crate) fn new(
         duration_meter: &RequestDurationSecondsMetrics,
+        corrected_meter: Option<&RequestDurationSecondsMetrics>,
         start_meter: &StartTimeMetrics,
         resp_size_meter: &ResponseSizeBytesMetrics,
         error_meter: Option<&ErrorMetrics>,
+        dropped_meter: Option<&DroppedMetrics>,
         kind: &ExecMode,
     ) -> Self {
-        let bar = ProgressBar::from_kinds(start_meter.elapsed_time(), duration_meter.count(), kind);
+        let bar = ProgressBar::from_kinds(
+            start_meter.elapsed_time(),
+            duration_meter.total_count(),
+            kind,
+        );
         let summary = SummaryReport::from(duration_meter);
-        let counter = CounterReport::from(duration_meter);
+        let counter = CounterReport::new(duration_meter, dropped_meter);
+        let protocols = ProtocolReport::from_versions(duration_meter.versions());
         let duration = DurationReport::from(duration_meter);
+        let corrected_duration = corrected_meter.map(DurationReport::corrected);
+        let statuses = duration_meter
+            .classes()
+            .into_iter()
+            .map(StatusReport::from)
+            .collect();
+        let steps = duration_meter
+            .steps()
+            .into_iter()
+            .map(StepReport::from)
+            .collect();
         let response_size = ResponseSizeReport::from(resp_size_meter);
         let errors = error_meter.map_or_else(Vec::new, Vec::<ErrorReport>::from);
+        // Unlike the summary, which shows the rate since the previous report
+        let elapsed = start_meter.elapsed_secs().max(1e-3);
+        let run_rps = duration_meter.total_count() as f64 / elapsed;
 
         Self {
             bar,
+            aborted: None,
             summary,
             counter,
+            protocols,
             duration,
+            corrected_duration,
+            timing: Vec::new(),
+            statuses,
+            endpoints: Vec::new(),
+            steps,
             response_size,
+            checks: Vec::new(),
             errors,
+            events: Vec::new(),
+            thresholds: Vec::new(),
+            timeseries: Vec::new(),
+            histogram: Vec::new(),
             horizontal_rule: HorizontalRule::new("─", 80),
+            run_rps,
+            error_count: error_meter.map_or(0, ErrorMetrics::count),
+            elapsed,
+        }
+    }
+
+    /// Percentiles from the recorded latencies instead of the OTel buckets
+    pub(crate) fn with_latencies(
+        self,
+        duration: Option<&LatencyRecorder>,
+        corrected: Option<&LatencyRecorder>,
+        percentiles: &[f64],
+    ) -> Self {
+        let Some(duration) = duration else {
+            return self;
+        };
+        let corrected_duration = self.corrected_duration.map(|report| {
+            let latencies = corrected.and_then(LatencyRecorder::all);
+            report.with_latencies(latencies, percentiles)
+        });
+        let statuses = self
+            .statuses
+            .into_iter()
+            .map(|status| {
+                let latencies = duration.latencies(&status.status);
+                StatusReport {
+                    latency: status.latency.with_latencies(latencies, percentiles),
+                    ..status
+                }
+            })
+            .collect();
+        let histogram = duration
+            .all()
+            .map(|latencies| HistogramReport::from_latencies(&latencies))
+            .unwrap_or_default();
+        Self {
+            duration: self.duration.with_latencies(duration.all(), percentiles),
+            corrected_duration,
+            statuses,
+            histogram,
+            ..self
+        }
+    }
+
+    /// Break the requests down by the values of the `group_by` attributes, with the percentiles
+    /// from the recorded latencies of every group when there are any
+    pub(crate) fn with_endpoints(
+        self,
+        duration_meter: &RequestDurationSecondsMetrics,
+        error_meter: Option<&ErrorMetrics>,
+        latency: Option<&LatencyRecorder>,
+        group_by: &[GroupBy],
+        percentiles: &[f64],
+    ) -> Self {
+        if group_by.is_empty() {
+            return self;
+        }
+
+        let mut latencies = latency.map_or_else(Default::default, LatencyRecorder::groups);
+        // The groups beyond the limit of the recorded latencies are reported as one
+        let fold = |key: Vec<String>| match latency {
+            Some(latency) => latency.group_of(key),
+            None => key,
+        };
+
+        let mut errors: BTreeMap<Vec<String>, u64> = BTreeMap::new();
+        if let Some(meter) = error_meter {
+            for (key, count) in meter.counts_by(group_by) {
+                *errors.entry(fold(key)).or_default() += count;
+            }
+        }
+        let mut groups: BTreeMap<Vec<String>, GroupLatency> = BTreeMap::new();
+        for group in duration_meter.groups(group_by, percentiles) {
+            let key = fold(group.key.clone());
+            let group = match groups.remove(&key) {
+                Some(merged) => merged.merge(group),
+                None => group,
+            };
+            groups.insert(key.clone(), GroupLatency { key, ..group });
+        }
+        let mut endpoints: Vec<_> = groups
+            .into_values()
+            .map(|group| {
+                let errors = errors.remove(&group.key).unwrap_or(0);
+                let recorded = latencies.remove(&group.key);
+                let report = EndpointReport::new(group_by, group, errors, self.elapsed);
+                match recorded {
+                    Some(recorded) => EndpointReport {
+                        latency: report.latency.with_latencies(Some(recorded), percentiles),
+                        ..report
+                    },
+                    None => report,
+                }
+            })
+            .collect();
+
+        // Endpoints that only ever failed have no latency
+        endpoints.extend(errors.into_iter().map(|(key, errors)| {
+            let group = GroupLatency {
+                key,
+                count: 0,
+                mean: 0.0,
+                percentiles: percentiles.iter().map(|percent| (*percent, 0.0)).collect(),
+                min: 0.0,
+                max: 0.0,
+            };
+            EndpointReport::new(group_by, group, errors, self.elapsed)
+        }));
+        endpoints.sort_by(|a, b| a.values().cmp(b.values()));
+
+        Self { endpoints, ..self }
+    }
+
+    /// Fill in the sample message of every error category from `error_log`
+    pub(crate) fn with_error_samples(mut self, error_log: Option<&ErrorLog>) -> Self {
+        if let Some(error_log) = error_log {
+            for error in &mut self.errors {
+                error.sample = error_log.sample(&error.category).unwrap_or_default();
+            }
+        }
+        self
+    }
+
+    pub(crate) fn with_checks(self, check_meter: Option<&CheckMetrics>) -> Self {
+        let checks = check_meter.map_or_else(Vec::new, |meter| {
+            meter.results().into_iter().map(CheckReport::from).collect()
+        });
+        Self { checks, ..self }
+    }
+
+    /// Evaluate the thresholds against this report, to be added after the other sections
+    pub(crate) fn with_thresholds(self, thresholds: &[Threshold]) -> Self {
+        let thresholds = thresholds
+            .iter()
+            .map(|threshold| ThresholdReport::new(threshold, self.actual(threshold.metric)))
+            .collect();
+        Self { thresholds, ..self }
+    }
+
+    pub(crate) fn with_connections(mut self, connection_meter: Option<&ConnectionMetrics>) -> Self {
+        self.counter.connections = connection_meter.map_or(0, ConnectionMetrics::count);
+        self
+    }
+
+    pub(crate) fn with_timing(self, timing: Option<&TimingRecorder>) -> Self {
+        let timing = timing.map_or_else(Vec::new, |timing| {
+            timing
+                .phases()
+                .into_iter()
+                .map(|(phase, latencies)| TimingReport::new(phase, &latencies))
+                .collect()
+        });
+        Self { timing, ..self }
+    }
+
+    pub(crate) fn with_aborted(self, reason: Option<String>) -> Self {
+        Self {
+            aborted: reason.map(AbortReport::new),
+            ..self
+        }
+    }
+
+    pub(crate) fn with_timeseries(self, timeseries: Option<&Timeseries>) -> Self {
+        Self {
+            timeseries: timeseries.map(Timeseries::points).unwrap_or_default(),
+            ..self
+        }
+    }
+
+    /// Changes of the load made during the run, e.g. from the keyboard or the control endpoint
+    pub(crate) fn with_events(
+        self,
+        control: Option<&LoadControl>,
+        start_meter: &StartTimeMetrics,
+    ) -> Self {
+        let events = control.map(LoadControl::events).unwrap_or_default();
+        Self {
+            events: events
+                .iter()
+                .map(|event| EventReport::new(event, start_meter.start_time()))
+                .collect(),
+            ..self
+        }
+    }
+
+    pub(crate) fn thresholds_passed(&self) -> bool {
+        self.thresholds.iter().all(|threshold| threshold.passed)
+    }
+
+    fn actual(&self, metric: ThresholdMetric) -> f64 {
+        match metric {
+            ThresholdMetric::Mean => self.duration.mean,
+            ThresholdMetric::Min => self.duration.min,
+            ThresholdMetric::Max => self.duration.max,
+            ThresholdMetric::Percentile(percent) => self.duration.percentile(percent),
+            ThresholdMetric::Rps => self.run_rps,
+            ThresholdMetric::ErrorRate => {
+                let total = self.counter.total + self.error_count;
+                self.error_count as f64 / total.max(1) as f64 * 100.0
+            }
+            ThresholdMetric::SuccessRate => self.summary.http_success_rate,
+            ThresholdMetric::CheckRate => {
+                let total: u64 = self.checks.iter().map(|check| check.total).sum();
+                let failed: u64 = self.checks.iter().map(|check| check.failed).sum();
+                // Without any check nothing has failed
+                if total == 0 {
+                    return 100.0;
+                }
+                total.saturating_sub(failed) as f64 / total as f64 * 100.0
+            }
         }
     }
 
@@ -58,18 +349,143 @@ impl Reporter {
         self.horizontal_rule.to_string()
     }
 
+    /// rps and p99 of the last seconds, None before the first of them
+    pub(crate) fn sparklines(&self) -> Option<String> {
+        (!self.timeseries.is_empty()).then(|| Sparklines::new(&self.timeseries).to_string())
+    }
+
+    /// Panels of the interactive screen
+    pub(crate) fn dashboard(&self) -> Dashboard {
+        const SPARKLINE_WIDTH: usize = 30;
+        let last = self.timeseries.last();
+        let recent = &self.timeseries[self.timeseries.len().saturating_sub(SPARKLINE_WIDTH)..];
+
+        let throughput = Panel::new(
+            "Throughput",
+            vec![
+                entry("Requests", self.counter.total.to_string()),
+                entry(
+                    "RPS",
+                    last.map_or_else(String::new, |point| format!("{:.2}", point.rps)),
+                ),
+                entry("Average", format!("{:.2}", self.run_rps)),
+                entry("2xx|3xx", format_percent(&self.summary.http_success_rate)),
+                format_sparkline(
+                    &recent
+                        .iter()
+                        .map(|point| Some(point.rps))
+                        .collect::<Vec<_>>(),
+                ),
+            ],
+        );
+
+        let latency = Panel::new(
+            "Latency",
+            vec![
+                latency_entry("Mean", self.duration.mean),
+                latency_entry("P50", self.duration.percentile(50.0)),
+                latency_entry("P99", self.duration.percentile(99.0)),
+                latency_entry("Max", self.duration.max),
+                format_sparkline(&recent.iter().map(|point| point.p99).collect::<Vec<_>>()),
+            ],
+        );
+
+        let attempts = self.counter.total + self.error_count;
+        let error_rate = match attempts {
+            0 => 0.0,
+            attempts => self.error_count as f64 / attempts as f64 * 100.0,
+        };
+        let errors = Panel::new(
+            "Errors",
+            [
+                entry("Errors", self.error_count.to_string()),
+                entry("Error Rate", format_percent(&error_rate)),
+            ]
+            .into_iter()
+            .chain(
+                self.errors
+                    .iter()
+                    .map(|error| format!("{}: {}", error.category, error.count)),
+            )
+            .collect(),
+        );
+
+        let rows: Vec<_> = self
+            .endpoints
+            .first()
+            .map(LatencyRow::headers)
+            .into_iter()
+            .chain(self.endpoints.iter().map(LatencyRow::fields))
+            .collect();
+        let endpoints = Panel::new("Endpoints", columns(&rows));
+
+        Dashboard {
+            progress: self.progress_bar(),
+            throughput,
+            latency,
+            errors,
+            endpoints,
+        }
+    }
+
     pub(crate) fn text(self) -> Result<String> {
-        let mut reports = vec![
-            table(&[self.summary])?,
-            table(&[self.counter])?,
-            table(&[self.duration])?,
-            table(&[self.response_size])?,
-        ];
+        let mut reports = Vec::new();
+
+        if let Some(aborted) = self.aborted {
+            reports.push(table(&[aborted])?);
+        }
+
+        reports.extend([table(&[self.summary])?, table(&[self.counter])?]);
+
+        if !self.protocols.is_empty() {
+            reports.push(table(&self.protocols)?);
+        }
+
+        reports.push(latency_table(
+            [self.duration].into_iter().chain(self.corrected_duration),
+        )?);
+
+        // A bimodal distribution, e.g. cache hits and misses, does not show in the percentiles
+        if self.histogram.len() > 1 {
+            reports.push(table(&self.histogram)?);
+        }
+
+        if !self.timing.is_empty() {
+            reports.push(table(&self.timing)?);
+        }
+
+        // With a single class the breakdown repeats the latencies above
+        if self.statuses.len() > 1 {
+            reports.push(latency_table(self.statuses)?);
+        }
+
+        // The same goes for a single endpoint
+        if self.endpoints.len() > 1 {
+            reports.push(latency_table(self.endpoints)?);
+        }
+
+        if !self.steps.is_empty() {
+            reports.push(table(&self.steps)?);
+        }
+
+        reports.push(table(&[self.response_size])?);
+
+        if !self.checks.is_empty() {
+            reports.push(table(&self.checks)?);
+        }
 
         if !self.errors.is_empty() {
             reports.push(table(&self.errors)?);
         }
 
+        if !self.events.is_empty() {
+            reports.push(table(&self.events)?);
+        }
+
+        if !self.thresholds.is_empty() {
+            reports.push(table(&self.thresholds)?);
+        }
+
         // add empty line
         reports.push(String::new());
 
@@ -87,6 +503,7 @@ pub struct ProgressBar {
     current_display: String,
     target: u64,
     target_display: String,
+    stage: Option<StageProgress>,
 }
 
 impl ProgressBar {
@@ -102,32 +519,89 @@ impl std::fmt::Display for ProgressBar {
         let filled_len = (Self::BAR_CHAR_COUNT as f64 * ratio).round() as usize;
         let empty_len = Self::BAR_CHAR_COUNT - filled_len;
 
+        let stage = self
+            .stage
+            .as_ref()
+            .map_or_else(String::new, |stage| format!(" {}", stage));
+
         let bar = format!(
-            "  {:>5}/{:<5} [{}>{}] {:.1}%\n",
+            "  {:>5}/{:<5} [{}>{}] {:.1}%{}\n",
             self.current_display,
             self.target_display,
             "=".repeat(filled_len),
             " ".repeat(empty_len),
-            ratio * 100.0
+            ratio * 100.0,
+            stage
         );
         write!(f, "{}", bar)
     }
 }
 
 impl ProgressBar {
-    fn new(current: u64, current_display: String, target: u64, target_display: String) -> Self {
+    fn new(
+        current: u64,
+        current_display: String,
+        target: u64,
+        target_display: String,
+        stage: Option<StageProgress>,
+    ) -> Self {
         Self {
             current,
             current_display,
             target,
             target_display,
+            stage,
         }
     }
 
     fn from_kinds(elapsed: u64, count: u64, kind: &ExecMode) -> Self {
         let (current, current_display, target, target_display) =
             kind.state_progress(elapsed, count);
-        Self::new(current, current_display, target, target_display)
+        let stage = kind.stage_progress(elapsed);
+        Self::new(current, current_display, target, target_display, stage)
+    }
+}
+
+/// rps and p99 of every second as sparklines, with the values of the last one
+#[derive(Debug)]
+pub struct Sparklines {
+    rps: Vec<Option<f64>>,
+    p99: Vec<Option<f64>>,
+}
+
+impl Sparklines {
+    const WIDTH: usize = 60;
+
+    fn new(timeseries: &[TimeseriesPoint]) -> Self {
+        let points = &timeseries[timeseries.len().saturating_sub(Self::WIDTH)..];
+        Self {
+            rps: points.iter().map(|point| Some(point.rps)).collect(),
+            p99: points.iter().map(|point| point.p99).collect(),
+        }
+    }
+}
+
+impl std::fmt::Display for Sparklines {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let last = |values: &[Option<f64>]| values.last().copied().flatten();
+        let rps = last(&self.rps).map_or_else(String::new, |rps| format!("{:.2}", rps));
+        let p99 = last(&self.p99).map_or_else(String::new, |p99| format_dynamic_precision(&p99));
+        writeln!(
+            f,
+            "  {:<4}{:<width$} {}",
+            "RPS",
+            format_sparkline(&self.rps),
+            rps,
+            width = Self::WIDTH
+        )?;
+        writeln!(
+            f,
+            "  {:<4}{:<width$} {}",
+            "P99",
+            format_sparkline(&self.p99),
+            p99,
+            width = Self::WIDTH
+        )
     }
 }
 
@@ -159,6 +633,65 @@ impl std::fmt::Display for HorizontalRule {
     }
 }
 
+#[derive(Debug, Tabled, Serialize)]
+#[tabled(rename_all = "Pascal")]
+pub(crate) struct AbortReport {
+    #[tabled(rename = "Aborted")]
+    #[serde(skip)]
+    _title: String,
+    reason: String,
+}
+
+impl AbortReport {
+    fn new(reason: String) -> Self {
+        Self {
+            _title: dummy_title(),
+            reason,
+        }
+    }
+}
+
+/// One range of the latency distribution
+#[derive(Debug, Tabled)]
+#[tabled(rename_all = "Pascal")]
+pub(crate) struct HistogramReport {
+    #[tabled(rename = "Histogram")]
+    _title: String,
+    #[tabled(display = "format_dynamic_precision")]
+    latency: f64,
+    count: u64,
+    #[tabled(rename = "")]
+    bar: String,
+}
+
+impl HistogramReport {
+    const BINS: usize = 10;
+    const BAR_CHAR_COUNT: usize = 40;
+
+    fn from_latencies(latencies: &Latencies) -> Vec<Self> {
+        let distribution = latencies.distribution(Self::BINS);
+        let max = distribution
+            .iter()
+            .map(|(_, count)| *count)
+            .max()
+            .unwrap_or(0);
+        distribution
+            .into_iter()
+            .map(|(latency, count)| Self {
+                _title: dummy_title(),
+                latency,
+                count,
+                // Padded, the bars read from the left in the right-aligned table
+                bar: format!(
+                    "{:<width$}",
+                    format_bar(count, max, Self::BAR_CHAR_COUNT),
+                    width = Self::BAR_CHAR_COUNT
+                ),
+            })
+            .collect()
+    }
+}
+
 #[derive(Debug, Tabled, Serialize)]
 #[tabled(rename_all = "Pascal")]
 pub(crate) struct SummaryReport {
@@ -201,10 +734,13 @@ pub(crate) struct CounterReport {
     #[serde(rename = "5xx")]
     _5xx: u64,
     total: u64,
+    dropped: u64,
+    /// Opened over the run, including the ones opened again after being closed
+    connections: u64,
 }
 
-impl From<&RequestDurationSecondsMetrics> for CounterReport {
-    fn from(item: &RequestDurationSecondsMetrics) -> Self {
+impl CounterReport {
+    fn new(item: &RequestDurationSecondsMetrics, dropped: Option<&DroppedMetrics>) -> Self {
         Self {
             _title: dummy_title(),
             _2xx: item.status_2xx_count(),
@@ -212,42 +748,345 @@ impl From<&RequestDurationSecondsMetrics> for CounterReport {
             _4xx: item.status_4xx_count(),
             _5xx: item.status_5xx_count(),
             total: item.count(),
+            dropped: dropped.map_or(0, DroppedMetrics::count),
+            connections: 0,
         }
     }
 }
 
+/// Responses over one protocol version, e.g. HTTP/1.1 after a declined h2c upgrade
 #[derive(Debug, Tabled, Serialize)]
 #[tabled(rename_all = "Pascal")]
-pub(crate) struct DurationReport {
-    #[tabled(rename = "Duration")]
+pub(crate) struct ProtocolReport {
+    #[tabled(rename = "Protocol")]
     #[serde(skip)]
     _title: String,
-    #[tabled(display = "format_dynamic_precision")]
+    #[tabled(skip)]
+    version: String,
+    count: u64,
+    #[tabled(display = "format_percent")]
+    share: f64,
+}
+
+impl ProtocolReport {
+    fn from_versions(versions: Vec<(String, u64)>) -> Vec<Self> {
+        let total: u64 = versions.iter().map(|(_, count)| count).sum();
+        versions
+            .into_iter()
+            .map(|(version, count)| ProtocolReport {
+                _title: format!("{:>width$}", version, width = TITLE_PADDING_SPACES),
+                version,
+                count,
+                share: count as f64 / total.max(1) as f64 * 100.0,
+            })
+            .collect()
+    }
+}
+
+/// Latencies of one row, with the percentiles asked for on the command line
+#[derive(Debug)]
+pub(crate) struct DurationReport {
+    _title: String,
     mean: f64,
-    #[tabled(display = "format_dynamic_precision")]
-    p50: f64,
-    #[tabled(display = "format_dynamic_precision")]
-    p95: f64,
-    #[tabled(display = "format_dynamic_precision")]
-    p99: f64,
-    #[tabled(display = "format_dynamic_precision")]
+    // (percent, latency) pairs in the order they are shown
+    percentiles: Vec<(f64, f64)>,
     min: f64,
-    #[tabled(display = "format_dynamic_precision")]
     max: f64,
+    latencies: Option<Latencies>,
 }
 
 impl From<&RequestDurationSecondsMetrics> for DurationReport {
     fn from(item: &RequestDurationSecondsMetrics) -> Self {
-        let a = 1.0;
-        let _b = format_dynamic_precision(&a);
         DurationReport {
             _title: dummy_title(),
             mean: item.mean(),
-            p50: item.p50(),
-            p95: item.p95(),
-            p99: item.p99(),
+            percentiles: vec![(50.0, item.p50()), (95.0, item.p95()), (99.0, item.p99())],
             min: item.min(),
             max: item.max(),
+            latencies: None,
+        }
+    }
+}
+
+impl DurationReport {
+    // Shown on its own row under the uncorrected latencies, the way wrk2 reports them
+    fn corrected(item: &RequestDurationSecondsMetrics) -> Self {
+        Self {
+            _title: format!("{:>width$}", "Corrected", width = TITLE_PADDING_SPACES),
+            ..Self::from(item)
+        }
+    }
+
+    // The recorded latencies are exact to their precision, unlike the interpolated buckets
+    fn with_latencies(self, latencies: Option<Latencies>, percentiles: &[f64]) -> Self {
+        let percentiles = percentiles
+            .iter()
+            .map(|percent| {
+                let latency = latencies
+                    .as_ref()
+                    .map_or(0.0, |latencies| latencies.percentile(*percent));
+                (*percent, latency.min(self.max))
+            })
+            .collect();
+        Self {
+            percentiles,
+            latencies,
+            ..self
+        }
+    }
+
+    /// Latency at `percent`, from the recorded latencies when there are any
+    fn percentile(&self, percent: f64) -> f64 {
+        match &self.latencies {
+            Some(latencies) => latencies.percentile(percent).min(self.max),
+            None => self
+                .percentiles
+                .iter()
+                .find(|(shown, _)| *shown == percent)
+                .map_or(0.0, |(_, latency)| *latency),
+        }
+    }
+
+    // Names and values of the latency columns, the keys are the ones of the JSON output
+    fn entries(&self) -> Vec<(String, f64)> {
+        let percentiles = self
+            .percentiles
+            .iter()
+            .map(|(percent, latency)| (format!("p{}", percent), *latency));
+        std::iter::once(("mean".to_string(), self.mean))
+            .chain(percentiles)
+            .chain([("min".to_string(), self.min), ("max".to_string(), self.max)])
+            .collect()
+    }
+}
+
+impl LatencyRow for DurationReport {
+    fn headers(&self) -> Vec<String> {
+        std::iter::once("Duration".to_string())
+            .chain(
+                self.entries()
+                    .into_iter()
+                    .map(|(name, _)| capitalize(&name)),
+            )
+            .collect()
+    }
+
+    fn fields(&self) -> Vec<String> {
+        std::iter::once(self._title.clone())
+            .chain(
+                self.entries()
+                    .iter()
+                    .map(|(_, latency)| format_dynamic_precision(latency)),
+            )
+            .collect()
+    }
+}
+
+impl Serialize for DurationReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let entries = self.entries();
+        let mut map = serializer.serialize_map(Some(entries.len()))?;
+        for (name, latency) in &entries {
+            map.serialize_entry(name, latency)?;
+        }
+        map.end()
+    }
+}
+
+/// Latencies of the responses of one status class
+#[derive(Debug)]
+pub(crate) struct StatusReport {
+    status: String,
+    count: u64,
+    latency: DurationReport,
+}
+
+impl From<ClassLatency> for StatusReport {
+    fn from(item: ClassLatency) -> Self {
+        StatusReport {
+            latency: DurationReport {
+                _title: format!("{:>width$}", item.class, width = TITLE_PADDING_SPACES),
+                mean: item.mean,
+                percentiles: vec![(50.0, item.p50), (95.0, item.p95), (99.0, item.p99)],
+                min: item.min,
+                max: item.max,
+                latencies: None,
+            },
+            status: item.class,
+            count: item.count,
+        }
+    }
+}
+
+impl LatencyRow for StatusReport {
+    fn headers(&self) -> Vec<String> {
+        let mut headers = self.latency.headers();
+        headers[0] = "Status".to_string();
+        headers.insert(1, "Count".to_string());
+        headers
+    }
+
+    fn fields(&self) -> Vec<String> {
+        let mut fields = self.latency.fields();
+        fields.insert(1, self.count.to_string());
+        fields
+    }
+}
+
+impl Serialize for StatusReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let entries = self.latency.entries();
+        let mut map = serializer.serialize_map(Some(entries.len() + 2))?;
+        map.serialize_entry("status", &self.status)?;
+        map.serialize_entry("count", &self.count)?;
+        for (name, latency) in &entries {
+            map.serialize_entry(name, latency)?;
+        }
+        map.end()
+    }
+}
+
+/// Requests of one endpoint, identified by the values of the grouped attributes
+#[derive(Debug)]
+pub(crate) struct EndpointReport {
+    key: Vec<(GroupBy, String)>,
+    count: u64,
+    rps: f64,
+    error_rate: f64,
+    latency: DurationReport,
+}
+
+impl EndpointReport {
+    fn new(group_by: &[GroupBy], group: GroupLatency, errors: u64, elapsed: f64) -> Self {
+        let title = group.key.join(" ");
+        let attempts = group.count + errors;
+        Self {
+            key: group_by.iter().copied().zip(group.key).collect(),
+            count: group.count,
+            rps: group.count as f64 / elapsed,
+            error_rate: errors as f64 / attempts.max(1) as f64 * 100.0,
+            latency: DurationReport {
+                _title: format!("{:>width$}", title, width = TITLE_PADDING_SPACES),
+                mean: group.mean,
+                percentiles: group.percentiles,
+                min: group.min,
+                max: group.max,
+                latencies: None,
+            },
+        }
+    }
+
+    fn values(&self) -> impl Iterator<Item = &String> {
+        self.key.iter().map(|(_, value)| value)
+    }
+}
+
+impl LatencyRow for EndpointReport {
+    fn headers(&self) -> Vec<String> {
+        let mut headers = self.latency.headers();
+        headers[0] = "Endpoint".to_string();
+        headers.splice(
+            1..1,
+            ["Count", "RPS", "Error Rate"].map(ToString::to_string),
+        );
+        headers
+    }
+
+    fn fields(&self) -> Vec<String> {
+        let mut fields = self.latency.fields();
+        fields.splice(
+            1..1,
+            [
+                self.count.to_string(),
+                format!("{:.2}", self.rps),
+                format_percent(&self.error_rate),
+            ],
+        );
+        fields
+    }
+}
+
+impl Serialize for EndpointReport {
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let entries = self.latency.entries();
+        let mut map = serializer.serialize_map(Some(self.key.len() + entries.len() + 3))?;
+        for (attribute, value) in &self.key {
+            map.serialize_entry(attribute.as_ref(), value)?;
+        }
+        map.serialize_entry("count", &self.count)?;
+        map.serialize_entry("rps", &self.rps)?;
+        map.serialize_entry("error_rate", &self.error_rate)?;
+        for (name, latency) in &entries {
+            map.serialize_entry(name, latency)?;
+        }
+        map.end()
+    }
+}
+
+/// Latency of one phase of the requests, connection phases only count new connections
+#[derive(Debug, Tabled, Serialize)]
+#[tabled(rename_all = "Pascal")]
+pub(crate) struct TimingReport {
+    #[tabled(rename = "Timing breakdown")]
+    #[serde(skip)]
+    _title: String,
+    #[tabled(skip)]
+    phase: String,
+    count: u64,
+    #[tabled(display = "format_dynamic_precision")]
+    mean: f64,
+    #[tabled(display = "format_dynamic_precision")]
+    p50: f64,
+    #[tabled(display = "format_dynamic_precision")]
+    p99: f64,
+}
+
+impl TimingReport {
+    fn new(phase: Phase, latencies: &Latencies) -> Self {
+        TimingReport {
+            _title: format!("{:>width$}", phase.as_ref(), width = TITLE_PADDING_SPACES),
+            phase: phase.as_ref().to_string(),
+            count: latencies.count(),
+            mean: latencies.mean(),
+            p50: latencies.percentile(50.0),
+            p99: latencies.percentile(99.0),
+        }
+    }
+}
+
+#[derive(Debug, Tabled, Serialize)]
+#[tabled(rename_all = "Pascal")]
+pub(crate) struct StepReport {
+    #[tabled(rename = "Step")]
+    #[serde(skip)]
+    _title: String,
+    #[tabled(skip)]
+    scenario: String,
+    #[tabled(skip)]
+    step: String,
+    count: u64,
+    #[tabled(display = "format_dynamic_precision")]
+    mean: f64,
+    #[tabled(display = "format_dynamic_precision")]
+    p50: f64,
+    #[tabled(display = "format_dynamic_precision")]
+    p95: f64,
+    #[tabled(display = "format_dynamic_precision")]
+    p99: f64,
+}
+
+impl From<StepLatency> for StepReport {
+    fn from(item: StepLatency) -> Self {
+        let title = format!("{}/{}", item.scenario, item.step);
+        StepReport {
+            _title: format!("{:>width$}", title, width = TITLE_PADDING_SPACES),
+            scenario: item.scenario,
+            step: item.step,
+            count: item.count,
+            mean: item.mean,
+            p50: item.p50,
+            p95: item.p95,
+            p99: item.p99,
         }
     }
 }
@@ -274,27 +1113,127 @@ impl From<&ResponseSizeBytesMetrics> for ResponseSizeReport {
     }
 }
 
+#[derive(Debug, Tabled, Serialize)]
+#[tabled(rename_all = "Pascal")]
+pub(crate) struct CheckReport {
+    #[tabled(rename = "Check")]
+    #[serde(skip)]
+    _title: String,
+    #[tabled(skip)]
+    name: String,
+    total: u64,
+    failed: u64,
+    #[tabled(rename = "Pass Rate", display = "format_percent")]
+    pass_rate: f64,
+}
+
+impl From<CheckResult> for CheckReport {
+    fn from(item: CheckResult) -> Self {
+        let passed = item.total.saturating_sub(item.failed);
+        CheckReport {
+            _title: format!("{:>width$}", item.name, width = TITLE_PADDING_SPACES),
+            name: item.name,
+            total: item.total,
+            failed: item.failed,
+            pass_rate: passed as f64 / item.total.max(1) as f64 * 100.0,
+        }
+    }
+}
+
+/// Errors of one category, with the message of one of them to tell what went wrong
 #[derive(Debug, Tabled, Serialize)]
 #[tabled(rename_all = "Pascal")]
 pub(crate) struct ErrorReport {
     #[tabled(rename = "Error")]
     #[serde(skip)]
     _title: String,
-    message: String,
+    #[tabled(skip)]
+    category: String,
+    count: u64,
+    // Cut in the text report, the JSON has the whole message
+    #[tabled(display = "format_message")]
+    sample: String,
 }
 
 impl From<&ErrorMetrics> for Vec<ErrorReport> {
     fn from(item: &ErrorMetrics) -> Self {
-        item.error_messages()
+        item.categories()
             .into_iter()
-            .map(|message| ErrorReport {
-                _title: dummy_title(),
-                message,
+            .map(|(category, count)| ErrorReport {
+                _title: format!("{:>width$}", category, width = TITLE_PADDING_SPACES),
+                category,
+                count,
+                sample: String::new(),
             })
             .collect()
     }
 }
 
+#[derive(Debug, Tabled, Serialize)]
+#[tabled(rename_all = "Pascal")]
+pub(crate) struct ThresholdReport {
+    #[tabled(rename = "Threshold")]
+    #[serde(skip)]
+    _title: String,
+    #[tabled(skip)]
+    threshold: String,
+    #[tabled(skip)]
+    actual: f64,
+    #[tabled(rename = "Actual")]
+    #[serde(skip)]
+    _actual: String,
+    #[tabled(rename = "Result", display = "format_result")]
+    passed: bool,
+}
+
+impl ThresholdReport {
+    fn new(threshold: &Threshold, actual: f64) -> Self {
+        let title = threshold.to_string();
+        Self {
+            _title: format!("{:>width$}", title, width = TITLE_PADDING_SPACES),
+            threshold: title,
+            actual,
+            _actual: format_metric(threshold.metric, &actual),
+            passed: threshold.passes(actual),
+        }
+    }
+}
+
+/// A change of the load, at the time since the start of the run
+#[derive(Debug, Tabled, Serialize)]
+#[tabled(rename_all = "Pascal")]
+pub(crate) struct EventReport {
+    #[tabled(rename = "Events")]
+    #[serde(skip)]
+    _title: String,
+    #[tabled(skip)]
+    elapsed: f64,
+    #[serde(flatten)]
+    change: LoadChange,
+    source: Source,
+}
+
+impl EventReport {
+    fn new(event: &LoadEvent, start_time: f64) -> Self {
+        // Changed before the first request, e.g. paused right away
+        let elapsed = (event.ts - start_time).max(0.0);
+        Self {
+            _title: format!("{:>width$.1}s", elapsed, width = TITLE_PADDING_SPACES - 1),
+            elapsed,
+            change: event.change,
+            source: event.source,
+        }
+    }
+}
+
+// "p99.9" is shown as "P99.9"
+fn capitalize(name: &str) -> String {
+    let mut chars = name.chars();
+    chars.next().map_or_else(String::new, |first| {
+        first.to_uppercase().chain(chars).collect()
+    })
+}
+
 fn dummy_title() -> String {
     " ".repeat(TITLE_PADDING_SPACES)
 }
@@ -304,16 +1243,277 @@ where
     I: IntoIterator<Item = T>,
     T: Tabled,
 {
+    style(Table::new(iter))
+}
+
+/// A row whose columns depend on the percentiles asked for, so it cannot derive `Tabled`
+trait LatencyRow {
+    fn headers(&self) -> Vec<String>;
+    fn fields(&self) -> Vec<String>;
+}
+
+fn latency_table<I, T>(rows: I) -> Result<String>
+where
+    I: IntoIterator<Item = T>,
+    T: LatencyRow,
+{
+    let mut builder = Builder::default();
+    for (n, row) in rows.into_iter().enumerate() {
+        if n == 0 {
+            builder.push_record(row.headers());
+        }
+        builder.push_record(row.fields());
+    }
+    style(builder.build())
+}
+
+fn style(mut table: Table) -> Result<String> {
     let header_border = Border::full(' ', '─', ' ', ' ', ' ', ' ', '─', '─');
     let top_left_border = Border::full(' ', '─', ' ', '│', ' ', ' ', ' ', '┼');
     let top_bottom_border = Border::full('─', ' ', ' ', '│', ' ', '┼', ' ', ' ');
 
-    let mut table = Table::new(iter);
     table.with(Style::blank());
     table.with(Alignment::right());
     table.modify(Rows::first(), header_border);
     table.modify(Cell::new(0, 0), top_left_border);
-    table.modify(Cell::new(1, 0), top_bottom_border);
+    let rows = table.count_rows();
+    if rows <= 2 {
+        table.modify(Cell::new(1, 0), top_bottom_border);
+    } else {
+        // Following rows continue the vertical rule, the blank line stays under the last row only
+        let first_row_border = Border::new().top('─').right('│').corner_top_right('┼');
+        table.modify(Cell::new(1, 0), first_row_border);
+        for row in 2..rows {
+            table.modify(Cell::new(row, 0), Border::new().right('│'));
+        }
+        table.modify(Cell::new(rows - 1, 0), Border::new().bottom(' '));
+    }
 
     Ok(table.to_string())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::time::Duration;
+
+    #[derive(Tabled)]
+    struct Row {
+        #[tabled(rename = "Title")]
+        _title: String,
+        value: u64,
+    }
+
+    #[test]
+    fn test_table_multiple_rows() {
+        let rows = [
+            Row {
+                _title: "".to_string(),
+                value: 1,
+            },
+            Row {
+                _title: "second".to_string(),
+                value: 2,
+            },
+        ];
+        let lines = table(rows).unwrap();
+        let lines: Vec<_> = lines.lines().map(str::trim_end).collect();
+
+        assert_eq!(lines.len(), 6);
+        assert_eq!(lines[2], " ────────┼────────");
+        assert_eq!(lines[3], "         │     1");
+        assert_eq!(lines[4], "  second │     2");
+        assert_eq!(lines[5], "");
+    }
+
+    #[test]
+    fn test_status_report() {
+        let mut report = StatusReport::from(ClassLatency {
+            class: "5xx".to_string(),
+            count: 3,
+            mean: 0.5,
+            p50: 0.4,
+            p95: 0.9,
+            p99: 0.95,
+            min: 0.1,
+            max: 1.0,
+        });
+        report.latency.percentiles = vec![(90.0, 0.8), (99.9, 0.99)];
+
+        assert_eq!(
+            report.headers(),
+            ["Status", "Count", "Mean", "P90", "P99.9", "Min", "Max"]
+        );
+        assert_eq!(report.fields()[..3], ["          5xx", "3", "0.500s"]);
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(
+            json,
+            r#"{"status":"5xx","count":3,"mean":0.5,"p90":0.8,"p99.9":0.99,"min":0.1,"max":1.0}"#
+        );
+    }
+
+    #[test]
+    fn test_endpoint_report() {
+        let group = GroupLatency {
+            key: vec!["GET".to_string(), "/items".to_string()],
+            count: 30,
+            mean: 0.2,
+            percentiles: vec![(99.0, 0.5)],
+            min: 0.1,
+            max: 0.6,
+        };
+        let report = EndpointReport::new(&[GroupBy::Method, GroupBy::Path], group, 10, 10.0);
+
+        assert_eq!(
+            report.headers(),
+            [
+                "Endpoint",
+                "Count",
+                "RPS",
+                "Error Rate",
+                "Mean",
+                "P99",
+                "Min",
+                "Max"
+            ]
+        );
+        assert_eq!(
+            report.fields()[..4],
+            ["   GET /items", "30", "3.00", "25.00%"]
+        );
+
+        let json = serde_json::to_string(&report).unwrap();
+        assert_eq!(
+            json,
+            r#"{"method":"GET","path":"/items","count":30,"rps":3.0,"error_rate":25.0,"mean":0.2,"p99":0.5,"min":0.1,"max":0.6}"#
+        );
+    }
+
+    #[test]
+    fn test_sparklines() {
+        let timeseries: Vec<_> = (1..=100)
+            .map(|n| TimeseriesPoint {
+                elapsed: n as f64,
+                rps: n as f64,
+                error_rate: 0.0,
+                p50: None,
+                p99: (n % 2 == 0).then_some(0.25),
+            })
+            .collect();
+
+        let lines = Sparklines::new(&timeseries).to_string();
+        let lines: Vec<_> = lines.lines().collect();
+        assert_eq!(lines.len(), 2);
+        // Only the last minute
+        assert!(lines[0].starts_with("  RPS ▄"));
+        assert!(lines[0].ends_with("█ 100.00"));
+        assert!(lines[1].starts_with("  P99  █ █"));
+        assert!(lines[1].ends_with("█ 0.250s"));
+    }
+
+    #[test]
+    fn test_event_report() {
+        let event = LoadEvent {
+            ts: 1012.34,
+            change: LoadChange::Clients { from: 10, to: 20 },
+            source: Source::Endpoint,
+        };
+        let report = EventReport::new(&event, 1000.0);
+
+        let text = table(&[report]).unwrap();
+        assert!(text.contains("Events"));
+        assert!(text.contains("12.3s"));
+        assert!(text.contains("clients 10 -> 20"));
+        assert!(text.contains("endpoint"));
+
+        let json = serde_json::to_value(EventReport::new(&event, 1000.0)).unwrap();
+        assert_eq!(json["event"], "clients");
+        assert_eq!(json["from"], 10);
+        assert_eq!(json["to"], 20);
+        assert_eq!(json["source"], "endpoint");
+        assert!((json["elapsed"].as_f64().unwrap() - 12.34).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_protocol_report() {
+        let reports = ProtocolReport::from_versions(vec![
+            ("HTTP/1.1".to_string(), 1),
+            ("HTTP/2".to_string(), 3),
+        ]);
+
+        let text = table(&reports).unwrap();
+        assert!(text.contains("Protocol"));
+        assert!(text.contains("HTTP/2"));
+        assert!(text.contains("75.00%"));
+
+        let json = serde_json::to_value(&reports).unwrap();
+        assert_eq!(json[0]["version"], "HTTP/1.1");
+        assert_eq!(json[1]["count"], 3);
+        assert_eq!(json[1]["share"], 75.0);
+    }
+
+    #[test]
+    fn test_timing_report() {
+        let recorder = TimingRecorder::try_new(3, false).unwrap();
+        for millis in [10, 10, 15, 25] {
+            recorder.record(Phase::Tls, Duration::from_millis(millis), false);
+        }
+        recorder.record(Phase::Ttfb, Duration::from_millis(100), false);
+        recorder.record(Phase::Ttfb, Duration::from_millis(100), false);
+
+        let reports: Vec<_> = recorder
+            .phases()
+            .into_iter()
+            .map(|(phase, latencies)| TimingReport::new(phase, &latencies))
+            .collect();
+        let text = table(&reports).unwrap();
+        assert!(text.contains("Timing breakdown"));
+        assert!(text.contains("TLS"));
+        assert!(text.contains("0.01s"));
+
+        let json = serde_json::to_value(&reports).unwrap();
+        assert_eq!(json[0]["phase"], "TLS");
+        assert_eq!(json[0]["count"], 4);
+        // Values are kept to the 3 significant digits of the histograms
+        let close = |value: &serde_json::Value, expected: f64| {
+            (value.as_f64().unwrap() - expected).abs() < expected / 1000.0
+        };
+        assert!(close(&json[0]["p50"], 0.01));
+        assert!(close(&json[0]["p99"], 0.025));
+        assert_eq!(json[1]["phase"], "TTFB");
+        assert_eq!(json[1]["count"], 2);
+        assert!(close(&json[1]["mean"], 0.1));
+        assert!(close(&json[1]["p99"], 0.1));
+    }
+
+    #[test]
+    fn test_error_report() {
+        let report = ErrorReport {
+            _title: format!(
+                "{:>width$}",
+                "tls_certificate",
+                width = TITLE_PADDING_SPACES
+            ),
+            category: "tls_certificate".to_string(),
+            count: 4,
+            sample: "invalid peer certificate: Expired".to_string(),
+        };
+
+        let text = table(&[report]).unwrap();
+        assert!(text.contains("Error"));
+        assert!(text.contains("tls_certificate"));
+        assert!(text.contains("invalid peer certificate: Expired"));
+
+        let json = serde_json::to_value(ErrorReport {
+            _title: dummy_title(),
+            category: "timeout".to_string(),
+            count: 1,
+            sample: "operation timed out".to_string(),
+        })
+        .unwrap();
+        assert_eq!(json["category"], "timeout");
+        assert_eq!(json["count"], 1);
+        assert_eq!(json["sample"], "operation timed out");
+    }
+}
@@ -1,9 +1,15 @@
-use crate::reporter::ExecMode;
+use crate::control::LoadControl;
+use crate::error_log::ErrorLog;
+use crate::histogram::{LatencyRecorder, TimingRecorder};
 use crate::reporter::components::Reporter;
 use crate::reporter::formatter::OutputFormat;
 use crate::reporter::metrics::{
-    ErrorMetrics, RequestDurationSecondsMetrics, ResponseSizeBytesMetrics, StartTimeMetrics,
+    CheckMetrics, ConnectionMetrics, DroppedMetrics, ErrorMetrics, RequestDurationSecondsMetrics,
+    ResponseSizeBytesMetrics, StartTimeMetrics,
 };
+use crate::reporter::timeseries::Timeseries;
+use crate::reporter::{ExecMode, GroupBy};
+use crate::threshold::{AbortSignal, Threshold, Verdict};
 use crate::time::now_ts;
 use anyhow::Result;
 use async_trait::async_trait;
@@ -12,7 +18,7 @@ use crossterm::queue;
 use crossterm::style::Print;
 use crossterm::{
     cursor,
-    terminal::{Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
+    terminal::{self, Clear, ClearType, EnterAlternateScreen, LeaveAlternateScreen},
 };
 use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
 use opentelemetry_sdk::metrics::{
@@ -27,8 +33,20 @@ use std::sync::{Arc, Mutex};
 pub struct StdoutExporter {
     output_format: OutputFormat,
     exec_mode: ExecMode,
+    include_warmup: bool,
     no_interactive: bool,
     no_clear_console: bool,
+    thresholds: Vec<Threshold>,
+    verdict: Option<Verdict>,
+    abort: Option<AbortSignal>,
+    latency: Option<LatencyRecorder>,
+    corrected_latency: Option<LatencyRecorder>,
+    timing: Option<TimingRecorder>,
+    percentiles: Vec<f64>,
+    group_by: Vec<GroupBy>,
+    timeseries: Option<Timeseries>,
+    control: Option<LoadControl>,
+    error_log: Option<ErrorLog>,
     last_count: Arc<AtomicU64>,
     last_ts: Arc<AtomicF64>,
     last_report: Arc<Mutex<String>>,
@@ -38,20 +56,95 @@ impl StdoutExporter {
     pub(crate) fn new(
         output_format: OutputFormat,
         exec_mode: ExecMode,
+        include_warmup: bool,
         no_interactive: bool,
         no_clear_console: bool,
     ) -> Self {
         Self {
             output_format,
             exec_mode,
+            include_warmup,
             no_interactive,
             no_clear_console,
+            thresholds: Vec::new(),
+            verdict: None,
+            abort: None,
+            latency: None,
+            corrected_latency: None,
+            timing: None,
+            percentiles: Vec::new(),
+            group_by: Vec::new(),
+            timeseries: None,
+            control: None,
+            error_log: None,
             last_count: Arc::new(AtomicU64::new(0)),
             last_ts: Arc::new(AtomicF64::new(0.0)),
             last_report: Arc::new(Mutex::new(String::new())),
         }
     }
 
+    /// Evaluate `thresholds` on every report, the outcome of the last one is kept in `verdict`
+    pub(crate) fn with_thresholds(self, thresholds: Vec<Threshold>, verdict: Verdict) -> Self {
+        Self {
+            thresholds,
+            verdict: Some(verdict),
+            ..self
+        }
+    }
+
+    /// Show why the run was aborted once `abort` has been signalled
+    pub(crate) fn with_abort(self, abort: AbortSignal) -> Self {
+        Self {
+            abort: Some(abort),
+            ..self
+        }
+    }
+
+    /// Report `percentiles` of the recorded latencies
+    pub(crate) fn with_latencies(
+        self,
+        latency: LatencyRecorder,
+        corrected_latency: LatencyRecorder,
+        percentiles: Vec<f64>,
+    ) -> Self {
+        Self {
+            latency: Some(latency),
+            corrected_latency: Some(corrected_latency),
+            percentiles,
+            ..self
+        }
+    }
+
+    /// Show the time spent in each phase of the requests from `timing`
+    pub(crate) fn with_timing(self, timing: TimingRecorder) -> Self {
+        Self {
+            timing: Some(timing),
+            ..self
+        }
+    }
+
+    /// Break the report down by the values of the `group_by` attributes
+    pub(crate) fn with_group_by(self, group_by: Vec<GroupBy>) -> Self {
+        Self { group_by, ..self }
+    }
+
+    /// Include the points of `timeseries` in the JSON report
+    pub(crate) fn with_timeseries(self, timeseries: Timeseries) -> Self {
+        Self {
+            timeseries: Some(timeseries),
+            ..self
+        }
+    }
+
+    /// Show the state of `control` and the latest errors of `error_log` on the interactive screen
+    pub(crate) fn with_control(self, control: LoadControl, error_log: ErrorLog) -> Self {
+        Self {
+            control: Some(control),
+            error_log: Some(error_log),
+            ..self
+        }
+    }
+
     fn update_last_report(&self, report: String) {
         *self.last_report.lock().unwrap() = report;
     }
@@ -68,7 +161,10 @@ impl StdoutExporter {
         let last_cnt = self.last_count.load(Relaxed);
         let last_ts = self.last_ts.load(Relaxed);
 
-        let meter_opt = RequestDurationSecondsMetrics::try_find(meter, last_cnt, last_ts)?;
+        let include_warmup = self.include_warmup;
+
+        let meter_opt =
+            RequestDurationSecondsMetrics::try_find(meter, last_cnt, last_ts, include_warmup)?;
         let duration_meter = match meter_opt {
             Some(duration_meter) => duration_meter,
             None => return Ok(String::from("Wait until metrics can be collected.")),
@@ -80,28 +176,60 @@ impl StdoutExporter {
             None => return Ok(String::from("Wait until metrics can be collected.")),
         };
 
-        let meter_opt = ResponseSizeBytesMetrics::find(meter);
+        let meter_opt = ResponseSizeBytesMetrics::find(meter, include_warmup);
         let resp_size_meter = match meter_opt {
             Some(resp_size_meter) => resp_size_meter,
             None => return Ok(String::from("Wait until metrics can be collected.")),
         };
 
-        let err_meter = ErrorMetrics::find(meter);
+        let corrected_meter =
+            RequestDurationSecondsMetrics::try_find_corrected(meter, include_warmup)?;
+        let err_meter = ErrorMetrics::find(meter, include_warmup);
+        let dropped_meter = DroppedMetrics::find(meter);
+        let connection_meter = ConnectionMetrics::find(meter);
+        let check_meter = CheckMetrics::find(meter, include_warmup);
 
         let reporter = Reporter::new(
             &duration_meter,
+            corrected_meter.as_ref(),
             &start_meter,
             &resp_size_meter,
             err_meter.as_ref(),
+            dropped_meter.as_ref(),
             &self.exec_mode,
-        );
+        )
+        .with_latencies(
+            self.latency.as_ref(),
+            self.corrected_latency.as_ref(),
+            &self.percentiles,
+        )
+        .with_endpoints(
+            &duration_meter,
+            err_meter.as_ref(),
+            self.latency.as_ref(),
+            &self.group_by,
+            &self.percentiles,
+        )
+        .with_error_samples(self.error_log.as_ref())
+        .with_checks(check_meter.as_ref())
+        .with_connections(connection_meter.as_ref())
+        .with_timing(self.timing.as_ref())
+        .with_thresholds(&self.thresholds)
+        .with_aborted(self.abort.as_ref().and_then(AbortSignal::reason))
+        .with_timeseries(self.timeseries.as_ref())
+        .with_events(self.control.as_ref(), &start_meter);
 
+        if let Some(verdict) = &self.verdict {
+            verdict.set(reporter.thresholds_passed());
+        }
+
+        let screen = self.screen(&reporter);
         let header = self.header(&reporter);
         let body = self.body(reporter)?;
-        let report = header.clone().unwrap_or_default() + &body;
+        let report = screen.unwrap_or_else(|| header.unwrap_or_default() + &body);
 
         self.update_last_report(body);
-        self.update_last_count(duration_meter.count());
+        self.update_last_count(duration_meter.total_count());
         self.update_last_ts();
 
         Ok(report)
@@ -113,17 +241,42 @@ impl StdoutExporter {
             self.no_interactive,
             self.no_clear_console,
         ) {
-            (OutputFormat::Text, false, false) => Some(reporter.progress_bar()),
             (OutputFormat::Text, false, true) => {
                 let rule = reporter.horizontal_rule();
                 let bar = reporter.progress_bar();
-                Some(format!("{}\n{}", rule, bar))
+                let sparklines = reporter.sparklines().unwrap_or_default();
+                Some(format!("{}\n{}{}", rule, bar, sparklines))
             }
             (OutputFormat::Text, _, _) => None,
             (OutputFormat::Json, _, _) => None,
         }
     }
 
+    // The interactive screen takes the whole terminal unless the console is kept
+    fn screen(&self, reporter: &Reporter) -> Option<String> {
+        match (
+            &self.output_format,
+            self.no_interactive,
+            self.no_clear_console,
+        ) {
+            (OutputFormat::Text, false, false) => {
+                let (width, height) = terminal::size().unwrap_or((80, 24));
+                let errors = self
+                    .error_log
+                    .as_ref()
+                    .map(|log| log.latest(height as usize))
+                    .unwrap_or_default();
+                let dashboard = reporter.dashboard();
+                Some(dashboard.render(
+                    self.control.as_ref(),
+                    &errors,
+                    (width as usize, height as usize),
+                ))
+            }
+            _ => None,
+        }
+    }
+
     fn body(&self, reporter: Reporter) -> Result<String> {
         match self.output_format {
             OutputFormat::Text => reporter.text(),
@@ -135,9 +288,8 @@ impl StdoutExporter {
         let mut stdout = stdout();
 
         if !self.no_clear_console {
-            queue!(stdout, EnterAlternateScreen)?;
-            let clear = Clear(ClearType::FromCursorDown);
-            queue!(stdout, cursor::RestorePosition, clear)?;
+            queue!(stdout, EnterAlternateScreen, cursor::Hide)?;
+            queue!(stdout, cursor::MoveTo(0, 0), Clear(ClearType::All))?;
         }
 
         queue!(stdout, Print(report))?;
@@ -149,7 +301,9 @@ impl StdoutExporter {
     fn last_print(&self) -> Result<()> {
         let mut stdout = stdout();
         // Output the last recorded report to the original buffer screen
-        queue!(stdout, LeaveAlternateScreen)?;
+        if !self.no_interactive && !self.no_clear_console {
+            queue!(stdout, LeaveAlternateScreen, cursor::Show)?;
+        }
         queue!(stdout, Print(self.last_report.lock().unwrap()))?;
         Ok(stdout.flush()?)
     }
@@ -163,8 +317,11 @@ impl PushMetricExporter for StdoutExporter {
             .await
             .map_err(|e| OTelSdkError::InternalFailure(e.to_string()))?;
 
-        self.print(report)
-            .map_err(|e| OTelSdkError::InternalFailure(e.to_string()))?;
+        // In non-interactive mode only the last report is printed, on shutdown
+        if !self.no_interactive {
+            self.print(report)
+                .map_err(|e| OTelSdkError::InternalFailure(e.to_string()))?;
+        }
 
         Ok(())
     }
@@ -1,3 +1,4 @@
+use crate::threshold::ThresholdMetric;
 use clap::ValueEnum;
 use strum::AsRefStr;
 
@@ -12,6 +13,61 @@ pub(crate) fn format_percent(value: &f64) -> String {
     format!("{:.2}%", value)
 }
 
+/// A value of the report in the unit of `metric`
+pub(crate) fn format_metric(metric: ThresholdMetric, value: &f64) -> String {
+    match metric {
+        ThresholdMetric::Rps => format!("{:.2}", value),
+        ThresholdMetric::ErrorRate | ThresholdMetric::SuccessRate | ThresholdMetric::CheckRate => {
+            format_percent(value)
+        }
+        _ => format_dynamic_precision(value),
+    }
+}
+
+pub(crate) fn format_result(passed: &bool) -> String {
+    if *passed { "pass" } else { "FAIL" }.to_string()
+}
+
+/// Values as a line of block characters scaled to the largest one, blank where there is none
+pub(crate) fn format_sparkline(values: &[Option<f64>]) -> String {
+    const BLOCKS: [char; 8] = ['▁', '▂', '▃', '▄', '▅', '▆', '▇', '█'];
+    let max = values.iter().flatten().copied().fold(0.0, f64::max);
+
+    values
+        .iter()
+        .map(|value| match value {
+            Some(value) if max > 0.0 => {
+                let level = (value / max * (BLOCKS.len() - 1) as f64).round() as usize;
+                BLOCKS[level.min(BLOCKS.len() - 1)]
+            }
+            Some(_) => BLOCKS[0],
+            None => ' ',
+        })
+        .collect()
+}
+
+/// A bar of `count` relative to `max` as wide as `width` at most
+pub(crate) fn format_bar(count: u64, max: u64, width: usize) -> String {
+    if max == 0 {
+        return String::new();
+    }
+    let len = (count as f64 / max as f64 * width as f64).round() as usize;
+    // Anything at all stays visible
+    let len = if count > 0 { len.max(1) } else { 0 };
+    "■".repeat(len)
+}
+
+/// The end of an error message, where the root cause is, cut to `MESSAGE_CHARS`
+pub(crate) fn format_message(message: &str) -> String {
+    const MESSAGE_CHARS: usize = 100;
+    let count = message.chars().count();
+    if count <= MESSAGE_CHARS {
+        return message.to_string();
+    }
+    let tail: String = message.chars().skip(count - (MESSAGE_CHARS - 1)).collect();
+    format!("…{}", tail)
+}
+
 pub(crate) fn format_iec(size: &u64) -> String {
     const UNITS: [&str; 5] = ["B", "KiB", "MiB", "GiB", "TiB"];
     let mut size = *size as f64;
@@ -53,13 +109,13 @@ pub(crate) fn format_duration(secs: &u64) -> String {
     if days > 0 {
         parts.push(format!("{}d", days));
     }
-    if hours % 24 > 0 {
+    if !hours.is_multiple_of(24) {
         parts.push(format!("{}h", hours % 24));
     }
-    if mins % 60 > 0 {
+    if !mins.is_multiple_of(60) {
         parts.push(format!("{}m", mins % 60));
     }
-    if secs % 60 > 0 || parts.is_empty() {
+    if !secs.is_multiple_of(60) || parts.is_empty() {
         parts.push(format!("{}s", secs % 60));
     }
 
@@ -90,6 +146,25 @@ pub(crate) fn format_dynamic_precision(value: &f64) -> String {
 mod tests {
     use super::*;
 
+    #[test]
+    fn test_format_sparkline() {
+        assert_eq!(format_sparkline(&[]), "");
+        assert_eq!(
+            format_sparkline(&[Some(0.0), Some(50.0), Some(100.0), None]),
+            "▁▅█ "
+        );
+        assert_eq!(format_sparkline(&[Some(0.0), Some(0.0)]), "▁▁");
+    }
+
+    #[test]
+    fn test_format_bar() {
+        assert_eq!(format_bar(10, 10, 4), "■■■■");
+        assert_eq!(format_bar(5, 10, 4), "■■");
+        assert_eq!(format_bar(1, 1000, 4), "■");
+        assert_eq!(format_bar(0, 10, 4), "");
+        assert_eq!(format_bar(0, 0, 4), "");
+    }
+
     #[test]
     fn test_format_si() {
         assert_eq!(format_si(&999), "999");
@@ -124,4 +199,14 @@ mod tests {
         assert_eq!(format_duration(&86400), "1d");
         assert_eq!(format_duration(&90061), "1d1h1m1s");
     }
+
+    #[test]
+    fn test_format_message() {
+        assert_eq!(format_message("connection refused"), "connection refused");
+        let message = format!("{}connection refused", "x".repeat(200));
+        let formatted = format_message(&message);
+        assert_eq!(formatted.chars().count(), 100);
+        assert!(formatted.starts_with('…'));
+        assert!(formatted.ends_with("xconnection refused"));
+    }
 }
@@ -1,13 +1,16 @@
+use crate::reporter::GroupBy;
 use crate::stats::percentile;
+use crate::threshold::ThresholdMetric;
 use crate::time::now_ts;
 use anyhow::{Context, Result};
+use opentelemetry::KeyValue;
 use opentelemetry_sdk::metrics::data::{
     self, GaugeDataPoint, Histogram, HistogramDataPoint, ResourceMetrics, Sum, SumDataPoint,
 };
 use std::collections::BTreeMap;
 use std::ops::AddAssign;
 
-#[derive(Debug, Default)]
+#[derive(Debug, Clone, Default)]
 struct Bucket {
     count: u64,
     bounds: Vec<f64>,
@@ -31,32 +34,141 @@ impl Bucket {
 impl AddAssign for Bucket {
     fn add_assign(&mut self, rhs: Self) {
         self.count += rhs.count;
+        // Data points of one histogram share the bounds, so the counts add up bucket by bucket
+        if self.bucket_counts.is_empty() {
+            self.bucket_counts = rhs.bucket_counts;
+        } else {
+            self.bucket_counts
+                .iter_mut()
+                .zip(rhs.bucket_counts)
+                .for_each(|(count, rhs)| *count += rhs);
+        }
         self.bounds = rhs.bounds;
-        self.bucket_counts = rhs.bucket_counts;
     }
 }
+
+impl Bucket {
+    fn percentile(&self, percent: f64, max: f64) -> f64 {
+        let pt = percentile(percent, &self.bucket_counts, &self.bounds).unwrap_or(0.0);
+        max.min(pt)
+    }
+}
+
+/// Latency of the responses of one status class
+#[derive(Debug)]
+pub(crate) struct ClassLatency {
+    pub class: String,
+    pub count: u64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+    pub min: f64,
+    pub max: f64,
+}
+
+/// Latency of the requests sharing the values of the grouped attributes
+#[derive(Debug)]
+pub(crate) struct GroupLatency {
+    pub key: Vec<String>,
+    pub count: u64,
+    pub mean: f64,
+    // (percent, latency) pairs
+    pub percentiles: Vec<(f64, f64)>,
+    pub min: f64,
+    pub max: f64,
+}
+
+impl GroupLatency {
+    /// Both groups as one, the percentiles are only bounded by the larger of the two
+    pub(crate) fn merge(self, other: Self) -> Self {
+        let count = self.count + other.count;
+        let mean = if count == 0 {
+            0.0
+        } else {
+            (self.mean * self.count as f64 + other.mean * other.count as f64) / count as f64
+        };
+        let percentiles = self
+            .percentiles
+            .iter()
+            .zip(&other.percentiles)
+            .map(|((percent, a), (_, b))| (*percent, a.max(*b)))
+            .collect();
+        Self {
+            key: self.key,
+            count,
+            mean,
+            percentiles,
+            min: self.min.min(other.min),
+            max: self.max.max(other.max),
+        }
+    }
+}
+
+/// Latency of one step of a journey
+#[derive(Debug)]
+pub(crate) struct StepLatency {
+    pub scenario: String,
+    pub step: String,
+    pub count: u64,
+    pub mean: f64,
+    pub p50: f64,
+    pub p95: f64,
+    pub p99: f64,
+}
 #[derive(Debug, Default)]
 pub struct RequestDurationSecondsMetrics {
     data_points: Vec<HistogramDataPoint<f64>>,
     counts: BTreeMap<String, Bucket>,
+    // Requests recorded during warm-up and left out of the statistics
+    excluded_count: u64,
     last_count: u64,
     last_ts: f64,
 }
 
 impl RequestDurationSecondsMetrics {
     const NAME: &'static str = "http_client_request_duration";
+    const CORRECTED_NAME: &'static str = "http_client_request_corrected_duration";
 
     pub(crate) fn try_find(
         meter: &ResourceMetrics,
         last_count: u64,
         last_ts: f64,
+        include_warmup: bool,
     ) -> Result<Option<Self>> {
-        let Some(hist) = find_metrcis::<Histogram<f64>>(meter, Self::NAME) else {
+        Self::try_find_by_name(meter, Self::NAME, last_count, last_ts, include_warmup)
+    }
+
+    // Latencies measured from the intended send time, only recorded when requests follow a schedule
+    pub(crate) fn try_find_corrected(
+        meter: &ResourceMetrics,
+        include_warmup: bool,
+    ) -> Result<Option<Self>> {
+        let corrected =
+            Self::try_find_by_name(meter, Self::CORRECTED_NAME, 0, 0.0, include_warmup)?;
+        Ok(corrected.filter(|corrected| corrected.count() > 0))
+    }
+
+    fn try_find_by_name(
+        meter: &ResourceMetrics,
+        name: &str,
+        last_count: u64,
+        last_ts: f64,
+        include_warmup: bool,
+    ) -> Result<Option<Self>> {
+        let Some(hist) = find_metrcis::<Histogram<f64>>(meter, name) else {
             return Ok(None);
         };
 
+        let (data_points, excluded): (Vec<_>, Vec<_>) = hist
+            .data_points
+            .iter()
+            .cloned()
+            .partition(|point| include_warmup || !is_warmup(&point.attributes));
+        let excluded_count = excluded.iter().map(|point| point.count).sum();
+
         let mut counts = BTreeMap::new();
-        for point in &hist.data_points {
+        for point in &data_points {
             let status = Self::status(point)?;
             let status_key = Self::status_key(&status)?;
 
@@ -70,8 +182,9 @@ impl RequestDurationSecondsMetrics {
         }
 
         Ok(Some(Self {
-            data_points: hist.data_points.clone(),
+            data_points,
             counts,
+            excluded_count,
             last_count,
             last_ts,
         }))
@@ -131,7 +244,7 @@ impl RequestDurationSecondsMetrics {
             return 0;
         }
 
-        let inc = self.count() - self.last_count;
+        let inc = self.total_count() - self.last_count;
         (inc as f64 / elapsed_secs) as u64
     }
 
@@ -169,6 +282,11 @@ impl RequestDurationSecondsMetrics {
         self.counts.values().map(|bucket| bucket.count()).sum()
     }
 
+    // Including warm-up, for progress and throughput
+    pub(crate) fn total_count(&self) -> u64 {
+        self.count() + self.excluded_count
+    }
+
     // for DurationReport
     // =================================================================================
     pub(crate) fn mean(&self) -> f64 {
@@ -179,11 +297,17 @@ impl RequestDurationSecondsMetrics {
         self.sum() / cnt as f64
     }
 
+    // Over every status class, like the mean, min and max
     fn percentile(&self, percent: f64) -> f64 {
-        self.counts.get("2xx").map_or(0.0, |bucket| {
-            let pt = percentile(percent, &bucket.bucket_counts, &bucket.bounds).unwrap_or(0.0);
-            self.max().min(pt)
-        })
+        self.latency().percentile(percent, self.max())
+    }
+
+    fn latency(&self) -> Bucket {
+        let mut total = Bucket::default();
+        for bucket in self.counts.values() {
+            total += bucket.clone();
+        }
+        total
     }
 
     pub(crate) fn p50(&self) -> f64 {
@@ -213,7 +337,216 @@ impl RequestDurationSecondsMetrics {
             .reduce(f64::max)
             .unwrap_or(0.0)
     }
+
+    // for StatusReport
+    // =================================================================================
+    /// Latency of each status class that has responses
+    pub(crate) fn classes(&self) -> Vec<ClassLatency> {
+        let mut groups: BTreeMap<&'static str, (f64, f64, f64)> = BTreeMap::new();
+        for point in &self.data_points {
+            // Every status has been parsed when the data points were found
+            let Ok(class) = Self::status(point).and_then(|status| Self::status_key(&status)) else {
+                continue;
+            };
+            let (sum, min, max) = groups.entry(class).or_insert((0.0, f64::MAX, 0.0));
+            *sum += point.sum;
+            *min = min.min(point.min.unwrap_or_default());
+            *max = max.max(point.max.unwrap_or_default());
+        }
+
+        groups
+            .into_iter()
+            .filter_map(|(class, (sum, min, max))| {
+                let bucket = self.counts.get(class)?;
+                (bucket.count() > 0).then(|| ClassLatency {
+                    class: class.to_string(),
+                    count: bucket.count(),
+                    mean: sum / bucket.count() as f64,
+                    p50: bucket.percentile(0.5, max),
+                    p95: bucket.percentile(0.95, max),
+                    p99: bucket.percentile(0.99, max),
+                    min,
+                    max,
+                })
+            })
+            .collect()
+    }
+
+    // for EndpointReport
+    // =================================================================================
+    /// Latency by the values of the `keys` attributes, with the bucket estimates of `percentiles`
+    pub(crate) fn groups(&self, keys: &[GroupBy], percentiles: &[f64]) -> Vec<GroupLatency> {
+        let mut groups: BTreeMap<Vec<String>, (Bucket, f64, f64, f64)> = BTreeMap::new();
+        for point in &self.data_points {
+            let bucket = Bucket::new(
+                point.count,
+                point.bounds.clone(),
+                point.bucket_counts.clone(),
+            );
+
+            let (total, sum, min, max) = groups
+                .entry(group_key(&point.attributes, keys))
+                .or_insert((Bucket::default(), 0.0, f64::MAX, 0.0));
+            *total += bucket;
+            *sum += point.sum;
+            *min = min.min(point.min.unwrap_or_default());
+            *max = max.max(point.max.unwrap_or_default());
+        }
+
+        groups
+            .into_iter()
+            .filter(|(_, (bucket, ..))| bucket.count() > 0)
+            .map(|(key, (bucket, sum, min, max))| GroupLatency {
+                key,
+                count: bucket.count(),
+                mean: sum / bucket.count() as f64,
+                percentiles: percentiles
+                    .iter()
+                    .map(|percent| (*percent, bucket.percentile(percent / 100.0, max)))
+                    .collect(),
+                min,
+                max,
+            })
+            .collect()
+    }
+
+    // for ProtocolReport
+    // =================================================================================
+    /// Number of responses over each protocol version, e.g. ("HTTP/2", 10)
+    pub(crate) fn versions(&self) -> Vec<(String, u64)> {
+        let mut versions: BTreeMap<String, u64> = BTreeMap::new();
+        for point in &self.data_points {
+            *versions
+                .entry(attribute(&point.attributes, "version"))
+                .or_default() += point.count;
+        }
+        versions
+            .into_iter()
+            .filter(|(version, count)| !version.is_empty() && *count > 0)
+            .collect()
+    }
+
+    // for StepReport
+    // =================================================================================
+    /// Latency of every step, empty when no scenario has more than one step
+    pub(crate) fn steps(&self) -> Vec<StepLatency> {
+        let mut groups: BTreeMap<(String, String), (Bucket, f64, f64)> = BTreeMap::new();
+        for point in &self.data_points {
+            let key = (
+                attribute(&point.attributes, "scenario"),
+                attribute(&point.attributes, "step"),
+            );
+            let bucket = Bucket::new(
+                point.count,
+                point.bounds.clone(),
+                point.bucket_counts.clone(),
+            );
+
+            let (total, sum, max) = groups.entry(key).or_default();
+            *total += bucket;
+            *sum += point.sum;
+            *max = max.max(point.max.unwrap_or_default());
+        }
+
+        let has_journey = groups
+            .keys()
+            .zip(groups.keys().skip(1))
+            .any(|((scenario, _), (next, _))| scenario == next);
+        if !has_journey {
+            return Vec::new();
+        }
+
+        groups
+            .into_iter()
+            .map(|((scenario, step), (bucket, sum, max))| StepLatency {
+                scenario,
+                step,
+                count: bucket.count(),
+                mean: sum / bucket.count().max(1) as f64,
+                p50: bucket.percentile(0.5, max),
+                p95: bucket.percentile(0.95, max),
+                p99: bucket.percentile(0.99, max),
+            })
+            .collect()
+    }
+}
+/// Cumulative totals at one export, two of them give the values over the time in between
+#[derive(Debug, Clone, Default)]
+pub(crate) struct Snapshot {
+    pub ts: f64,
+    responses: u64,
+    successes: u64,
+    errors: u64,
+    sum: f64,
+    checks: u64,
+    check_failures: u64,
+}
+
+impl Snapshot {
+    /// Nothing recorded yet at `ts`
+    pub(crate) fn empty(ts: f64) -> Self {
+        Self {
+            ts,
+            ..Default::default()
+        }
+    }
+
+    pub(crate) fn new(
+        duration_meter: &RequestDurationSecondsMetrics,
+        error_meter: Option<&ErrorMetrics>,
+        check_meter: Option<&CheckMetrics>,
+    ) -> Self {
+        let (checks, check_failures) = check_meter.map_or((0, 0), |meter| {
+            meter
+                .results()
+                .iter()
+                .fold((0, 0), |(total, failed), check| {
+                    (total + check.total, failed + check.failed)
+                })
+        });
+
+        Self {
+            ts: now_ts(),
+            responses: duration_meter.count(),
+            successes: duration_meter.status_2xx_count() + duration_meter.status_3xx_count(),
+            errors: error_meter.map_or(0, ErrorMetrics::count),
+            sum: duration_meter.sum(),
+            checks,
+            check_failures,
+        }
+    }
+
+    /// Value of `metric` between `earlier` and this snapshot, None without anything to compute it from
+    pub(crate) fn since(&self, earlier: &Snapshot, metric: ThresholdMetric) -> Option<f64> {
+        let responses = self.responses - earlier.responses;
+        let errors = self.errors - earlier.errors;
+        let checks = self.checks - earlier.checks;
+
+        let rate = |part: u64, total: u64| (total > 0).then(|| part as f64 / total as f64 * 100.0);
+
+        match metric {
+            // A stalled run has no responses and still a rate
+            ThresholdMetric::Rps => {
+                let elapsed = self.ts - earlier.ts;
+                (elapsed > 0.0).then(|| responses as f64 / elapsed)
+            }
+            ThresholdMetric::ErrorRate => rate(errors, responses + errors),
+            ThresholdMetric::SuccessRate => rate(self.successes - earlier.successes, responses),
+            ThresholdMetric::CheckRate => rate(
+                checks - (self.check_failures - earlier.check_failures),
+                checks,
+            ),
+            ThresholdMetric::Mean => {
+                (responses > 0).then(|| (self.sum - earlier.sum) / responses as f64)
+            }
+            // From the latencies recorded in between instead of the buckets
+            ThresholdMetric::Percentile(_) => None,
+            // Only known for the whole run
+            ThresholdMetric::Min | ThresholdMetric::Max => None,
+        }
+    }
 }
+
 pub struct ResponseSizeBytesMetrics {
     data_points: Vec<HistogramDataPoint<u64>>,
 }
@@ -221,11 +554,16 @@ pub struct ResponseSizeBytesMetrics {
 impl ResponseSizeBytesMetrics {
     const NAME: &'static str = "http_client_response_size";
 
-    pub(crate) fn find(meter: &ResourceMetrics) -> Option<Self> {
+    pub(crate) fn find(meter: &ResourceMetrics, include_warmup: bool) -> Option<Self> {
         let hist = find_metrcis::<Histogram<u64>>(meter, Self::NAME)?;
 
         Some(ResponseSizeBytesMetrics {
-            data_points: hist.data_points.clone(),
+            data_points: hist
+                .data_points
+                .iter()
+                .filter(|point| include_warmup || !is_warmup(&point.attributes))
+                .cloned()
+                .collect(),
         })
     }
 
@@ -238,8 +576,7 @@ impl ResponseSizeBytesMetrics {
     }
 
     pub(crate) fn mean(&self) -> u64 {
-        let cnt = self.count();
-        if cnt > 0 { self.sum() / cnt } else { 0 }
+        self.sum().checked_div(self.count()).unwrap_or_default()
     }
 }
 
@@ -251,29 +588,148 @@ pub struct ErrorMetrics {
 impl ErrorMetrics {
     const NAME: &'static str = "http_client_errors";
 
-    pub(crate) fn find(meter: &ResourceMetrics) -> Option<Self> {
+    pub(crate) fn find(meter: &ResourceMetrics, include_warmup: bool) -> Option<Self> {
         let hist = find_metrcis::<Sum<u64>>(meter, Self::NAME)?;
         Some(ErrorMetrics {
-            sum: hist.data_points.clone(),
+            sum: hist
+                .data_points
+                .iter()
+                .filter(|point| include_warmup || !is_warmup(&point.attributes))
+                .cloned()
+                .collect(),
         })
     }
 
-    pub(crate) fn error_messages(&self) -> Vec<String> {
-        self.sum
-            .iter()
-            .filter_map(|point| {
-                point
-                    .attributes
-                    .iter()
-                    .find(|keyval| keyval.key.as_str() == "phase")
-                    .map(|keyval| {
-                        format!("phase: {}, count: {}", keyval.value.as_str(), point.value)
-                    })
+    pub(crate) fn count(&self) -> u64 {
+        self.sum.iter().map(|point| point.value).sum()
+    }
+
+    /// Errors of every endpoint added up by category, the most frequent first
+    pub(crate) fn categories(&self) -> Vec<(String, u64)> {
+        let mut categories: BTreeMap<String, u64> = BTreeMap::new();
+        for point in &self.sum {
+            *categories
+                .entry(attribute(&point.attributes, "category"))
+                .or_default() += point.value;
+        }
+
+        let mut categories: Vec<_> = categories.into_iter().collect();
+        categories.sort_by(|(_, a), (_, b)| b.cmp(a));
+        categories
+    }
+
+    /// Errors by the values of the `keys` attributes
+    pub(crate) fn counts_by(&self, keys: &[GroupBy]) -> BTreeMap<Vec<String>, u64> {
+        let mut counts = BTreeMap::new();
+        for point in &self.sum {
+            *counts
+                .entry(group_key(&point.attributes, keys))
+                .or_default() += point.value;
+        }
+        counts
+    }
+}
+
+/// Result of one check over the run
+#[derive(Debug)]
+pub(crate) struct CheckResult {
+    pub name: String,
+    pub total: u64,
+    pub failed: u64,
+}
+
+pub struct CheckMetrics {
+    checks: Vec<SumDataPoint<u64>>,
+    failures: Vec<SumDataPoint<u64>>,
+}
+
+impl CheckMetrics {
+    const NAME: &'static str = "http_client_checks";
+    const FAILURES_NAME: &'static str = "http_client_check_failures";
+
+    pub(crate) fn find(meter: &ResourceMetrics, include_warmup: bool) -> Option<Self> {
+        let points = |sum: &Sum<u64>| {
+            sum.data_points
+                .iter()
+                .filter(|point| include_warmup || !is_warmup(&point.attributes))
+                .cloned()
+                .collect()
+        };
+
+        let checks = find_metrcis::<Sum<u64>>(meter, Self::NAME)?;
+        // Only exported once a check has failed
+        let failures = find_metrcis::<Sum<u64>>(meter, Self::FAILURES_NAME);
+        Some(CheckMetrics {
+            checks: points(checks),
+            failures: failures.map(points).unwrap_or_default(),
+        })
+    }
+
+    /// Totals by check name, the same check of several steps is added up
+    pub(crate) fn results(&self) -> Vec<CheckResult> {
+        let mut results: BTreeMap<String, (u64, u64)> = BTreeMap::new();
+        for point in &self.checks {
+            results
+                .entry(attribute(&point.attributes, "check"))
+                .or_default()
+                .0 += point.value;
+        }
+        for point in &self.failures {
+            results
+                .entry(attribute(&point.attributes, "check"))
+                .or_default()
+                .1 += point.value;
+        }
+
+        results
+            .into_iter()
+            .map(|(name, (total, failed))| CheckResult {
+                name,
+                total,
+                failed,
             })
             .collect()
     }
 }
 
+pub struct DroppedMetrics {
+    sum: Vec<SumDataPoint<u64>>,
+}
+
+impl DroppedMetrics {
+    const NAME: &'static str = "http_client_dropped_requests";
+
+    pub(crate) fn find(meter: &ResourceMetrics) -> Option<Self> {
+        let sum = find_metrcis::<Sum<u64>>(meter, Self::NAME)?;
+        Some(DroppedMetrics {
+            sum: sum.data_points.clone(),
+        })
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.sum.iter().map(|point| point.value).sum()
+    }
+}
+
+pub struct ConnectionMetrics {
+    sum: Vec<SumDataPoint<u64>>,
+}
+
+impl ConnectionMetrics {
+    const NAME: &'static str = "http_client_connections";
+
+    pub(crate) fn find(meter: &ResourceMetrics) -> Option<Self> {
+        let sum = find_metrcis::<Sum<u64>>(meter, Self::NAME)?;
+        Some(ConnectionMetrics {
+            sum: sum.data_points.clone(),
+        })
+    }
+
+    pub(crate) fn count(&self) -> u64 {
+        self.sum.iter().map(|point| point.value).sum()
+    }
+}
+
 pub struct StartTimeMetrics {
     data_points: Vec<GaugeDataPoint<f64>>,
 }
@@ -293,7 +749,11 @@ impl StartTimeMetrics {
         delta.round() as u64
     }
 
-    fn start_time(&self) -> f64 {
+    pub(crate) fn elapsed_secs(&self) -> f64 {
+        now_ts() - self.start_time()
+    }
+
+    pub(crate) fn start_time(&self) -> f64 {
         self.data_points
             .iter()
             .map(|point| point.value)
@@ -302,6 +762,35 @@ impl StartTimeMetrics {
     }
 }
 
+fn attribute(attributes: &[KeyValue], key: &str) -> String {
+    attributes
+        .iter()
+        .find(|keyval| keyval.key.as_str() == key)
+        .map(|keyval| keyval.value.as_str().to_string())
+        .unwrap_or_default()
+}
+
+// Values of the `keys` attributes, "-" for the ones a data point does not have
+fn group_key(attributes: &[KeyValue], keys: &[GroupBy]) -> Vec<String> {
+    keys.iter()
+        .map(|key| {
+            let value = attribute(attributes, key.as_ref());
+            if value.is_empty() {
+                "-".to_string()
+            } else {
+                value
+            }
+        })
+        .collect()
+}
+
+// Warm-up data points carry phase="warmup"
+fn is_warmup(attributes: &[KeyValue]) -> bool {
+    attributes
+        .iter()
+        .any(|keyval| keyval.key.as_str() == "phase" && keyval.value.as_str().as_ref() == "warmup")
+}
+
 fn find_metrcis<'a, T: 'static>(meter: &'a ResourceMetrics, name: &'a str) -> Option<&'a T> {
     let metric = meter
         .scope_metrics
@@ -311,3 +800,84 @@ fn find_metrcis<'a, T: 'static>(meter: &'a ResourceMetrics, name: &'a str) -> Op
 
     metric.data.as_any().downcast_ref::<T>()
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn snapshot(ts: f64, responses: u64, errors: u64) -> Snapshot {
+        Snapshot {
+            ts,
+            responses,
+            successes: responses,
+            errors,
+            sum: responses as f64 * 0.1,
+            ..Default::default()
+        }
+    }
+
+    #[test]
+    fn test_snapshot_since() {
+        let start = Snapshot::default();
+        let earlier = snapshot(10.0, 100, 0);
+        let latest = snapshot(20.0, 150, 50);
+
+        let since = |metric| latest.since(&earlier, metric);
+        assert_eq!(since(ThresholdMetric::Rps), Some(5.0));
+        assert_eq!(since(ThresholdMetric::ErrorRate), Some(50.0));
+        assert_eq!(since(ThresholdMetric::SuccessRate), Some(100.0));
+        assert!(since(ThresholdMetric::Mean).is_some_and(|mean| (mean - 0.1).abs() < 1e-9));
+        assert_eq!(since(ThresholdMetric::Percentile(95.0)), None);
+        assert_eq!(since(ThresholdMetric::CheckRate), None);
+        assert_eq!(since(ThresholdMetric::Max), None);
+
+        // Before the first response there is nothing in the window yet
+        assert_eq!(earlier.since(&start, ThresholdMetric::Rps), Some(10.0));
+        assert_eq!(start.since(&start, ThresholdMetric::ErrorRate), None);
+    }
+
+    #[test]
+    fn test_group_latency_merge() {
+        let group = |path: &str, count, mean, p99, min, max| GroupLatency {
+            key: vec![path.to_string()],
+            count,
+            mean,
+            percentiles: vec![(99.0, p99)],
+            min,
+            max,
+        };
+        let merged = group("/a", 30, 0.2, 0.5, 0.1, 0.6).merge(group("/b", 10, 0.6, 0.9, 0.2, 1.0));
+        assert_eq!(merged.key, ["/a"]);
+        assert_eq!(merged.count, 40);
+        assert!((merged.mean - 0.3).abs() < 1e-9);
+        assert_eq!(merged.percentiles, [(99.0, 0.9)]);
+        assert_eq!((merged.min, merged.max), (0.1, 1.0));
+    }
+
+    #[test]
+    fn test_error_categories() {
+        let point = |path: &str, category: &str, value: u64| SumDataPoint {
+            attributes: vec![
+                KeyValue::new("path", path.to_string()),
+                KeyValue::new("category", category.to_string()),
+            ],
+            value,
+            exemplars: Vec::new(),
+        };
+        let errors = ErrorMetrics {
+            sum: vec![
+                point("/a", "timeout", 1),
+                point("/a", "connection_refused", 2),
+                point("/b", "connection_refused", 3),
+            ],
+        };
+
+        assert_eq!(
+            errors.categories(),
+            vec![
+                ("connection_refused".to_string(), 5),
+                ("timeout".to_string(), 1)
+            ]
+        );
+    }
+}
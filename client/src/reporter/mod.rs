@@ -1,32 +1,62 @@
+use crate::profile::{LoadProfile, StageProgress};
+use clap::ValueEnum;
 use formatter::{format_duration, format_si};
 use std::time::Duration;
+use strum::AsRefStr;
 
+pub mod abort;
 mod components;
 pub mod exporter;
 pub mod formatter;
 mod metrics;
+pub mod timeseries;
+pub mod tui;
 
 pub const REPORT_INTERVAL: Duration = Duration::from_secs(1);
 
+/// Attribute of the requests the endpoint breakdown is grouped on
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum, AsRefStr)]
+#[strum(serialize_all = "snake_case")]
+pub enum GroupBy {
+    Scenario,
+    Step,
+    Method,
+    Path,
+    Status,
+    /// Protocol version of the response, e.g. HTTP/2
+    Version,
+}
+
 #[derive(Debug, Clone)]
 pub enum ExecMode {
     ByCount(u64),
     ByDuration(Duration),
+    ByProfile(LoadProfile),
 }
 
 impl ExecMode {
     pub(crate) fn state_progress(&self, elapsed: u64, count: u64) -> (u64, String, u64, String) {
         match self {
             ExecMode::ByCount(goal) => (count, format_si(&count), *goal, format_si(goal)),
-            ExecMode::ByDuration(goal) => {
-                let elapsed = elapsed.min(goal.as_secs());
-                (
-                    elapsed,
-                    format_duration(&elapsed),
-                    goal.as_secs(),
-                    format_duration(&goal.as_secs()),
-                )
-            }
+            ExecMode::ByDuration(goal) => Self::duration_progress(elapsed, goal),
+            ExecMode::ByProfile(profile) => Self::duration_progress(elapsed, &profile.duration()),
+        }
+    }
+
+    pub(crate) fn stage_progress(&self, elapsed: u64) -> Option<StageProgress> {
+        match self {
+            ExecMode::ByProfile(profile) => profile.progress(elapsed as f64),
+            _ => None,
         }
     }
+
+    fn duration_progress(elapsed: u64, goal: &Duration) -> (u64, String, u64, String) {
+        let elapsed = elapsed.min(goal.as_secs());
+        (
+            elapsed,
+            format_duration(&elapsed),
+            goal.as_secs(),
+            format_duration(&goal.as_secs()),
+        )
+    }
 }
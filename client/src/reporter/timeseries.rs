@@ -0,0 +1,169 @@
+use crate::histogram::LatencyRecorder;
+use crate::reporter::metrics::{
+    CheckMetrics, ErrorMetrics, RequestDurationSecondsMetrics, Snapshot, StartTimeMetrics,
+};
+use crate::threshold::ThresholdMetric;
+use crate::time::now_ts;
+use anyhow::{Context, Result};
+use async_trait::async_trait;
+use opentelemetry_sdk::error::{OTelSdkError, OTelSdkResult};
+use opentelemetry_sdk::metrics::{
+    Temporality, data::ResourceMetrics, exporter::PushMetricExporter,
+};
+use serde::Serialize;
+use std::path::Path;
+use std::sync::{Arc, Mutex};
+
+/// Values of one interval of the run
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct TimeseriesPoint {
+    /// Seconds since the start of the run at the end of the interval
+    pub elapsed: f64,
+    pub rps: f64,
+    pub error_rate: f64,
+    /// None when no response arrived in the interval
+    pub p50: Option<f64>,
+    pub p99: Option<f64>,
+}
+
+/// Points of every interval so far, shared between the exporters and the caller
+#[derive(Debug, Clone, Default)]
+pub struct Timeseries(Arc<Mutex<Vec<TimeseriesPoint>>>);
+
+impl Timeseries {
+    pub fn points(&self) -> Vec<TimeseriesPoint> {
+        self.0.lock().unwrap().clone()
+    }
+
+    fn push(&self, point: TimeseriesPoint) {
+        self.0.lock().unwrap().push(point);
+    }
+
+    /// Write the points as CSV with a header
+    pub fn write_csv(&self, path: &Path) -> Result<()> {
+        let mut writer = csv::Writer::from_path(path)
+            .with_context(|| format!("Failed to create {}", path.display()))?;
+        for point in self.points() {
+            writer.serialize(point)?;
+        }
+        writer.flush()?;
+        Ok(())
+    }
+}
+
+/// Adds a point to the timeseries on every export, from the difference with the previous one
+#[derive(Debug)]
+pub struct TimeseriesExporter {
+    timeseries: Timeseries,
+    latency: LatencyRecorder,
+    include_warmup: bool,
+    last: Mutex<Option<Snapshot>>,
+}
+
+impl TimeseriesExporter {
+    pub(crate) fn new(
+        timeseries: Timeseries,
+        latency: LatencyRecorder,
+        include_warmup: bool,
+    ) -> Self {
+        Self {
+            timeseries,
+            latency,
+            include_warmup,
+            last: Mutex::new(None),
+        }
+    }
+
+    fn collect(&self, meter: &ResourceMetrics) -> Result<()> {
+        let Some(start_meter) = StartTimeMetrics::find(meter) else {
+            return Ok(());
+        };
+        let latest =
+            match RequestDurationSecondsMetrics::try_find(meter, 0, 0.0, self.include_warmup)? {
+                Some(duration_meter) => {
+                    let error_meter = ErrorMetrics::find(meter, self.include_warmup);
+                    let check_meter = CheckMetrics::find(meter, self.include_warmup);
+                    Snapshot::new(&duration_meter, error_meter.as_ref(), check_meter.as_ref())
+                }
+                // Nothing has been recorded yet, e.g. waiting for the first responses
+                None => Snapshot::empty(now_ts()),
+            };
+
+        let mut last = self.last.lock().unwrap();
+        let previous = last
+            .take()
+            .unwrap_or_else(|| Snapshot::empty(start_meter.start_time()));
+        let latencies = self.latency.take_interval();
+
+        self.timeseries.push(TimeseriesPoint {
+            elapsed: latest.ts - start_meter.start_time(),
+            rps: latest.since(&previous, ThresholdMetric::Rps).unwrap_or(0.0),
+            error_rate: latest
+                .since(&previous, ThresholdMetric::ErrorRate)
+                .unwrap_or(0.0),
+            p50: latencies
+                .as_ref()
+                .map(|latencies| latencies.percentile(50.0)),
+            p99: latencies
+                .as_ref()
+                .map(|latencies| latencies.percentile(99.0)),
+        });
+        *last = Some(latest);
+
+        Ok(())
+    }
+}
+
+#[async_trait]
+impl PushMetricExporter for TimeseriesExporter {
+    async fn export(&self, metrics: &mut ResourceMetrics) -> OTelSdkResult {
+        self.collect(metrics)
+            .map_err(|e| OTelSdkError::InternalFailure(e.to_string()))
+    }
+
+    async fn force_flush(&self) -> OTelSdkResult {
+        Ok(())
+    }
+
+    fn temporality(&self) -> Temporality {
+        Temporality::Cumulative
+    }
+
+    fn shutdown(&self) -> OTelSdkResult {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_timeseries_csv() {
+        let timeseries = Timeseries::default();
+        timeseries.push(TimeseriesPoint {
+            elapsed: 1.0,
+            rps: 120.5,
+            error_rate: 0.0,
+            p50: Some(0.012),
+            p99: Some(0.25),
+        });
+        timeseries.push(TimeseriesPoint {
+            elapsed: 2.0,
+            rps: 0.0,
+            error_rate: 100.0,
+            p50: None,
+            p99: None,
+        });
+
+        let path = std::env::temp_dir().join(format!("hb-timeseries-{}.csv", std::process::id()));
+        timeseries.write_csv(&path).unwrap();
+        let content = std::fs::read_to_string(&path).unwrap();
+        std::fs::remove_file(&path).unwrap();
+
+        assert_eq!(
+            content,
+            "elapsed,rps,error_rate,p50,p99\n1.0,120.5,0.0,0.012,0.25\n2.0,0.0,100.0,,\n"
+        );
+    }
+}
@@ -0,0 +1,340 @@
+use crate::control::{Adjustable, LoadControl, Source};
+use crate::error_log::LoggedError;
+use crate::reporter::formatter::format_dynamic_precision;
+use anyhow::Result;
+use crossterm::event::{self, Event, KeyCode, KeyEventKind, KeyModifiers};
+use crossterm::terminal::{disable_raw_mode, enable_raw_mode};
+use std::sync::Arc;
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::thread::JoinHandle;
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+use tracing::*;
+
+/// A titled box of the interactive screen
+#[derive(Debug, Clone, Default, PartialEq)]
+pub(crate) struct Panel {
+    title: String,
+    lines: Vec<String>,
+}
+
+impl Panel {
+    pub(crate) fn new(title: &str, lines: Vec<String>) -> Self {
+        Self {
+            title: title.to_string(),
+            lines,
+        }
+    }
+
+    // Exactly `height` lines as wide as `width`, borders included
+    fn render(&self, width: usize, height: usize) -> Vec<String> {
+        let inner = width.saturating_sub(4);
+        let title = truncate(&format!(" {} ", self.title), width.saturating_sub(3));
+        let top = format!(
+            "┌─{}{}┐",
+            title,
+            "─".repeat(width.saturating_sub(3 + title.chars().count()))
+        );
+        let bottom = format!("└{}┘", "─".repeat(width.saturating_sub(2)));
+
+        let mut lines = vec![top];
+        let body = height.saturating_sub(2);
+        for n in 0..body {
+            let line = self.lines.get(n).map_or("", String::as_str);
+            lines.push(format!("│ {} │", pad(line, inner)));
+        }
+        lines.push(bottom);
+        lines
+    }
+}
+
+/// Everything the interactive screen shows at one point in time
+#[derive(Debug, Default)]
+pub(crate) struct Dashboard {
+    pub progress: String,
+    pub throughput: Panel,
+    pub latency: Panel,
+    pub errors: Panel,
+    pub endpoints: Panel,
+}
+
+impl Dashboard {
+    /// The screen as `height` lines of `width` characters at most, with the state of the run
+    /// from `control` and the latest of `errors` at the bottom
+    pub(crate) fn render(
+        &self,
+        control: Option<&LoadControl>,
+        errors: &[LoggedError],
+        (width, height): (usize, usize),
+    ) -> String {
+        let mut lines = vec![truncate(self.progress.trim_end(), width)];
+        lines.push(truncate(&status_line(control), width));
+
+        // Side by side when there is room for them, one above the other otherwise
+        let metrics = [&self.throughput, &self.latency, &self.errors];
+        let rows = |panel: &Panel| panel.lines.len() + 2;
+        if width >= 90 {
+            let height = metrics.iter().map(|panel| rows(panel)).max().unwrap_or(2);
+            let widths = [width / 3, width / 3, width - 2 * (width / 3)];
+            let boxes: Vec<_> = metrics
+                .iter()
+                .zip(widths)
+                .map(|(panel, width)| panel.render(width, height))
+                .collect();
+            for n in 0..height {
+                lines.push(boxes.iter().map(|lines| lines[n].as_str()).collect());
+            }
+        } else {
+            for panel in metrics {
+                lines.extend(panel.render(width, rows(panel)));
+            }
+        }
+        lines.extend(self.endpoints.render(width, rows(&self.endpoints)));
+
+        // The log takes the rest of the screen, at least a few lines
+        let left = height.saturating_sub(lines.len()).max(5);
+        let log = error_log_panel(errors, left.saturating_sub(2));
+        lines.extend(log.render(width, left));
+
+        lines.truncate(height.max(1));
+        lines.join("\r\n")
+    }
+}
+
+fn status_line(control: Option<&LoadControl>) -> String {
+    let Some(control) = control else {
+        return String::new();
+    };
+    let state = control.state();
+    let run = if state.paused { "PAUSED" } else { "running" };
+    let (target, keys) = match (control.adjustable(), state.rps) {
+        (Adjustable::Rps, Some(rps)) => (format!("target {} rps", rps), "  +/- rps"),
+        (Adjustable::Clients, _) => (format!("{} clients", state.clients), "  +/- clients"),
+        _ => (format!("{} clients", state.clients), ""),
+    };
+    format!("  {} │ {} │ p pause/resume{}  q stop", run, target, keys)
+}
+
+fn error_log_panel(errors: &[LoggedError], count: usize) -> Panel {
+    let lines = if errors.is_empty() {
+        vec!["No errors".to_string()]
+    } else {
+        errors
+            .iter()
+            .take(count)
+            .map(|error| {
+                let time = chrono::DateTime::from_timestamp_millis((error.ts * 1000.0) as i64)
+                    .map(|ts| {
+                        ts.with_timezone(&chrono::Local)
+                            .format("%H:%M:%S")
+                            .to_string()
+                    })
+                    .unwrap_or_default();
+                format!(
+                    "{} {} [{}] {}",
+                    time, error.endpoint, error.category, error.message
+                )
+            })
+            .collect()
+    };
+    Panel::new("Recent Errors", lines)
+}
+
+/// Rows as columns aligned to the widest value, the first one to the left
+pub(crate) fn columns(rows: &[Vec<String>]) -> Vec<String> {
+    // Values padded for the tables of the report would misalign the first column
+    let rows: Vec<Vec<&str>> = rows
+        .iter()
+        .map(|row| row.iter().map(|value| value.trim()).collect())
+        .collect();
+    let count = rows.iter().map(Vec::len).max().unwrap_or(0);
+    let widths: Vec<_> = (0..count)
+        .map(|n| {
+            rows.iter()
+                .filter_map(|row| row.get(n))
+                .map(|value| value.chars().count())
+                .max()
+                .unwrap_or(0)
+        })
+        .collect();
+
+    rows.iter()
+        .map(|row| {
+            row.iter()
+                .zip(&widths)
+                .enumerate()
+                .map(|(n, (value, width))| match n {
+                    0 => format!("{:<width$}", value, width = width),
+                    _ => format!("{:>width$}", value, width = width),
+                })
+                .collect::<Vec<_>>()
+                .join("  ")
+        })
+        .collect()
+}
+
+/// A name and value line of a panel, e.g. "P99    0.250s"
+pub(crate) fn entry(name: &str, value: String) -> String {
+    format!("{:<11}{}", name, value)
+}
+
+pub(crate) fn latency_entry(name: &str, value: f64) -> String {
+    entry(name, format_dynamic_precision(&value))
+}
+
+fn truncate(line: &str, width: usize) -> String {
+    line.chars().take(width).collect()
+}
+
+fn pad(line: &str, width: usize) -> String {
+    format!("{:<width$}", truncate(line, width), width = width)
+}
+
+/// Reads the keys of the interactive screen while alive, with the terminal in raw mode meanwhile.
+/// `p` pauses and resumes the run, `+` and `-` change its load, and `q` stops it gracefully.
+#[derive(Debug)]
+pub struct Keyboard {
+    stop: Arc<AtomicBool>,
+    handle: Option<JoinHandle<()>>,
+}
+
+impl Keyboard {
+    const POLL_INTERVAL: Duration = Duration::from_millis(100);
+
+    /// `cxl` is cancelled to stop the run, which still prints the final report
+    pub fn start(control: LoadControl, cxl: CancellationToken) -> Result<Self> {
+        enable_raw_mode()?;
+        let stop = Arc::new(AtomicBool::new(false));
+
+        let handle = {
+            let stop = Arc::clone(&stop);
+            std::thread::spawn(move || {
+                while !stop.load(Relaxed) {
+                    match event::poll(Self::POLL_INTERVAL) {
+                        Ok(true) => {}
+                        Ok(false) => continue,
+                        Err(err) => {
+                            debug!("Failed to read the keyboard: {}", err);
+                            break;
+                        }
+                    }
+                    if let Ok(Event::Key(key)) = event::read()
+                        && key.kind == KeyEventKind::Press
+                    {
+                        handle_key(key.code, key.modifiers, &control, &cxl);
+                    }
+                }
+            })
+        };
+
+        Ok(Self {
+            stop,
+            handle: Some(handle),
+        })
+    }
+}
+
+fn handle_key(
+    code: KeyCode,
+    modifiers: KeyModifiers,
+    control: &LoadControl,
+    cxl: &CancellationToken,
+) {
+    let result = match code {
+        KeyCode::Char('p') => {
+            control.toggle_pause(Source::Keyboard);
+            Ok(())
+        }
+        KeyCode::Char('+') | KeyCode::Char('=') => control.increase(Source::Keyboard),
+        KeyCode::Char('-') => control.decrease(Source::Keyboard),
+        // Ctrl+C no longer sends SIGINT in raw mode
+        KeyCode::Char('c') if modifiers.contains(KeyModifiers::CONTROL) => {
+            cxl.cancel();
+            Ok(())
+        }
+        KeyCode::Char('q') | KeyCode::Esc => {
+            cxl.cancel();
+            Ok(())
+        }
+        _ => Ok(()),
+    };
+    if let Err(err) = result {
+        debug!("Ignored key: {}", err);
+    }
+}
+
+impl Drop for Keyboard {
+    fn drop(&mut self) {
+        self.stop.store(true, Relaxed);
+        if let Some(handle) = self.handle.take() {
+            let _ = handle.join();
+        }
+        let _ = disable_raw_mode();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_panel_render() {
+        let panel = Panel::new("Latency", vec!["P99 0.250s".to_string(), "x".repeat(30)]);
+        let lines = panel.render(20, 5);
+        assert_eq!(
+            lines,
+            [
+                "┌─ Latency ────────┐",
+                "│ P99 0.250s       │",
+                "│ xxxxxxxxxxxxxxxx │",
+                "│                  │",
+                "└──────────────────┘",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_columns() {
+        let rows = [
+            vec!["Endpoint".to_string(), "Count".to_string()],
+            vec!["        GET /".to_string(), "1200".to_string()],
+        ];
+        assert_eq!(columns(&rows), ["Endpoint  Count", "GET /      1200"]);
+    }
+
+    #[test]
+    fn test_dashboard_render() {
+        let control = LoadControl::new(Some(100), 10, Adjustable::Rps);
+        control.toggle_pause(Source::Keyboard);
+        let dashboard = Dashboard {
+            progress: "  1s/10s [>] 10.0%\n".to_string(),
+            throughput: Panel::new("Throughput", vec![entry("RPS", "99.00".to_string())]),
+            latency: Panel::new("Latency", vec![latency_entry("P99", 0.25)]),
+            errors: Panel::new("Errors", Vec::new()),
+            endpoints: Panel::new("Endpoints", Vec::new()),
+        };
+        let errors = [LoggedError {
+            ts: 0.0,
+            endpoint: "GET /".to_string(),
+            category: "connection_refused".to_string(),
+            message: "connection refused".to_string(),
+        }];
+
+        let screen = dashboard.render(Some(&control), &errors, (120, 20));
+        let lines: Vec<_> = screen.split("\r\n").collect();
+        // The log takes the rest of the screen
+        assert_eq!(lines.len(), 20);
+        assert_eq!(lines[0], "  1s/10s [>] 10.0%");
+        assert!(lines[1].contains("PAUSED │ target 100 rps"));
+        assert!(lines[2].starts_with("┌─ Throughput "));
+        assert!(lines[3].contains("RPS        99.00"));
+        assert!(lines[3].contains("P99        0.250s"));
+        assert!(lines[8].contains("GET / [connection_refused] connection refused"));
+        assert!(lines.iter().all(|line| line.chars().count() <= 120));
+
+        // Stacked on a narrow screen
+        let screen = dashboard.render(None, &[], (60, 40));
+        assert!(screen.split("\r\n").all(|line| line.chars().count() <= 60));
+        assert!(screen.contains("No errors"));
+    }
+}
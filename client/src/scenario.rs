@@ -0,0 +1,716 @@
+use crate::check::{Check, CheckSpec};
+use crate::extract::{Extraction, ExtractorSpec};
+use crate::http::{Method, Request};
+use crate::template::{RenderContext, Template};
+use crate::time::deserialize_duration;
+use anyhow::{Context, Result, bail};
+use clap::ValueEnum;
+use serde::{Deserialize, Deserializer};
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+/// Name of the scenario used when a single request is given on the command line
+pub const DEFAULT_SCENARIO: &str = "default";
+
+/// A named journey picked in proportion to its weight, its steps run in order
+#[derive(Debug, Clone)]
+pub struct Scenario {
+    pub name: String,
+    pub weight: u32,
+    pub steps: Vec<Step>,
+}
+
+impl Scenario {
+    /// Add `checks` to the ones of every step
+    pub fn with_checks(mut self, checks: &[Check]) -> Self {
+        for step in &mut self.steps {
+            step.checks.extend_from_slice(checks);
+        }
+        self
+    }
+
+    /// The request given on the command line, `url` may hold placeholders.
+    /// `inputs` are the variables set before the first step, e.g. the columns of a data file.
+    pub fn try_single(url: &str, request: Request, inputs: &[String]) -> Result<Self> {
+        let base_url = request.url.clone();
+        let step = Step::try_new(
+            DEFAULT_SCENARIO.to_string(),
+            &base_url,
+            url,
+            request,
+            Vec::new(),
+        )?;
+
+        if let Some(var) = step
+            .vars()
+            .into_iter()
+            .find(|var| !inputs.iter().any(|input| input == var))
+        {
+            bail!("Variable '{}' is not defined", var);
+        }
+
+        Ok(Self {
+            name: DEFAULT_SCENARIO.to_string(),
+            weight: 1,
+            steps: vec![step],
+        })
+    }
+}
+
+/// A request of a journey, which may use values captured by the previous steps
+#[derive(Debug, Clone)]
+pub struct Step {
+    pub name: String,
+    /// The request as sent when it has no placeholders
+    pub request: Request,
+    /// Path used as the metric label, placeholders are kept to bound the cardinality
+    pub path: String,
+    template: Option<RequestTemplate>,
+    pub extract: Vec<Extraction>,
+    pub checks: Vec<Check>,
+}
+
+#[derive(Debug, Clone)]
+struct RequestTemplate {
+    base_url: url::Url,
+    url: Template,
+    headers: Vec<(String, Template)>,
+    body: Option<Template>,
+}
+
+impl Step {
+    /// A request sent as is, its placeholders are not rendered
+    pub(crate) fn fixed(name: String, request: Request) -> Self {
+        Self {
+            name,
+            path: request.url.path().to_string(),
+            request,
+            template: None,
+            extract: Vec::new(),
+            checks: Vec::new(),
+        }
+    }
+
+    fn try_new(
+        name: String,
+        base_url: &url::Url,
+        url: &str,
+        request: Request,
+        extract: Vec<Extraction>,
+    ) -> Result<Self> {
+        let url_template = Template::parse(url)?;
+        let headers = request
+            .headers
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), Template::parse(value)?)))
+            .collect::<Result<Vec<_>>>()?;
+        // Binary bodies are sent as is
+        let body = std::str::from_utf8(&request.body)
+            .ok()
+            .map(Template::parse)
+            .transpose()?;
+
+        let is_literal = url_template.is_literal()
+            && headers.iter().all(|(_, value)| value.is_literal())
+            && body.as_ref().is_none_or(Template::is_literal);
+
+        let path = if url_template.is_literal() {
+            request.url.path().to_string()
+        } else {
+            template_path(url).to_string()
+        };
+
+        let template = (!is_literal).then(|| RequestTemplate {
+            base_url: base_url.clone(),
+            url: url_template,
+            headers,
+            body,
+        });
+
+        Ok(Self {
+            name,
+            request,
+            path,
+            template,
+            extract,
+            checks: Vec::new(),
+        })
+    }
+
+    /// Variables this step needs from the previous steps
+    pub fn vars(&self) -> Vec<&str> {
+        let Some(template) = &self.template else {
+            return Vec::new();
+        };
+
+        template
+            .headers
+            .iter()
+            .map(|(_, value)| value)
+            .chain([&template.url])
+            .chain(&template.body)
+            .flat_map(Template::vars)
+            .collect()
+    }
+
+    /// The request with its placeholders substituted, None when it has none
+    pub fn render(&self, ctx: &RenderContext) -> Result<Option<Request>> {
+        let Some(template) = &self.template else {
+            return Ok(None);
+        };
+
+        let url = template.base_url.join(&template.url.render(ctx)?)?;
+        let headers = template
+            .headers
+            .iter()
+            .map(|(key, value)| Ok((key.clone(), value.render(ctx)?)))
+            .collect::<Result<HashMap<_, _>>>()?;
+        let body = match &template.body {
+            Some(body) => body.render(ctx)?.into_bytes(),
+            None => self.request.body.clone(),
+        };
+
+        Ok(Some(Request {
+            url,
+            headers,
+            body,
+            ..self.request.clone()
+        }))
+    }
+}
+
+// Path of a URL template without the query, the URL may be relative
+fn template_path(url: &str) -> &str {
+    let path = match url.split_once("://") {
+        Some((_, rest)) => match rest.find(['/', '?']) {
+            Some(start) if rest[start..].starts_with('/') => &rest[start..],
+            _ => "/",
+        },
+        None => url,
+    };
+    path.split('?').next().unwrap_or_default()
+}
+
+/// Scenario file in YAML or TOML, chosen by the file extension
+///
+/// ```yaml
+/// scenarios:
+///   - name: list-items
+///     weight: 70
+///     url: /items
+///   - name: create-order
+///     weight: 10
+///     method: post
+///     url: /orders
+///     headers:
+///       content-type: application/json
+///     body: '{"sku": "A-1"}'
+///     timeout: 1s
+///   - name: checkout
+///     steps:
+///       - name: login
+///         method: post
+///         url: /login
+///         extract:
+///           token:
+///             json: $.token
+///       - name: order
+///         url: /orders
+///         headers:
+///           authorization: Bearer {{token}}
+/// ```
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ScenarioFile {
+    scenarios: Vec<ScenarioSpec>,
+    // Checked on the responses of every scenario
+    #[serde(default)]
+    checks: Vec<CheckSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ScenarioSpec {
+    name: String,
+    #[serde(default = "default_weight")]
+    weight: u32,
+    url: Option<String>,
+    #[serde(flatten)]
+    request: RequestSpec,
+    #[serde(default)]
+    steps: Vec<StepSpec>,
+    #[serde(default)]
+    checks: Vec<CheckSpec>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct StepSpec {
+    name: String,
+    url: String,
+    #[serde(flatten)]
+    request: RequestSpec,
+    #[serde(default)]
+    extract: BTreeMap<String, ExtractorSpec>,
+    #[serde(default)]
+    checks: Vec<CheckSpec>,
+}
+
+// Fields shared by scenarios and steps, a step falls back to its scenario
+#[derive(Debug, Default, Deserialize)]
+struct RequestSpec {
+    #[serde(default, deserialize_with = "deserialize_method")]
+    method: Option<Method>,
+    #[serde(default)]
+    headers: HashMap<String, String>,
+    body: Option<String>,
+    body_file: Option<PathBuf>,
+    #[serde(default, deserialize_with = "deserialize_duration")]
+    timeout: Option<Duration>,
+}
+
+fn default_weight() -> u32 {
+    1
+}
+
+fn deserialize_method<'de, D>(deserializer: D) -> Result<Option<Method>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let method = String::deserialize(deserializer)?;
+    Method::from_str(&method, true)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
+
+impl ScenarioFile {
+    pub fn from_path(path: &Path) -> Result<Self> {
+        let content = std::fs::read_to_string(path)
+            .with_context(|| format!("Failed to read scenario file {}", path.display()))?;
+
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => Self::from_yaml(&content),
+            Some("toml") => Self::from_toml(&content),
+            _ => bail!("Scenario file must have a .yaml, .yml or .toml extension"),
+        }
+    }
+
+    pub fn from_yaml(content: &str) -> Result<Self> {
+        Ok(serde_norway::from_str(content)?)
+    }
+
+    pub fn from_toml(content: &str) -> Result<Self> {
+        Ok(toml::from_str(content)?)
+    }
+
+    /// Build the scenarios on top of `base`, which holds the command-line options.
+    /// URLs are resolved against the base URL and unset fields fall back to the base request.
+    /// `inputs` are the variables set before the first step, e.g. the columns of a data file.
+    pub fn scenarios(self, base: &Request, inputs: &[String]) -> Result<Vec<Scenario>> {
+        if self.scenarios.is_empty() {
+            bail!("At least one scenario must be specified");
+        }
+
+        if self.scenarios.iter().all(|spec| spec.weight == 0) {
+            bail!("At least one scenario must have a weight greater than 0");
+        }
+
+        let checks = try_checks(self.checks)?;
+
+        let mut names = HashSet::new();
+        self.scenarios
+            .into_iter()
+            .map(|spec| {
+                if !names.insert(spec.name.clone()) {
+                    bail!("Scenario name '{}' is duplicated", spec.name);
+                }
+                Ok(spec.into_scenario(base, inputs)?.with_checks(&checks))
+            })
+            .collect()
+    }
+}
+
+impl ScenarioSpec {
+    fn into_scenario(self, base: &Request, inputs: &[String]) -> Result<Scenario> {
+        let name = self.name;
+        let scenario_base = self
+            .request
+            .apply(base)
+            .with_context(|| format!("Invalid scenario '{}'", name))?;
+        let checks =
+            try_checks(self.checks).with_context(|| format!("Invalid scenario '{}'", name))?;
+
+        let step_specs = match (self.url, self.steps.is_empty()) {
+            (Some(url), true) => vec![StepSpec {
+                name: name.clone(),
+                url,
+                request: RequestSpec::default(),
+                extract: BTreeMap::new(),
+                checks: Vec::new(),
+            }],
+            (None, false) => self.steps,
+            _ => bail!("Scenario '{}' must have either url or steps", name),
+        };
+
+        let mut step_names = HashSet::new();
+        let mut defined: HashSet<String> = inputs.iter().cloned().collect();
+        let mut steps = Vec::with_capacity(step_specs.len());
+        for spec in step_specs {
+            if !step_names.insert(spec.name.clone()) {
+                bail!("Step name '{}' is duplicated in '{}'", spec.name, name);
+            }
+
+            let step = spec
+                .into_step(base, &scenario_base)
+                .with_context(|| format!("Invalid scenario '{}'", name))?;
+
+            // Values can only come from the inputs or the steps before
+            if let Some(var) = step.vars().into_iter().find(|var| !defined.contains(*var)) {
+                bail!(
+                    "Variable '{}' of step '{}' is neither an input nor extracted by a previous step",
+                    var,
+                    step.name
+                );
+            }
+            defined.extend(step.extract.iter().map(|extraction| extraction.var.clone()));
+
+            steps.push(step);
+        }
+
+        let scenario = Scenario {
+            name,
+            weight: self.weight,
+            steps,
+        };
+        Ok(scenario.with_checks(&checks))
+    }
+}
+
+impl StepSpec {
+    fn into_step(self, base: &Request, scenario_base: &Request) -> Result<Step> {
+        let mut request = self.request.apply(scenario_base)?;
+        request.url = base
+            .url
+            .join(&self.url)
+            .with_context(|| format!("Invalid URL in step '{}'", self.name))?;
+
+        let extract = self
+            .extract
+            .into_iter()
+            .map(|(var, spec)| Extraction::try_new(var, spec))
+            .collect::<Result<Vec<_>>>()?;
+
+        let mut step = Step::try_new(self.name, &base.url, &self.url, request, extract)?;
+        step.checks = try_checks(self.checks)?;
+        Ok(step)
+    }
+}
+
+fn try_checks(specs: Vec<CheckSpec>) -> Result<Vec<Check>> {
+    specs.into_iter().map(Check::try_new).collect()
+}
+
+impl RequestSpec {
+    fn apply(self, base: &Request) -> Result<Request> {
+        let body = match (self.body, self.body_file) {
+            (Some(_), Some(_)) => bail!("Both body and body_file are specified"),
+            (Some(body), None) => body.into_bytes(),
+            (None, Some(body_file)) => std::fs::read(&body_file)
+                .with_context(|| format!("Failed to read body file {}", body_file.display()))?,
+            (None, None) => base.body.clone(),
+        };
+
+        let mut headers = base.headers.clone();
+        headers.extend(self.headers);
+
+        Ok(Request {
+            method: self.method.unwrap_or_else(|| base.method.clone()),
+            headers,
+            timeout: self.timeout.unwrap_or(base.timeout),
+            body,
+            ..base.clone()
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::http::{HttpVersion, Negotiation};
+    use crate::template::Vars;
+
+    fn base() -> Request {
+        Request {
+            http_version: HttpVersion::Http11,
+            negotiation: Negotiation::Auto,
+            url: url::Url::parse("http://localhost:3000/api/").unwrap(),
+            method: Method::Get,
+            headers: HashMap::from([("x-base".to_string(), "1".to_string())]),
+            timeout: Duration::from_secs(3),
+            body: Vec::new(),
+            basic_auth: None,
+            insecure: false,
+            disable_keepalive: false,
+            root_cert: None,
+            resolve: None,
+        }
+    }
+
+    fn render(step: &Step, vars: &Vars) -> Option<Request> {
+        let ctx = RenderContext {
+            vars,
+            client_id: 1,
+            seq: 7,
+        };
+        step.render(&ctx).unwrap()
+    }
+
+    #[test]
+    fn test_scenarios_from_yaml() {
+        let content = r#"
+scenarios:
+  - name: list-items
+    weight: 70
+    url: items
+  - name: create-order
+    weight: 10
+    method: post
+    url: /orders
+    headers:
+      content-type: application/json
+    body: '{"sku": "A-1"}'
+    timeout: 500ms
+  - name: external
+    url: http://example.com/health
+"#;
+        let scenarios = ScenarioFile::from_yaml(content)
+            .unwrap()
+            .scenarios(&base(), &[])
+            .unwrap();
+
+        assert_eq!(scenarios.len(), 3);
+
+        let list = &scenarios[0];
+        assert_eq!(list.name, "list-items");
+        assert_eq!(list.weight, 70);
+        assert_eq!(list.steps.len(), 1);
+        assert_eq!(list.steps[0].name, "list-items");
+        assert_eq!(
+            list.steps[0].request.url.as_str(),
+            "http://localhost:3000/api/items"
+        );
+        assert_eq!(list.steps[0].request.method, Method::Get);
+
+        let order = &scenarios[1].steps[0].request;
+        assert_eq!(order.url.as_str(), "http://localhost:3000/orders");
+        assert_eq!(order.method, Method::Post);
+        assert_eq!(order.body, br#"{"sku": "A-1"}"#);
+        assert_eq!(order.timeout, Duration::from_millis(500));
+        assert_eq!(order.headers["x-base"], "1");
+        assert_eq!(order.headers["content-type"], "application/json");
+
+        let external = &scenarios[2];
+        assert_eq!(external.weight, 1);
+        assert_eq!(
+            external.steps[0].request.url.as_str(),
+            "http://example.com/health"
+        );
+    }
+
+    #[test]
+    fn test_scenarios_from_toml() {
+        let content = r#"
+[[scenarios]]
+name = "get-item"
+weight = 20
+url = "/items/1"
+
+[[scenarios]]
+name = "delete-item"
+method = "DELETE"
+url = "/items/2"
+"#;
+        let scenarios = ScenarioFile::from_toml(content)
+            .unwrap()
+            .scenarios(&base(), &[])
+            .unwrap();
+
+        assert_eq!(scenarios[0].weight, 20);
+        let delete = &scenarios[1].steps[0].request;
+        assert_eq!(delete.method, Method::Delete);
+        assert_eq!(delete.url.as_str(), "http://localhost:3000/items/2");
+    }
+
+    #[test]
+    fn test_scenarios_with_steps() {
+        let content = r#"
+scenarios:
+  - name: checkout
+    headers:
+      accept: application/json
+    steps:
+      - name: login
+        method: post
+        url: /login
+        extract:
+          token:
+            json: $.token
+          session:
+            header: x-session
+      - name: order
+        url: /users/{{session}}/orders?page=1
+        headers:
+          authorization: Bearer {{token}}
+        body: '{"session": "{{session}}"}'
+      - name: logout
+        url: /logout
+"#;
+        let scenarios = ScenarioFile::from_yaml(content)
+            .unwrap()
+            .scenarios(&base(), &[])
+            .unwrap();
+        let steps = &scenarios[0].steps;
+
+        assert_eq!(steps.len(), 3);
+        assert_eq!(steps[0].request.method, Method::Post);
+        assert_eq!(steps[0].extract.len(), 2);
+        assert_eq!(steps[0].request.headers["accept"], "application/json");
+        assert!(render(&steps[0], &Vars::new()).is_none());
+
+        let order = &steps[1];
+        assert_eq!(order.path, "/users/{{session}}/orders");
+        let vars = Vars::from([
+            ("token".to_string(), "abc".to_string()),
+            ("session".to_string(), "s-1".to_string()),
+        ]);
+        let request = render(order, &vars).unwrap();
+        assert_eq!(
+            request.url.as_str(),
+            "http://localhost:3000/users/s-1/orders?page=1"
+        );
+        assert_eq!(request.headers["authorization"], "Bearer abc");
+        assert_eq!(request.headers["accept"], "application/json");
+        assert_eq!(request.body, br#"{"session": "s-1"}"#);
+        assert_eq!(request.method, Method::Get);
+    }
+
+    #[test]
+    fn test_scenarios_with_checks() {
+        let content = r#"
+checks:
+  - status: [200]
+scenarios:
+  - name: list
+    url: /items
+  - name: checkout
+    checks:
+      - {name: no-error, json: $.error, exists: false}
+    steps:
+      - name: login
+        url: /login
+        checks:
+          - header: x-session
+      - name: order
+        url: /orders
+"#;
+        let scenarios = ScenarioFile::from_yaml(content)
+            .unwrap()
+            .scenarios(&base(), &[])
+            .unwrap();
+        let names = |step: &Step| {
+            let mut names: Vec<_> = step.checks.iter().map(|check| check.name.clone()).collect();
+            names.sort();
+            names
+        };
+
+        assert_eq!(names(&scenarios[0].steps[0]), ["status"]);
+        assert_eq!(
+            names(&scenarios[1].steps[0]),
+            ["header x-session", "no-error", "status"]
+        );
+        assert_eq!(names(&scenarios[1].steps[1]), ["no-error", "status"]);
+
+        let invalid = "scenarios: [{name: a, url: /, checks: [{status: [200], header: x}]}]";
+        let result = ScenarioFile::from_yaml(invalid).and_then(|file| file.scenarios(&base(), &[]));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_single_scenario() {
+        let url = "http://localhost:3000/items/{{seq}}?client={{client_id}}";
+        let request = Request {
+            url: url::Url::parse(url).unwrap(),
+            headers: HashMap::from([("x-request-id".to_string(), "{{seq}}".to_string())]),
+            body: br#"{"client": {{client_id}}}"#.to_vec(),
+            ..base()
+        };
+        let scenario = Scenario::try_single(url, request, &[]).unwrap();
+        let step = &scenario.steps[0];
+        assert_eq!(scenario.name, DEFAULT_SCENARIO);
+        assert_eq!(step.path, "/items/{{seq}}");
+        assert!(step.vars().is_empty());
+
+        let request = render(step, &Vars::new()).unwrap();
+        assert_eq!(
+            request.url.as_str(),
+            "http://localhost:3000/items/7?client=1"
+        );
+        assert_eq!(request.headers["x-request-id"], "7");
+        assert_eq!(request.body, br#"{"client": 1}"#);
+
+        let scenario = Scenario::try_single("http://localhost:3000/api/", base(), &[]).unwrap();
+        assert_eq!(scenario.steps[0].path, "/api/");
+        assert!(render(&scenario.steps[0], &Vars::new()).is_none());
+
+        assert!(
+            Scenario::try_single("http://localhost:3000/{{random_int 1}}", base(), &[]).is_err()
+        );
+    }
+
+    #[test]
+    fn test_scenarios_with_inputs() {
+        let inputs = ["user_id".to_string()];
+        let url = "http://localhost:3000/users/{{user_id}}";
+        assert!(Scenario::try_single(url, base(), &inputs).is_ok());
+        assert!(Scenario::try_single(url, base(), &[]).is_err());
+
+        let content = "scenarios: [{name: a, steps: [{name: b, url: '/users/{{user_id}}'}]}]";
+        let file = || ScenarioFile::from_yaml(content).unwrap();
+        let scenarios = file().scenarios(&base(), &inputs).unwrap();
+        let vars = Vars::from([("user_id".to_string(), "42".to_string())]);
+        let request = render(&scenarios[0].steps[0], &vars).unwrap();
+        assert_eq!(request.url.as_str(), "http://localhost:3000/users/42");
+        assert!(file().scenarios(&base(), &[]).is_err());
+    }
+
+    #[test]
+    fn test_template_path() {
+        assert_eq!(template_path("/users/{{id}}?page=1"), "/users/{{id}}");
+        assert_eq!(template_path("http://localhost/a/{{id}}"), "/a/{{id}}");
+        assert_eq!(template_path("http://localhost?q=/{{seq}}"), "/");
+    }
+
+    #[test]
+    fn test_scenarios_invalid() {
+        let contents = [
+            "scenarios: []",
+            "scenarios: [{name: a, url: /, weight: 0}]",
+            "scenarios: [{name: a, url: /}, {name: a, url: /b}]",
+            "scenarios: [{name: a, url: /, method: fetch}]",
+            "scenarios: [{name: a, url: /, timeout: 1x}]",
+            "scenarios: [{name: a, url: /, unknown: 1}]",
+            "scenarios: [{name: a}]",
+            "scenarios: [{name: a, url: /, steps: [{name: b, url: /}]}]",
+            "scenarios: [{name: a, steps: [{name: b, url: /}, {name: b, url: /}]}]",
+            "scenarios: [{name: a, steps: [{name: b, url: '/{{token}}'}]}]",
+            "scenarios: [{name: a, steps: [{name: b, url: /, extract: {t: {json: token}}}]}]",
+        ];
+
+        contents.iter().for_each(|content| {
+            let result =
+                ScenarioFile::from_yaml(content).and_then(|file| file.scenarios(&base(), &[]));
+            assert!(result.is_err(), "{}", content);
+        });
+    }
+}
@@ -0,0 +1,269 @@
+use anyhow::{Context, Result, bail};
+use rand::Rng;
+use std::collections::HashMap;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Values captured from earlier responses of a journey, by variable name
+pub type Vars = HashMap<String, String>;
+
+/// Values a template is rendered with, besides the built-in ones generated on the fly
+#[derive(Debug, Clone, Copy)]
+pub struct RenderContext<'a> {
+    pub vars: &'a Vars,
+    /// Index of the client sending the request, starting from 0
+    pub client_id: usize,
+    /// Sequence number of the request within the run, starting from 0
+    pub seq: u64,
+}
+
+/// Text with `{{name}}` placeholders, rendered for every request
+///
+/// Besides the variables extracted by previous steps, the following placeholders are built in:
+///
+/// - `{{uuid}}`: a random UUID v4
+/// - `{{random_int 1 1000}}`: a random integer between the bounds, both inclusive
+/// - `{{seq}}`: the sequence number of the request
+/// - `{{timestamp}}`: the Unix time in milliseconds
+/// - `{{client_id}}`: the index of the client sending the request
+/// - `{{env NAME}}`: the value of an environment variable, read once when parsed
+///
+/// A literal `{{` is written `\{{`.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Template {
+    parts: Vec<Part>,
+}
+
+#[derive(Debug, Clone, PartialEq)]
+enum Part {
+    Literal(String),
+    Var(String),
+    Uuid,
+    RandomInt(i64, i64),
+    Seq,
+    Timestamp,
+    ClientId,
+}
+
+impl Part {
+    fn parse(placeholder: &str) -> Result<Self> {
+        let mut words = placeholder.split_whitespace();
+        let part = match (words.next(), words.next(), words.next(), words.next()) {
+            (Some("uuid"), None, None, None) => Part::Uuid,
+            (Some("seq"), None, None, None) => Part::Seq,
+            (Some("timestamp"), None, None, None) => Part::Timestamp,
+            (Some("client_id"), None, None, None) => Part::ClientId,
+            (Some("random_int"), Some(min), Some(max), None) => {
+                let min = min
+                    .parse()
+                    .with_context(|| format!("Invalid lower bound in '{{{{{}}}}}'", placeholder))?;
+                let max = max
+                    .parse()
+                    .with_context(|| format!("Invalid upper bound in '{{{{{}}}}}'", placeholder))?;
+                if min > max {
+                    bail!(
+                        "Lower bound is greater than upper bound in '{{{{{}}}}}'",
+                        placeholder
+                    );
+                }
+                Part::RandomInt(min, max)
+            }
+            (Some("env"), Some(name), None, None) => Part::Literal(
+                std::env::var(name)
+                    .with_context(|| format!("Environment variable '{}' is not set", name))?,
+            ),
+            (Some(name), None, None, None) => Part::Var(name.to_string()),
+            _ => bail!("Invalid placeholder '{{{{{}}}}}'", placeholder),
+        };
+        Ok(part)
+    }
+}
+
+impl Template {
+    pub fn parse(input: &str) -> Result<Self> {
+        let mut parts = Vec::new();
+        let mut literal = String::new();
+        let mut rest = input;
+
+        while let Some(start) = rest.find("{{") {
+            // An escaped `{{` is kept as is, along with the text before it
+            if let Some(before) = rest[..start].strip_suffix('\\') {
+                literal.push_str(before);
+                literal.push_str("{{");
+                rest = &rest[start + 2..];
+                continue;
+            }
+
+            let Some(end) = rest[start..].find("}}") else {
+                bail!("Unclosed placeholder in '{}'", input);
+            };
+
+            literal.push_str(&rest[..start]);
+            if !literal.is_empty() {
+                parts.push(Part::Literal(std::mem::take(&mut literal)));
+            }
+
+            let placeholder = rest[start + 2..start + end].trim();
+            if placeholder.is_empty() {
+                bail!("Empty placeholder in '{}'", input);
+            }
+            parts.push(Part::parse(placeholder)?);
+
+            rest = &rest[start + end + 2..];
+        }
+
+        literal.push_str(rest);
+        if !literal.is_empty() {
+            parts.push(Part::Literal(literal));
+        }
+
+        Ok(Self { parts })
+    }
+
+    /// True when there is nothing to substitute, so the text can be used as is
+    pub fn is_literal(&self) -> bool {
+        self.parts
+            .iter()
+            .all(|part| matches!(part, Part::Literal(_)))
+    }
+
+    /// Names of the variables referenced by the placeholders, built-in ones excluded
+    pub fn vars(&self) -> impl Iterator<Item = &str> {
+        self.parts.iter().filter_map(|part| match part {
+            Part::Var(name) => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    pub fn render(&self, ctx: &RenderContext) -> Result<String> {
+        let mut output = String::new();
+        for part in &self.parts {
+            match part {
+                Part::Literal(text) => output.push_str(text),
+                Part::Var(name) => match ctx.vars.get(name) {
+                    Some(value) => output.push_str(value),
+                    None => bail!("Variable '{}' is not defined", name),
+                },
+                Part::Uuid => {
+                    let uuid = uuid::Builder::from_random_bytes(rand::random()).into_uuid();
+                    output.push_str(&uuid.to_string());
+                }
+                Part::RandomInt(min, max) => {
+                    let value = rand::rng().random_range(*min..=*max);
+                    output.push_str(&value.to_string());
+                }
+                Part::Seq => output.push_str(&ctx.seq.to_string()),
+                Part::Timestamp => {
+                    let millis = SystemTime::now()
+                        .duration_since(UNIX_EPOCH)
+                        .unwrap_or_default()
+                        .as_millis();
+                    output.push_str(&millis.to_string());
+                }
+                Part::ClientId => output.push_str(&ctx.client_id.to_string()),
+            }
+        }
+        Ok(output)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn render(template: &Template, vars: &Vars) -> Result<String> {
+        template.render(&RenderContext {
+            vars,
+            client_id: 3,
+            seq: 42,
+        })
+    }
+
+    #[test]
+    fn test_template_render() {
+        let template = Template::parse("Bearer {{ token }} for {{user}}!").unwrap();
+        let vars = Vars::from([
+            ("token".to_string(), "abc".to_string()),
+            ("user".to_string(), "alice".to_string()),
+        ]);
+
+        assert!(!template.is_literal());
+        assert_eq!(template.vars().collect::<Vec<_>>(), ["token", "user"]);
+        assert_eq!(render(&template, &vars).unwrap(), "Bearer abc for alice!");
+    }
+
+    #[test]
+    fn test_template_literal() {
+        let template = Template::parse("/items?page=1").unwrap();
+        assert!(template.is_literal());
+        assert_eq!(render(&template, &Vars::new()).unwrap(), "/items?page=1");
+    }
+
+    #[test]
+    fn test_template_escape() {
+        let template = Template::parse(r"\{{name}} is {{name}}, \{{ and }} are kept").unwrap();
+        let vars = Vars::from([("name".to_string(), "alice".to_string())]);
+        assert_eq!(template.vars().collect::<Vec<_>>(), ["name"]);
+        assert_eq!(
+            render(&template, &vars).unwrap(),
+            "{{name}} is alice, {{ and }} are kept"
+        );
+
+        let template = Template::parse(r#"{"mustache": "\{{user}}"}"#).unwrap();
+        assert!(template.is_literal());
+        assert_eq!(
+            render(&template, &Vars::new()).unwrap(),
+            r#"{"mustache": "{{user}}"}"#
+        );
+    }
+
+    #[test]
+    fn test_template_builtins() {
+        let template = Template::parse("/clients/{{client_id}}/requests/{{ seq }}").unwrap();
+        assert_eq!(template.vars().count(), 0);
+        assert_eq!(
+            render(&template, &Vars::new()).unwrap(),
+            "/clients/3/requests/42"
+        );
+
+        let template = Template::parse("{{random_int 1 3}}").unwrap();
+        for _ in 0..100 {
+            let value: i64 = render(&template, &Vars::new()).unwrap().parse().unwrap();
+            assert!((1..=3).contains(&value));
+        }
+
+        let template = Template::parse("{{uuid}}").unwrap();
+        let first = render(&template, &Vars::new()).unwrap();
+        let uuid = uuid::Uuid::parse_str(&first).unwrap();
+        assert_eq!(uuid.get_version_num(), 4);
+        assert_ne!(first, render(&template, &Vars::new()).unwrap());
+
+        let template = Template::parse("{{timestamp}}").unwrap();
+        let millis: u128 = render(&template, &Vars::new()).unwrap().parse().unwrap();
+        assert!(millis > 1_600_000_000_000);
+    }
+
+    #[test]
+    fn test_template_env() {
+        let template = Template::parse("{{env PATH}}").unwrap();
+        assert!(template.is_literal());
+        assert_eq!(
+            render(&template, &Vars::new()).unwrap(),
+            std::env::var("PATH").unwrap()
+        );
+
+        assert!(Template::parse("{{env HB_TEST_UNDEFINED_VARIABLE}}").is_err());
+    }
+
+    #[test]
+    fn test_template_invalid() {
+        assert!(Template::parse("{{token").is_err());
+        assert!(Template::parse("{{ }}").is_err());
+        assert!(Template::parse("{{random_int 1}}").is_err());
+        assert!(Template::parse("{{random_int a 10}}").is_err());
+        assert!(Template::parse("{{random_int 10 1}}").is_err());
+        assert!(Template::parse("{{uuid 4}}").is_err());
+
+        let template = Template::parse("{{token}}").unwrap();
+        assert!(render(&template, &Vars::new()).is_err());
+    }
+}
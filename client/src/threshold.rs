@@ -0,0 +1,355 @@
+use crate::histogram::parse_percentile;
+use crate::time::parse_duration;
+use anyhow::{Result, bail};
+use regex::Regex;
+use std::fmt::Display;
+use std::str::FromStr;
+use std::sync::atomic::{AtomicBool, Ordering::Relaxed};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio_util::sync::CancellationToken;
+
+const DEFAULT_ABORT_WINDOW: Duration = Duration::from_secs(10);
+
+/// Value of the final report a threshold is compared with
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum ThresholdMetric {
+    Mean,
+    Min,
+    Max,
+    /// Latency at a percentile from 0 to 100, e.g. `p99.9`
+    Percentile(f64),
+    /// Responses per second over the whole run
+    Rps,
+    /// Percentage of requests that ended in an error instead of a response
+    ErrorRate,
+    /// Percentage of 2xx and 3xx responses
+    SuccessRate,
+    /// Percentage of passed checks
+    CheckRate,
+}
+
+impl ThresholdMetric {
+    fn is_latency(self) -> bool {
+        matches!(
+            self,
+            Self::Mean | Self::Min | Self::Max | Self::Percentile(_)
+        )
+    }
+
+    fn is_rate(self) -> bool {
+        matches!(self, Self::ErrorRate | Self::SuccessRate | Self::CheckRate)
+    }
+}
+
+impl FromStr for ThresholdMetric {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let metric = match s {
+            "mean" => Self::Mean,
+            "min" => Self::Min,
+            "max" => Self::Max,
+            "rps" => Self::Rps,
+            "error_rate" => Self::ErrorRate,
+            "success_rate" | "2xx|3xx_rate" => Self::SuccessRate,
+            "check_rate" => Self::CheckRate,
+            _ => match s.strip_prefix('p').map(parse_percentile) {
+                Some(Ok(percent)) => Self::Percentile(percent),
+                _ => bail!(
+                    "Unknown threshold metric '{}'. Available metrics are mean/min/max/p<percentile>/rps/error_rate/success_rate/check_rate",
+                    s
+                ),
+            },
+        };
+        Ok(metric)
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Comparison {
+    Lt,
+    Le,
+    Gt,
+    Ge,
+}
+
+/// A condition on the final report such as `p99<250ms` or `error_rate<0.5%`, the run fails
+/// when it does not hold
+#[derive(Debug, Clone, PartialEq)]
+pub struct Threshold {
+    pub metric: ThresholdMetric,
+    comparison: Comparison,
+    // Seconds for latencies, percent for rates, requests per second for rps
+    value: f64,
+    expr: String,
+}
+
+impl Threshold {
+    pub fn passes(&self, actual: f64) -> bool {
+        match self.comparison {
+            Comparison::Lt => actual < self.value,
+            Comparison::Le => actual <= self.value,
+            Comparison::Gt => actual > self.value,
+            Comparison::Ge => actual >= self.value,
+        }
+    }
+}
+
+impl FromStr for Threshold {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let re = Regex::new(r"^\s*(?P<metric>[^<>=]+?)\s*(?P<op><=|>=|<|>)\s*(?P<value>\S+)\s*$")?;
+        let Some(caps) = re.captures(s) else {
+            bail!("Threshold must be in the format <metric><op><value>, e.g. p99<250ms");
+        };
+
+        // The column name of the summary is accepted as well, e.g. `2xx|3xx rate>99%`
+        let metric = caps["metric"].to_lowercase().replace(' ', "_");
+        let metric: ThresholdMetric = metric.parse()?;
+        let comparison = match &caps["op"] {
+            "<" => Comparison::Lt,
+            "<=" => Comparison::Le,
+            ">" => Comparison::Gt,
+            _ => Comparison::Ge,
+        };
+
+        let value = &caps["value"];
+        let number = if metric.is_latency() {
+            parse_duration(value)
+                .map_err(|_| anyhow::anyhow!("Latency threshold must be a duration such as 250ms"))?
+                .as_secs_f64()
+        } else {
+            let number = if metric.is_rate() {
+                value.strip_suffix('%').unwrap_or(value)
+            } else {
+                value
+            };
+            match number.parse::<f64>() {
+                Ok(number) if number.is_finite() && number >= 0.0 => number,
+                _ => bail!("'{}' is not a valid threshold value", value),
+            }
+        };
+
+        Ok(Self {
+            metric,
+            comparison,
+            value: number,
+            expr: format!("{}{}{}", &caps["metric"], &caps["op"], &caps["value"]),
+        })
+    }
+}
+
+impl Display for Threshold {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+/// Whether all thresholds held in the last report, shared between the reporter and the caller
+#[derive(Debug, Clone)]
+pub struct Verdict(Arc<AtomicBool>);
+
+impl Verdict {
+    /// A run with thresholds fails until a report has been evaluated
+    pub fn new(thresholds: &[Threshold]) -> Self {
+        Self(Arc::new(AtomicBool::new(thresholds.is_empty())))
+    }
+
+    pub fn passed(&self) -> bool {
+        self.0.load(Relaxed)
+    }
+
+    pub(crate) fn set(&self, passed: bool) {
+        self.0.store(passed, Relaxed);
+    }
+}
+
+/// A condition on the last `window` of the run such as `error_rate>20% for 10s`, the run is
+/// aborted as soon as it holds
+#[derive(Debug, Clone, PartialEq)]
+pub struct AbortCondition {
+    pub condition: Threshold,
+    pub window: Duration,
+    expr: String,
+}
+
+impl FromStr for AbortCondition {
+    type Err = anyhow::Error;
+
+    fn from_str(s: &str) -> Result<Self> {
+        let re = Regex::new(r"^(?P<condition>.+?)(?:\s+for\s+(?P<window>\S+))?\s*$")?;
+        let Some(caps) = re.captures(s) else {
+            bail!("Abort condition must be in the format <metric><op><value> [for <window>]");
+        };
+
+        let condition: Threshold = caps["condition"].parse()?;
+        // Only the totals can be taken apart into windows
+        if matches!(
+            condition.metric,
+            ThresholdMetric::Min | ThresholdMetric::Max
+        ) {
+            bail!("min and max cannot be used in an abort condition");
+        }
+
+        let (window, expr) = match caps.name("window") {
+            Some(window) => (
+                parse_duration(window.as_str())?,
+                format!("{} for {}", condition, window.as_str()),
+            ),
+            None => (DEFAULT_ABORT_WINDOW, condition.to_string()),
+        };
+        if window.is_zero() {
+            bail!("Window of an abort condition must be greater than 0");
+        }
+
+        Ok(Self {
+            condition,
+            window,
+            expr,
+        })
+    }
+}
+
+impl Display for AbortCondition {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}", self.expr)
+    }
+}
+
+/// Stops the run on behalf of an abort condition and keeps the reason for the report
+#[derive(Debug, Clone)]
+pub struct AbortSignal {
+    token: CancellationToken,
+    reason: Arc<Mutex<Option<String>>>,
+}
+
+impl AbortSignal {
+    pub fn new(token: CancellationToken) -> Self {
+        Self {
+            token,
+            reason: Arc::new(Mutex::new(None)),
+        }
+    }
+
+    /// Cancel the run, only the first reason is kept
+    pub(crate) fn abort(&self, reason: String) {
+        self.reason.lock().unwrap().get_or_insert(reason);
+        self.token.cancel();
+    }
+
+    pub fn reason(&self) -> Option<String> {
+        self.reason.lock().unwrap().clone()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_threshold_latency() {
+        let threshold: Threshold = "p99 < 250ms".parse().unwrap();
+        assert_eq!(threshold.metric, ThresholdMetric::Percentile(99.0));
+        assert_eq!(threshold.to_string(), "p99<250ms");
+        assert!(threshold.passes(0.249));
+        assert!(!threshold.passes(0.25));
+
+        let threshold: Threshold = "p99.9<1s".parse().unwrap();
+        assert_eq!(threshold.metric, ThresholdMetric::Percentile(99.9));
+
+        // Fractional values are kept whole, not read from the digits after the point
+        let threshold: Threshold = "p99<0.25s".parse().unwrap();
+        assert!((threshold.value - 0.25).abs() < 1e-9);
+        assert!(threshold.passes(0.249));
+        assert!(!threshold.passes(0.986));
+
+        let threshold: Threshold = "p99<0.5ms".parse().unwrap();
+        assert!((threshold.value - 0.0005).abs() < 1e-9);
+        assert!(threshold.passes(0.0004));
+        assert!(!threshold.passes(0.005));
+
+        let threshold: Threshold = "max<=1s".parse().unwrap();
+        assert!(threshold.passes(1.0));
+        assert!(!threshold.passes(1.001));
+    }
+
+    #[test]
+    fn test_threshold_rates() {
+        let threshold: Threshold = "error_rate<0.5%".parse().unwrap();
+        assert!(threshold.passes(0.4));
+        assert!(!threshold.passes(0.5));
+
+        let threshold: Threshold = "2xx|3xx rate > 99%".parse().unwrap();
+        assert_eq!(threshold.metric, ThresholdMetric::SuccessRate);
+        assert_eq!(threshold.to_string(), "2xx|3xx rate>99%");
+
+        let threshold: Threshold = "success_rate>=99".parse().unwrap();
+        assert!(threshold.passes(99.0));
+        assert!(!threshold.passes(98.9));
+
+        let threshold: Threshold = "rps>1000".parse().unwrap();
+        assert_eq!(threshold.metric, ThresholdMetric::Rps);
+        assert!(threshold.passes(1000.5));
+        assert!(!threshold.passes(1000.0));
+    }
+
+    #[test]
+    fn test_abort_condition() {
+        let abort: AbortCondition = "error_rate > 20% for 10s".parse().unwrap();
+        assert_eq!(abort.condition.metric, ThresholdMetric::ErrorRate);
+        assert_eq!(abort.window, Duration::from_secs(10));
+        assert_eq!(abort.to_string(), "error_rate>20% for 10s");
+        assert!(abort.condition.passes(20.5));
+
+        // Both the value and the window may have a fractional part
+        let abort: AbortCondition = "p95>1.5s for 2.5s".parse().unwrap();
+        assert!((abort.condition.value - 1.5).abs() < 1e-9);
+        assert_eq!(abort.window, Duration::from_millis(2500));
+        assert!(abort.condition.passes(1.6));
+        assert!(!abort.condition.passes(1.4));
+
+        let abort: AbortCondition = "p95>2s".parse().unwrap();
+        assert_eq!(abort.window, DEFAULT_ABORT_WINDOW);
+        assert_eq!(abort.to_string(), "p95>2s");
+
+        for input in [
+            "max>2s for 10s",
+            "p95>2s for soon",
+            "p95>2s for 0s",
+            "for 10s",
+        ] {
+            assert!(input.parse::<AbortCondition>().is_err(), "{}", input);
+        }
+    }
+
+    #[test]
+    fn test_abort_signal() {
+        let token = CancellationToken::new();
+        let signal = AbortSignal::new(token.clone());
+        assert_eq!(signal.reason(), None);
+
+        signal.abort("first".to_string());
+        signal.abort("second".to_string());
+        assert!(token.is_cancelled());
+        assert_eq!(signal.reason().as_deref(), Some("first"));
+    }
+
+    #[test]
+    fn test_threshold_invalid() {
+        let inputs = [
+            "p99",
+            "p99=250ms",
+            "p420<250ms",
+            "pmax<250ms",
+            "p99<250",
+            "rps>fast",
+            "error_rate<-1%",
+            "rps>10%",
+        ];
+        for input in inputs {
+            assert!(input.parse::<Threshold>().is_err(), "{}", input);
+        }
+    }
+}
@@ -1,4 +1,18 @@
-use std::time::SystemTime;
+use anyhow::{Result, bail};
+use regex::Regex;
+use serde::{Deserialize, Deserializer};
+use std::time::{Duration, SystemTime};
+
+/// Deserialize an optional duration written like `500ms` in scenario files
+pub(crate) fn deserialize_duration<'de, D>(deserializer: D) -> Result<Option<Duration>, D::Error>
+where
+    D: Deserializer<'de>,
+{
+    let duration = String::deserialize(deserializer)?;
+    parse_duration(&duration)
+        .map(Some)
+        .map_err(serde::de::Error::custom)
+}
 
 pub fn now_ts() -> f64 {
     SystemTime::now()
@@ -7,3 +21,74 @@ pub fn now_ts() -> f64 {
         .unwrap()
         .as_secs_f64()
 }
+
+/// Parse a duration such as `500ms`, `30s`, `1h30m15s` or `0.25s`
+pub fn parse_duration(input: &str) -> Result<Duration> {
+    let re_validate = Regex::new(r"^(\d+(?:\.\d+)?(?:ms|s|m|h|d))+$")?;
+    if !re_validate.is_match(input) {
+        bail!("Invalid format");
+    }
+
+    let re = Regex::new(r"(?P<value>\d+(?:\.\d+)?)(?P<unit>(ms|s|m|h|d))")?;
+    let mut total_duration = Duration::new(0, 0);
+
+    if re.captures_iter(input).count() == 0 {
+        bail!("Duration must be in the format <value><unit>. Available units are ms/s/m/h/d.");
+    }
+
+    for caps in re.captures_iter(input) {
+        let value: f64 = caps["value"].parse()?;
+        let unit = &caps["unit"];
+
+        let secs = match unit {
+            "ms" => value / 1000.0,
+            "s" => value,
+            "m" => value * 60.0,
+            "h" => value * 60.0 * 60.0,
+            "d" => value * 60.0 * 60.0 * 24.0,
+            _ => bail!("Available units are ms/s/m/h/d"),
+        };
+
+        total_duration += Duration::try_from_secs_f64(secs)?;
+    }
+
+    if total_duration.is_zero() {
+        bail!("Duration must be greater than 0");
+    }
+
+    Ok(total_duration)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_duration() {
+        let inputs = [
+            ("500ms", Duration::from_millis(500)),
+            ("30s", Duration::from_secs(30)),
+            ("45m", Duration::from_secs(2700)),
+            ("1h", Duration::from_secs(3600)),
+            ("2d", Duration::from_secs(172800)),
+            ("1h30m15s", Duration::from_secs(5415)),
+            ("0.25s", Duration::from_millis(250)),
+            ("1.5m", Duration::from_secs(90)),
+            ("0.5ms", Duration::from_micros(500)),
+        ];
+
+        inputs.iter().for_each(|(input, expected)| {
+            let result = parse_duration(input).unwrap();
+            assert_eq!(result, *expected);
+        });
+    }
+
+    #[test]
+    fn test_parse_duration_invalid() {
+        let inputs = ["1x", "-1s", "0s"];
+        inputs.iter().for_each(|input| {
+            let result = parse_duration(input);
+            assert!(result.is_err());
+        });
+    }
+}
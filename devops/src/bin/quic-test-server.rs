@@ -0,0 +1,86 @@
+use anyhow::Result;
+use bytes::Bytes;
+use h3::server::RequestResolver;
+use http::{Response, StatusCode};
+use quinn::crypto::rustls::QuicServerConfig;
+use rand::Rng;
+use rustls::pki_types::PrivatePkcs8KeyDer;
+use std::net::SocketAddr;
+use std::sync::Arc;
+
+// An HTTP/3 counterpart of test-server, with a self-signed certificate for localhost.
+// Run hb with `--http-version 3 --root-cert <printed path>` or `--insecure` against it.
+#[tokio::main]
+async fn main() -> Result<()> {
+    let certified = rcgen::generate_simple_self_signed(vec!["localhost".to_string()])?;
+    let cert_path = std::env::temp_dir().join("hb-quic-test-server.pem");
+    std::fs::write(&cert_path, certified.cert.pem())?;
+
+    let key = PrivatePkcs8KeyDer::from(certified.key_pair.serialize_der());
+    let provider = Arc::new(rustls::crypto::ring::default_provider());
+    let mut tls = rustls::ServerConfig::builder_with_provider(provider)
+        .with_protocol_versions(&[&rustls::version::TLS13])?
+        .with_no_client_auth()
+        .with_single_cert(vec![certified.cert.der().clone()], key.into())?;
+    tls.alpn_protocols = vec![b"h3".to_vec()];
+
+    let config = quinn::ServerConfig::with_crypto(Arc::new(QuicServerConfig::try_from(tls)?));
+    let addr = SocketAddr::from(([127, 0, 0, 1], 4433));
+    let endpoint = quinn::Endpoint::server(config, addr)?;
+    println!(
+        "Listening on https://localhost:4433, certificate in {}",
+        cert_path.display()
+    );
+
+    while let Some(incoming) = endpoint.accept().await {
+        tokio::task::spawn(async move {
+            if let Err(err) = serve_connection(incoming).await {
+                eprintln!("Error serving connection: {:?}", err);
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn serve_connection(incoming: quinn::Incoming) -> Result<()> {
+    let quic = incoming.await?;
+    let mut conn = h3::server::Connection::new(h3_quinn::Connection::new(quic)).await?;
+    while let Some(resolver) = conn.accept().await? {
+        tokio::task::spawn(async move {
+            if let Err(err) = handle_request(resolver).await {
+                eprintln!("Error serving request: {:?}", err);
+            }
+        });
+    }
+    Ok(())
+}
+
+async fn handle_request(resolver: RequestResolver<h3_quinn::Connection, Bytes>) -> Result<()> {
+    let (req, mut stream) = resolver.resolve_request().await?;
+    let (status, body) = match req.uri().path() {
+        "/" => (StatusCode::OK, "Hello, world!".to_string()),
+        "/sleep" => {
+            let delay_ms = rand::rng().random_range(100..=1000);
+            tokio::time::sleep(tokio::time::Duration::from_millis(delay_ms)).await;
+            (StatusCode::OK, "Sleeping...".to_string())
+        }
+        "/random" => {
+            let status = match rand::rng().random_range(0..100) {
+                0..50 => StatusCode::OK,                      // 50% 200 OK
+                50..70 => StatusCode::MOVED_PERMANENTLY,      // 20% 301
+                70..90 => StatusCode::BAD_REQUEST,            // 20% 400
+                90..100 => StatusCode::INTERNAL_SERVER_ERROR, // 10% 500
+                _ => StatusCode::OK,
+            };
+            (status, format!("Status: {}", status))
+        }
+        _ => (StatusCode::NOT_FOUND, "Not Found".to_string()),
+    };
+
+    stream
+        .send_response(Response::builder().status(status).body(())?)
+        .await?;
+    stream.send_data(Bytes::from(body)).await?;
+    stream.finish().await?;
+    Ok(())
+}